@@ -33,8 +33,10 @@ async fn main() -> anyhow::Result<()> {
         .context("Please set env: TWITCH_REDIRECT_URL or pass as third argument")??;
 
     // Create the builder!
-    let mut builder =
-        UserTokenBuilder::new(client_id, client_secret, redirect_url).force_verify(false);
+    // user:read:chat is only needed by the chat relay
+    let mut builder = UserTokenBuilder::new(client_id, client_secret, redirect_url)
+        .set_scopes(vec![twitch_oauth2::Scope::UserReadChat])
+        .force_verify(false);
 
     // Generate the URL, this is the url that the user should visit to authenticate.
     let (url, _) = builder.generate_url();
@@ -1,10 +1,78 @@
+use std::collections::HashMap;
+use std::env::var;
+use std::fs;
 use std::num::NonZeroU64;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
 use serde::Deserialize;
+use tracing::warn;
+use url::Url;
+
+use crate::quiet_hours::QuietHours;
+use crate::templates;
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct TwitchUser {
     pub twitch_channel_id: NonZeroU64,
     pub discord_id: NonZeroU64,
+    /// role pinged when the stream goes live, users get it with /notify_role
+    #[serde(default)]
+    pub notify_role_id: Option<NonZeroU64>,
+    /// relay the twitch chat of the stream in twitch_watcher.chat_relay
+    #[serde(default)]
+    pub chat_relay: bool,
+    /// name given to the channel of the streamer while streaming, over the channel template
+    /// and renamed_channel_name, the streamer can override it with /my_template
+    #[serde(default)]
+    pub rename_override: Option<String>,
+}
+
+/// how stream events are announced in announce_channel_id
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnnounceStyle {
+    #[default]
+    Message,
+    Embed,
+    /// announce_channel_id is a forum, a thread is created for each stream
+    ForumThread,
+}
+
+/// how the voice channel of a streamer shows the stream
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RenameMode {
+    /// the channel is renamed to renamed_channel_name
+    #[default]
+    Name,
+    /// the channel name is kept and its voice status is set to voice_status
+    VoiceStatus,
+}
+
+/// what the bot presence shows
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PresenceMode {
+    /// one of activity_messages, picked on startup
+    #[default]
+    Activity,
+    /// the number of monitored streams that are live
+    LiveCount,
+}
+
+/// how commands acknowledge their success when there is nothing else to say
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AckStyle {
+    /// reply with "ok"
+    #[default]
+    Text,
+    /// reply with a checkmark
+    Checkmark,
+    /// reply with "ok" only visible to the user
+    Ephemeral,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -12,14 +80,627 @@ pub struct TwitchWatcher {
     pub servers: Vec<u64>,
     pub channels: Vec<TwitchUser>,
     pub renamed_channel_name: String,
+    /// renamed_channel_name overridden per guild id, the templates of channels and streamers
+    /// come before it
+    #[serde(default)]
+    pub renamed_channel_names: HashMap<NonZeroU64, String>,
+    #[serde(default)]
+    pub rename_mode: RenameMode,
+    /// voice status of the channel of a streamer in voice-status mode, {login} is replaced by
+    /// the twitch login of the streamer
+    #[serde(default = "default_voice_status")]
+    pub voice_status: String,
+    pub enabled: bool,
+    /// post stream events in announce_channel_id, whether the streamer is in a voice channel
+    /// or not
+    #[serde(default = "default_true")]
+    pub announce_enabled: bool,
+    /// rename the voice channel of live streamers, channels already renamed are still restored
+    /// when disabled
+    #[serde(default = "default_true")]
+    pub rename_enabled: bool,
+    #[serde(default)]
+    pub announce_channel_id: Option<NonZeroU64>,
+    #[serde(default)]
+    pub announce_style: AnnounceStyle,
+    /// with the embed style, edit the go-live announce this often with a fresh thumbnail and
+    /// viewer count while the stream is live, never if absent
+    #[serde(default)]
+    pub announce_refresh_seconds: Option<u64>,
+    /// with the message and embed styles, edit the go-live announce when the stream ends
+    /// instead of posting a new message, a new one is posted if it can't be edited
+    #[serde(default)]
+    pub edit_ended_announce: bool,
+    /// exit the process when no message, keepalives included, has been received from the
+    /// twitch websocket for this many seconds, disabled if absent
+    #[serde(default)]
+    pub watchdog_timeout_seconds: Option<u64>,
+    /// the websocket logs repeated during reconnect storms are written once per this many
+    /// seconds with the count of the ones skipped, 0 to write them all
+    #[serde(default = "default_repeated_log_window_seconds")]
+    pub repeated_log_window_seconds: u64,
+    /// how many times a twitch user lookup is retried when the twitch api fails
+    #[serde(default = "default_login_lookup_retries")]
+    pub login_lookup_retries: u32,
+    /// twitch teams whose members are monitored too, members not in channels are only announced
+    #[serde(default)]
+    pub teams: Vec<String>,
+    /// streams are announced once they have been online for this long, so that streams ending
+    /// sooner aren't announced at all, 0 to announce immediately
+    #[serde(default)]
+    pub min_stream_duration_seconds: u64,
+    /// rename channels after min_stream_duration_seconds too, instead of immediately
+    #[serde(default)]
+    pub defer_rename: bool,
+    /// time a streamer has to stay in a voice channel before it is renamed, so that quick moves
+    /// only rename the last channel, 0 to rename on every move
+    #[serde(default = "default_rename_cooldown_seconds")]
+    pub rename_cooldown_seconds: u64,
+    /// rename again a channel already renamed for a streamer when its name differs from the
+    /// one the current template gives, like after a template change
+    #[serde(default)]
+    pub reapply_template: bool,
+    /// stop linking a monitored user who leaves a server of servers to their twitch channel,
+    /// their streams are still announced, the link is back on restart if they are still in
+    /// channels
+    #[serde(default)]
+    pub forget_departed_streamers: bool,
+    /// name given to renamed channels when the stream ends, instead of restoring their
+    /// original name right away, in voice-status mode this is the voice status
+    #[serde(default)]
+    pub offline_channel_name: Option<String>,
+    /// offline_channel_name overridden per guild id
+    #[serde(default)]
+    pub offline_channel_names: HashMap<NonZeroU64, String>,
+    /// channels with the offline name get their original name back after this many seconds,
+    /// they keep it until the next stream or shutdown if absent
+    #[serde(default)]
+    pub offline_restore_seconds: Option<u64>,
+    /// time range of each day during which stream events aren't announced, renames still happen
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHoursConfig>,
+    /// destinations where stream events are sent in addition to announce_channel_id
+    #[serde(default)]
+    pub notification_sinks: Vec<SinkConfig>,
+    /// channel where the twitch chat of the channels with chat_relay is posted
+    #[serde(default)]
+    pub chat_relay: Option<ChatRelayConfig>,
+    /// text of the announces by kind of event, overriding the default ones
+    #[serde(default)]
+    pub announce_templates: HashMap<EventKind, String>,
+}
+
+/// time range of each day during which stream events aren't announced
+#[derive(Deserialize, Clone, Debug)]
+pub struct QuietHoursConfig {
+    /// IANA timezone of start and end, like Europe/Paris
+    #[serde(default = "default_quiet_hours_timezone")]
+    pub timezone: String,
+    /// like 02:00, the range ends the next day if end is before start
+    pub start: String,
+    pub end: String,
+    /// announce the events held during the range when it ends, they are dropped otherwise
+    #[serde(default)]
+    pub hold: bool,
+}
+
+/// relay of twitch chats into a discord channel, the token needs the user:read:chat scope
+#[derive(Deserialize, Clone, Debug)]
+pub struct ChatRelayConfig {
+    pub channel_id: NonZeroU64,
+    /// relayed messages are grouped and posted at most this often
+    #[serde(default = "default_chat_relay_batch_seconds")]
+    pub batch_seconds: u64,
+    /// messages beyond this count in a batch are dropped
+    #[serde(default = "default_chat_relay_max_batch")]
+    pub max_batch: usize,
+    /// logins whose messages aren't relayed, like bots
+    #[serde(default = "default_chat_relay_ignored_chatters")]
+    pub ignored_chatters: Vec<String>,
+    /// messages starting with one of these are chat commands and aren't relayed
+    #[serde(default = "default_chat_relay_command_prefixes")]
+    pub command_prefixes: Vec<String>,
+}
+
+/// kind of stream event, selects the notification sinks and the announce template
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Online,
+    Offline,
+}
+
+fn default_sink_events() -> Vec<EventKind> {
+    vec![EventKind::Online, EventKind::Offline]
+}
+
+/// destination of the stream events other than Discord
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkConfig {
+    /// POST the event as json to url
+    Webhook {
+        url: String,
+        #[serde(default = "default_sink_events")]
+        events: Vec<EventKind>,
+        /// only the events of the streamers of this guild are sent, all of them if absent
+        #[serde(default)]
+        guild_id: Option<NonZeroU64>,
+    },
+    /// post the event as a message in a matrix room
+    Matrix {
+        homeserver: String,
+        room_id: String,
+        access_token: String,
+        #[serde(default = "default_sink_events")]
+        events: Vec<EventKind>,
+        /// only the events of the streamers of this guild are sent, all of them if absent
+        #[serde(default)]
+        guild_id: Option<NonZeroU64>,
+    },
+}
+
+/// entry of a pool of random answers, either a plain string or a string with a weight
+/// a plain string has a weight of 1
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum PoolEntry {
+    Plain(String),
+    Weighted { value: String, weight: u32 },
+}
+
+impl PoolEntry {
+    pub fn value(&self) -> &str {
+        match self {
+            PoolEntry::Plain(value) => value,
+            PoolEntry::Weighted { value, .. } => value,
+        }
+    }
+
+    pub fn weight(&self) -> u32 {
+        match self {
+            PoolEntry::Plain(_) => 1,
+            PoolEntry::Weighted { weight, .. } => *weight,
+        }
+    }
+}
+
+/// backend used to persist the bot state
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum StoreConfig {
+    /// one json file per stored value in directory
+    File { directory: PathBuf },
+    /// a single sqlite database
+    Sqlite { path: PathBuf },
+}
+
+impl Default for StoreConfig {
+    /// the directory of TWITCH_CACHE_PATH, which held the twitch token before the store existed
+    fn default() -> Self {
+        let directory = var("TWITCH_CACHE_PATH")
+            .ok()
+            .and_then(|path| PathBuf::from(path).parent().map(PathBuf::from))
+            .filter(|directory| !directory.as_os_str().is_empty())
+            .unwrap_or(PathBuf::from("."));
+        StoreConfig::File { directory }
+    }
+}
+
+/// what is done with the direct messages sent to the bot
+#[derive(Deserialize, Clone, Debug)]
+pub struct DirectMessagesConfig {
+    /// direct messages are ignored if false
+    #[serde(default = "default_true")]
     pub enabled: bool,
+    /// answer sent to every direct message, the auto-responder answers them if absent
+    #[serde(default)]
+    pub reply: Option<String>,
+    /// channel where direct messages are forwarded
+    #[serde(default)]
+    pub forward_channel_id: Option<NonZeroU64>,
+}
+
+impl Default for DirectMessagesConfig {
+    fn default() -> Self {
+        DirectMessagesConfig {
+            enabled: true,
+            reply: None,
+            forward_channel_id: None,
+        }
+    }
+}
+
+/// destination of the anonymous usage counters
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TelemetryConfig {
+    /// overwrite a json file
+    File {
+        path: PathBuf,
+        #[serde(default)]
+        interval_seconds: Option<u64>,
+    },
+    /// post a json document
+    Http {
+        url: String,
+        #[serde(default)]
+        interval_seconds: Option<u64>,
+    },
+}
+
+impl TelemetryConfig {
+    /// time between two exports, an hour by default
+    pub fn interval_seconds(&self) -> u64 {
+        match self {
+            TelemetryConfig::File {
+                interval_seconds, ..
+            }
+            | TelemetryConfig::Http {
+                interval_seconds, ..
+            } => interval_seconds.unwrap_or(3600),
+        }
+    }
 }
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct Config {
-    pub activity_messages: Vec<String>,
-    pub question_answers: Vec<String>,
-    pub random_answers: Vec<String>,
+    // picked according to their weight, like answers
+    pub activity_messages: Vec<PoolEntry>,
+    pub question_answers: Vec<PoolEntry>,
+    pub random_answers: Vec<PoolEntry>,
+    // answer every message from question_answers and random_answers merged together
+    #[serde(default)]
+    pub unified_answers: bool,
+    // answers used when the bot is mentioned without being named
+    #[serde(default)]
+    pub default_answers: Vec<PoolEntry>,
+    // words naming the bot, matched anywhere in messages ignoring case
+    #[serde(default = "default_trigger_words")]
+    pub trigger_words: Vec<String>,
+    // words at most this many edits away from a trigger word name the bot too, 0 to disable
+    #[serde(default)]
+    pub trigger_max_distance: usize,
+    // file read by /reload_answers, replacing the answer pools above, the config file if absent
+    // and the config is read from a file
+    #[serde(default)]
+    pub answers_path: Option<PathBuf>,
+    // file the config was read from, None for an url or the default config
+    #[serde(skip)]
+    pub source_path: Option<PathBuf>,
+    // answer messages mentioning the bot, for guilds not in auto_responder_guilds and DMs
+    #[serde(default = "default_true")]
+    pub auto_responder_enabled: bool,
+    // auto_responder_enabled overridden per guild id
+    #[serde(default)]
+    pub auto_responder_guilds: HashMap<NonZeroU64, bool>,
     pub trusted_users: Vec<u64>,
+    // guilds the bot operates in, events of other guilds are ignored, every guild if empty
+    #[serde(default)]
+    pub allowed_guilds: Vec<u64>,
+    // leave the guilds not in allowed_guilds instead of ignoring them
+    #[serde(default)]
+    pub leave_disallowed_guilds: bool,
     pub twitch_watcher: TwitchWatcher,
+    #[serde(default)]
+    pub store: StoreConfig,
+    // channel where significant errors are posted
+    #[serde(default)]
+    pub error_channel_id: Option<NonZeroU64>,
+    // channel where /feedback messages are posted, /feedback is disabled if absent
+    #[serde(default)]
+    pub feedback_channel_id: Option<NonZeroU64>,
+    #[serde(default)]
+    pub ack_style: AckStyle,
+    #[serde(default)]
+    pub presence_mode: PresenceMode,
+    #[serde(default)]
+    pub direct_messages: DirectMessagesConfig,
+    // usage counters export, disabled if absent
+    #[serde(default)]
+    pub telemetry: Option<TelemetryConfig>,
+    // http proxy for the requests to twitch, HTTPS_PROXY is used if absent
+    #[serde(default)]
+    pub proxy: Option<String>,
+    // number of twitch events kept for /events
+    #[serde(default = "default_event_history_size")]
+    pub event_history_size: usize,
+    // register the commands globally at startup, /register manages them otherwise
+    #[serde(default = "default_true")]
+    pub register_commands_globally: bool,
+}
+
+/// answer pools of the config, which can be reloaded from answers_path
+#[derive(Deserialize, Clone, Debug)]
+pub struct AnswersConfig {
+    pub question_answers: Vec<PoolEntry>,
+    pub random_answers: Vec<PoolEntry>,
+    #[serde(default)]
+    pub default_answers: Vec<PoolEntry>,
+}
+
+impl AnswersConfig {
+    /// read the pools from a json file, other keys are ignored so this can be the config file
+    pub fn load(path: &Path) -> anyhow::Result<AnswersConfig> {
+        let answers = serde_json::from_str::<AnswersConfig>(&fs::read_to_string(path)?)?;
+        Ok(answers)
+    }
+
+    /// question_answers and random_answers in a single pool
+    pub fn merged_answers(&self) -> Vec<PoolEntry> {
+        self.question_answers
+            .iter()
+            .chain(self.random_answers.iter())
+            .cloned()
+            .collect()
+    }
+
+    pub fn validate(&self, unified_answers: bool) -> anyhow::Result<()> {
+        if unified_answers {
+            validate_pool("answers", &self.merged_answers())?;
+        } else {
+            validate_pool("question_answers", &self.question_answers)?;
+            validate_pool("random_answers", &self.random_answers)?;
+        }
+        if !self.default_answers.is_empty() {
+            validate_pool("default_answers", &self.default_answers)?;
+        }
+        Ok(())
+    }
+}
+
+// maximum length of a channel name accepted by Discord
+pub const CHANNEL_NAME_MAX_LENGTH: usize = 100;
+
+// a config url not answering within this delay fails the startup
+const CONFIG_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+// config used when CONFIG_PATH isn't set and there is no ./config.json, enough for a demo
+const DEFAULT_CONFIG: &str = include_str!("default_config.json");
+
+/// path of the config file, the url itself if CONFIG_PATH is an url
+pub fn config_path() -> PathBuf {
+    PathBuf::from(var("CONFIG_PATH").unwrap_or(String::from("./config.json")))
+}
+
+/// where the config is read from
+enum ConfigSource {
+    File(PathBuf),
+    Url(Url),
+    Default,
+}
+
+fn config_source() -> anyhow::Result<ConfigSource> {
+    let Ok(path) = var("CONFIG_PATH") else {
+        let path = config_path();
+        return Ok(match path.exists() {
+            true => ConfigSource::File(path),
+            false => ConfigSource::Default,
+        });
+    };
+    if path.starts_with("http://") || path.starts_with("https://") {
+        let url = path
+            .parse()
+            .with_context(|| format!("CONFIG_PATH {} isn't a valid url", path))?;
+        return Ok(ConfigSource::Url(url));
+    }
+    Ok(ConfigSource::File(PathBuf::from(path)))
+}
+
+impl Config {
+    pub fn answers(&self) -> AnswersConfig {
+        AnswersConfig {
+            question_answers: self.question_answers.clone(),
+            random_answers: self.random_answers.clone(),
+            default_answers: self.default_answers.clone(),
+        }
+    }
+
+    /// read the config from CONFIG_PATH, a file or an http(s) url, or the embedded default
+    /// config if it isn't set and there is no ./config.json, then validate it
+    pub async fn load() -> anyhow::Result<Config> {
+        let source = config_source()?;
+        let (name, text) = match &source {
+            ConfigSource::File(path) => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("Can't read config file {}", path.display()))?;
+                (path.display().to_string(), text)
+            }
+            ConfigSource::Url(url) => {
+                let text = reqwest::Client::builder()
+                    .timeout(CONFIG_FETCH_TIMEOUT)
+                    .build()?
+                    .get(url.clone())
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .with_context(|| format!("Can't fetch config from {}", url))?
+                    .text()
+                    .await
+                    .with_context(|| format!("Can't fetch config from {}", url))?;
+                (url.to_string(), text)
+            }
+            ConfigSource::Default => {
+                warn!("No config file, using the default config");
+                (String::from("default config"), String::from(DEFAULT_CONFIG))
+            }
+        };
+        let mut config = serde_json::from_str::<Config>(&text)
+            .with_context(|| format!("Can't parse config from {}", name))?;
+        if let ConfigSource::File(path) = source {
+            config.source_path = Some(path);
+        }
+        config
+            .validate()
+            .with_context(|| format!("Invalid config from {}", name))?;
+        Ok(config)
+    }
+
+    /// file read by /reload_answers, None if answers_path isn't set and the config isn't a file
+    pub fn answers_path(&self) -> Option<PathBuf> {
+        self.answers_path.clone().or(self.source_path.clone())
+    }
+
+    /// check values that can't be expressed by the config types
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.answers().validate(self.unified_answers)?;
+        validate_pool("activity_messages", &self.activity_messages)?;
+        if self.trigger_words.iter().any(|w| w.trim().is_empty()) {
+            return Err(anyhow!("trigger_words must not contain empty words"));
+        }
+        for (kind, template) in &self.twitch_watcher.announce_templates {
+            templates::validate(*kind, template)?;
+        }
+        if let Some(quiet_hours) = &self.twitch_watcher.quiet_hours {
+            QuietHours::new(quiet_hours)?;
+        }
+        validate_channel_name(
+            "twitch_watcher.renamed_channel_name",
+            &self.twitch_watcher.renamed_channel_name,
+        )?;
+        for (guild_id, renamed_channel_name) in &self.twitch_watcher.renamed_channel_names {
+            validate_channel_name(
+                &format!("renamed_channel_names of {}", guild_id),
+                renamed_channel_name,
+            )?;
+        }
+        for user in &self.twitch_watcher.channels {
+            if let Some(rename_override) = &user.rename_override {
+                validate_channel_name(
+                    &format!("rename_override of {}", user.discord_id),
+                    rename_override,
+                )?;
+            }
+        }
+        validate_ids("twitch_watcher.servers", &self.twitch_watcher.servers)?;
+        validate_ids("trusted_users", &self.trusted_users)?;
+        validate_ids("allowed_guilds", &self.allowed_guilds)?;
+        Ok(())
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_quiet_hours_timezone() -> String {
+    String::from("UTC")
+}
+
+fn default_voice_status() -> String {
+    String::from("🔴 {login} is live")
+}
+
+fn default_login_lookup_retries() -> u32 {
+    2
+}
+
+fn default_repeated_log_window_seconds() -> u64 {
+    60
+}
+
+fn default_rename_cooldown_seconds() -> u64 {
+    5
+}
+
+fn default_chat_relay_batch_seconds() -> u64 {
+    5
+}
+
+fn default_chat_relay_max_batch() -> usize {
+    20
+}
+
+fn default_chat_relay_ignored_chatters() -> Vec<String> {
+    [
+        "nightbot",
+        "streamelements",
+        "streamlabs",
+        "moobot",
+        "fossabot",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+fn default_chat_relay_command_prefixes() -> Vec<String> {
+    vec![String::from("!")]
+}
+
+fn default_trigger_words() -> Vec<String> {
+    vec![String::from("samousse")]
+}
+
+fn default_event_history_size() -> usize {
+    20
+}
+
+/// channel names must not be empty nor longer than discord allows
+pub fn validate_channel_name(name: &str, channel_name: &str) -> anyhow::Result<()> {
+    templates::check_placeholders(name, channel_name, templates::CHANNEL_NAME_PLACEHOLDERS)?;
+    if channel_name.trim().is_empty() {
+        return Err(anyhow!("{} must not be empty", name));
+    }
+    if channel_name.trim().chars().count() > CHANNEL_NAME_MAX_LENGTH {
+        return Err(anyhow!(
+            "{} must not be longer than {} characters",
+            name,
+            CHANNEL_NAME_MAX_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// discord ids can't be 0
+fn validate_ids(name: &str, ids: &[u64]) -> anyhow::Result<()> {
+    if ids.contains(&0) {
+        return Err(anyhow!("{} must not contain the id 0", name));
+    }
+    Ok(())
+}
+
+fn validate_pool(name: &str, pool: &[PoolEntry]) -> anyhow::Result<()> {
+    if pool.is_empty() {
+        return Err(anyhow!("{} must not be empty", name));
+    }
+    if let Some(entry) = pool.iter().find(|e| e.weight() == 0) {
+        return Err(anyhow!(
+            "{} entry \"{}\" must have a positive weight",
+            name,
+            entry.value()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_config() -> Config {
+        serde_json::from_str(DEFAULT_CONFIG).unwrap()
+    }
+
+    #[test]
+    fn server_id_0_is_rejected() {
+        let mut config = default_config();
+        config.twitch_watcher.servers = vec![1, 0];
+
+        let error = config.validate().unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "twitch_watcher.servers must not contain the id 0"
+        );
+    }
+
+    #[test]
+    fn nonzero_ids_are_accepted() {
+        let mut config = default_config();
+        config.twitch_watcher.servers = vec![1, 2];
+        config.trusted_users = vec![3];
+
+        assert!(config.validate().is_ok());
+    }
 }
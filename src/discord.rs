@@ -1,17 +1,66 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use poise::serenity_prelude as serenity;
-use serenity::all::{ChannelId, GuildId, UserId};
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId, MessageId, RoleId, UserId};
 use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::{Mutex, Notify, RwLock};
+use tracing::{error, info};
 
-use crate::inter_comm::InterComm;
+use crate::config::{AckStyle, AnnounceStyle, DirectMessagesConfig, RenameMode};
+use crate::discord::chat_relay::ChatRelay;
+use crate::discord::error_notifier::ErrorNotifier;
+use crate::discord::events::EventHistory;
+use crate::discord::feedback::Feedback;
+use crate::discord::guilds::GuildAllowlist;
+use crate::discord::message_response::{Answers, RandomPool};
+use crate::discord::rename_queue::RenameQueue;
+use crate::discord::scheduler::ScheduledMessage;
+use crate::discord::sink::NotificationSink;
+use crate::discord::telemetry::Telemetry;
+use crate::discord::tuning::TuningKey;
+use crate::inter_comm::{InterComm, TwitchRequest};
+use crate::quiet_hours::QuietHours;
+use crate::store::{
+    Store, ANNOUNCE_MESSAGES_KEY, ANNOUNCE_THREADS_KEY, CHANNEL_TEMPLATES_KEY,
+    NOTIFY_SUBSCRIPTIONS_KEY, RENAMED_CHANNELS_KEY, SCHEDULES_KEY, SNOOZES_KEY, TUNING_KEY,
+    USER_TEMPLATES_KEY,
+};
+use crate::templates::AnnounceTemplates;
+use crate::twitch::health::TwitchHealth;
 
+mod announce;
 pub mod bot;
+mod broadcast;
+mod builtins;
+mod chat_relay;
+mod config_show;
+mod diag;
+mod error_notifier;
+mod events;
+mod feedback;
+#[cfg(test)]
+mod fixtures;
+mod guilds;
+mod logs;
 mod message_response;
+mod moderation;
+mod notify;
+mod permissions;
+mod presence;
 mod random_stuff;
+mod rename_audit;
+mod rename_queue;
+mod scheduler;
+mod shutdown;
+mod sink;
+mod snooze;
+mod stats;
+mod telemetry;
+mod tuning;
 mod twitch;
 
 // Types used by all command functions
@@ -24,9 +73,20 @@ struct Data {
     pub twitch: Arc<RwLock<DiscordTwitchWatcher>>,
     pub sender: Mutex<Sender<InterComm>>,
     pub receiver: Mutex<Option<Receiver<InterComm>>>,
-    pub activity_messages: Vec<String>,
-    pub question_answers: Arc<Vec<String>>,
-    pub random_answers: Arc<Vec<String>>,
+    // requests to the twitch side
+    pub twitch_requests: Sender<TwitchRequest>,
+    pub activity_messages: RandomPool,
+    // swapped by /reload_answers
+    pub answers: RwLock<Arc<Answers>>,
+    // None if the config isn't read from a file and answers_path isn't set
+    pub answers_path: Option<PathBuf>,
+    pub twitch_health: Arc<TwitchHealth>,
+    pub ack_style: AckStyle,
+    pub feedback: Feedback,
+    pub direct_messages: DirectMessagesConfig,
+    pub guild_allowlist: GuildAllowlist,
+    // client of the http requests made by the discord side outside of Discord, like telemetry
+    pub http_client: reqwest::Client,
 }
 
 #[derive(Debug)]
@@ -35,15 +95,120 @@ struct DiscordTwitchWatcher {
     pub channels: HashMap<ChannelId, Channel>,
     // list of twitch channel with tied discord account to monitor
     pub users: HashMap<UserId, User>,
+    // reverse index of users.current_channel_id, kept in sync by set_user_voice_channel
+    pub users_by_channel: HashMap<ChannelId, HashSet<UserId>>,
     pub renamed_channel_name: String,
+    // renamed_channel_name overridden per guild
+    pub renamed_channel_names: HashMap<GuildId, String>,
+    pub rename_mode: RenameMode,
+    // false to only restore the channels already renamed
+    pub rename_enabled: bool,
+    // status set in voice-status mode, see config
+    pub voice_status: String,
+    // renamed_channel_name overridden for specific channels
+    pub channel_templates: HashMap<ChannelId, String>,
+    // rename again renamed channels whose name isn't the one of the current template
+    pub reapply_template: bool,
+    // unlink monitored users who leave a watched guild from their twitch channel
+    pub forget_departed_streamers: bool,
+    // name given to renamed channels when the stream ends, the original one is restored if None
+    pub offline_channel_name: Option<String>,
+    // offline_channel_name overridden per guild
+    pub offline_channel_names: HashMap<GuildId, String>,
+    // delay before restoring the original name of channels with the offline name, never if None
+    pub offline_restore: Option<Duration>,
+    // timers changed by /tuning_set, in seconds, applied over the config ones
+    pub tuning: HashMap<TuningKey, u64>,
     pub enabled: bool,
     // contains servers where DiscordTwitchWatch should operate (excluding server not in this list)
     pub servers: Vec<GuildId>,
+    // persist channels across restarts
+    pub store: Arc<dyn Store>,
+    // channel where stream events are posted, no announce if None
+    pub announce_channel_id: Option<ChannelId>,
+    pub announce_style: AnnounceStyle,
+    // false to skip the announce channel, notification sinks still receive the events
+    pub announce_enabled: bool,
+    pub announce_templates: AnnounceTemplates,
+    // delay between edits of the embed announce of a live stream, no edit if None
+    pub announce_refresh: Option<Duration>,
+    // edit the go-live announce when the stream ends instead of posting a new one
+    pub edit_ended_announce: bool,
+    // stream events aren't announced during these hours, None if they are always announced
+    pub quiet_hours: Option<QuietHours>,
+    // latest event of each streamer held during quiet_hours, keyed by login
+    pub held_announces: HashMap<String, HeldAnnounce>,
+    // why announce_channel_id can't receive announces, they are skipped if Some
+    pub announce_channel_problem: Option<String>,
+    pub error_notifier: Arc<ErrorNotifier>,
+    // users to notify by direct message when the streamer goes live, keyed by streamer, with
+    // the guilds they subscribed from
+    pub notify_subscriptions: HashMap<UserId, HashMap<UserId, HashSet<GuildId>>>,
+    // renames waiting for the Discord rate limit
+    pub rename_queue: RenameQueue,
+    // delay before renaming the channel a streamer moved to
+    pub rename_cooldown: Duration,
+    // count of voice channel moves of each streamer waiting for rename_cooldown,
+    // only the last one renames channels
+    pub voice_moves: HashMap<UserId, u64>,
+    // announces wait for the stream to last this long
+    pub min_stream_duration: Duration,
+    // renames wait for min_stream_duration too
+    pub defer_rename: bool,
+    // id of the deferred announce waiting for min_stream_duration, keyed by streamer twitch id
+    pub deferred_announces: HashMap<u64, u64>,
+    pub last_deferred_announce_id: u64,
+    // twitch ids of the streamers without a discord user whose stream is online
+    pub unlinked_live: HashSet<u64>,
+    // last events received from the twitch side
+    pub event_history: EventHistory,
+    // notified when the live count changes, None if the presence doesn't show it
+    pub live_presence: Option<Arc<Notify>>,
+    pub telemetry: Arc<Telemetry>,
+    // messages posted at a given time
+    pub schedules: Vec<ScheduledMessage>,
+    // destinations of the stream events other than the announce channel
+    pub notification_sinks: Vec<Arc<dyn NotificationSink>>,
+    // requests to the twitch side from background tasks
+    pub twitch_requests: Sender<TwitchRequest>,
+    // posts the twitch chat messages, None if the relay isn't configured
+    pub chat_relay: Option<ChatRelay>,
 }
 
-#[derive(Debug)]
+/// stream event received during quiet hours, announced when they end
+#[derive(Debug, Clone, Copy)]
+struct HeldAnnounce {
+    pub discord_user_id: Option<UserId>,
+    pub is_streaming: bool,
+}
+
+/// name of a channel renamed by the bot, the other channels have their original name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+enum ChannelNaming {
+    /// renamed for a live streamer
+    #[default]
+    Live,
+    /// renamed with the offline name since the stream ended
+    Offline { since: SystemTime },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 struct Channel {
+    // voice status instead of name in voice-status mode
     pub original_name: String,
+    #[serde(default)]
+    pub naming: ChannelNaming,
+    // guild of the channel, used by departures and the status
+    // channel ids are unique across guilds, so the state of a channel never leaks to another guild
+    #[serde(default)]
+    pub guild_id: Option<GuildId>,
+    // user whose stream caused the rename
+    #[serde(default)]
+    pub streamer: Option<UserId>,
+    // name or voice status last given by the bot while live, None for the channels renamed
+    // before it was kept
+    #[serde(default)]
+    pub applied_name: Option<String>,
 }
 
 #[derive(Debug)]
@@ -56,42 +221,710 @@ struct User {
     pub has_been_part_of_voice_state_event: bool,
     pub twitch_id: u64,
     pub twitch_is_streaming: Option<bool>,
+    // known once a stream event of the user has been received
+    pub twitch_login: Option<String>,
     // last time twitch_is_streaming has been updated
     pub last_twitch_is_streaming_update: Option<SystemTime>,
+    // forum thread created for the current stream when announce_style is ForumThread
+    pub announce_thread_id: Option<ChannelId>,
+    // go-live embed announce of the current stream, edited every announce_refresh
+    pub announce_message_id: Option<MessageId>,
+    // role pinged by the go-live announce
+    pub notify_role_id: Option<RoleId>,
+    // renames and announces are suppressed until this time
+    pub snoozed_until: Option<SystemTime>,
+    // name of the renamed channel set by the config for this streamer
+    pub rename_override: Option<String>,
+    // name of the renamed channel set by the streamer with /my_template, over rename_override
+    pub custom_template: Option<String>,
+    // channels the user has been in while streaming since the stream started, a channel
+    // renamed for another streamer stays renamed for them, they are restored with it
+    pub session_channels: HashSet<ChannelId>,
+    // twitch category of the current stream, kept after it ends until the next one starts
+    pub twitch_category: Option<String>,
 }
 
 impl User {
     /// helper function to update twitch_is_streaming and tied value last_twitch_is_streaming
     /// if the current value is the same as new_value, last_twitch_is_streaming_update will not be updated
+    /// the category of the previous stream is forgotten when a new one starts
     pub fn set_twitch_is_streaming(&mut self, new_value: Option<bool>) {
         if self.twitch_is_streaming != new_value {
             self.twitch_is_streaming = new_value;
             self.last_twitch_is_streaming_update = Some(SystemTime::now());
+            if new_value == Some(true) {
+                self.twitch_category = None;
+            }
         }
     }
+
+    pub fn is_snoozed(&self) -> bool {
+        self.snoozed_until.is_some_and(|t| t > SystemTime::now())
+    }
 }
 
 impl DiscordTwitchWatcher {
-    pub fn find_user_by_twitch_id(&self, twitch_id: u64) -> Option<&User> {
-        self.users
+    /// save value under key, errors are logged since the in memory state stays usable
+    /// what names the value in the log
+    fn persist<T: Serialize>(&self, key: &str, value: &T, what: &str) {
+        if let Err(why) = self.store.save(key, value) {
+            error!("Error on saving {} {}", what, why);
+        }
+    }
+
+    pub fn save_channels(&self) {
+        self.persist(RENAMED_CHANNELS_KEY, &self.channels, "renamed channels");
+    }
+
+    pub fn save_channel_templates(&self) {
+        self.persist(
+            CHANNEL_TEMPLATES_KEY,
+            &self.channel_templates,
+            "channel templates",
+        );
+    }
+
+    pub fn save_schedules(&self) {
+        self.persist(SCHEDULES_KEY, &self.schedules, "schedules");
+    }
+
+    /// name given to renamed channels of the guild when the stream ends, None to restore them
+    pub fn offline_name_for(&self, guild_id: GuildId) -> Option<&str> {
+        self.offline_channel_names
+            .get(&guild_id)
+            .or(self.offline_channel_name.as_ref())
+            .map(String::as_str)
+    }
+
+    /// channels with the offline name for longer than offline_restore
+    pub fn find_expired_offline_channels(&self) -> Vec<ChannelId> {
+        let Some(offline_restore) = self.offline_restore else {
+            return vec![];
+        };
+        self.channels
             .iter()
-            .find(|f| f.1.twitch_id == twitch_id)
-            .map(|m| m.1)
+            .filter(|(_, c)| match c.naming {
+                ChannelNaming::Offline { since } => since
+                    .elapsed()
+                    .is_ok_and(|elapsed| elapsed >= offline_restore),
+                ChannelNaming::Live => false,
+            })
+            .map(|(channel_id, _)| *channel_id)
+            .collect()
+    }
+
+    /// name given to renamed channels of the guild without a template of their own
+    pub fn renamed_name_of_guild(&self, guild_id: GuildId) -> &str {
+        self.renamed_channel_names
+            .get(&guild_id)
+            .unwrap_or(&self.renamed_channel_name)
+    }
+
+    /// name given to channel_id of guild_id while streamer is in it, the template of the
+    /// streamer comes before the one of the channel, then the one of the guild
+    /// {game} is replaced by the twitch category of the streamer, empty until it's known
+    pub fn renamed_name_for(
+        &self,
+        channel_id: &ChannelId,
+        guild_id: GuildId,
+        streamer: &UserId,
+    ) -> String {
+        let user = self.users.get(streamer);
+        user.and_then(|u| u.custom_template.as_deref())
+            .or_else(|| user.and_then(|u| u.rename_override.as_deref()))
+            .or_else(|| self.channel_templates.get(channel_id).map(String::as_str))
+            .unwrap_or_else(|| self.renamed_name_of_guild(guild_id))
+            .replace("{game}", self.category_of(streamer))
+    }
+
+    /// voice status of the channel of a streaming user in voice-status mode
+    pub fn voice_status_for(&self, discord_user_id: &UserId) -> String {
+        let login = self
+            .users
+            .get(discord_user_id)
+            .and_then(|u| u.twitch_login.clone())
+            .unwrap_or_else(|| discord_user_id.to_string());
+        self.voice_status
+            .replace("{login}", &login)
+            .replace("{game}", self.category_of(discord_user_id))
+    }
+
+    fn category_of(&self, discord_user_id: &UserId) -> &str {
+        self.users
+            .get(discord_user_id)
+            .and_then(|u| u.twitch_category.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// register a deferred announce for the streamer, replacing any previous one, returns its id
+    pub fn defer_announce(&mut self, twitch_id: u64) -> u64 {
+        self.last_deferred_announce_id += 1;
+        self.deferred_announces
+            .insert(twitch_id, self.last_deferred_announce_id);
+        self.last_deferred_announce_id
+    }
+
+    /// remove the deferred announce of the streamer, returns true if it was still pending,
+    /// or if announce_id is given, true only if that is the pending one
+    pub fn take_deferred_announce(&mut self, twitch_id: u64, announce_id: Option<u64>) -> bool {
+        match (self.deferred_announces.get(&twitch_id), announce_id) {
+            (Some(pending), Some(announce_id)) if *pending != announce_id => false,
+            (Some(_), _) => {
+                self.deferred_announces.remove(&twitch_id);
+                true
+            }
+            (None, _) => false,
+        }
+    }
+
+    pub fn save_notify_subscriptions(&self) {
+        self.persist(
+            NOTIFY_SUBSCRIPTIONS_KEY,
+            &self.notify_subscriptions,
+            "notify subscriptions",
+        );
     }
+
+    /// returns false if the subscriber wasn't subscribed to the streamer
+    pub fn remove_notify_subscription(&mut self, streamer: &UserId, subscriber: &UserId) -> bool {
+        let Some(subscribers) = self.notify_subscriptions.get_mut(streamer) else {
+            return false;
+        };
+        if subscribers.remove(subscriber).is_none() {
+            return false;
+        }
+        if subscribers.is_empty() {
+            self.notify_subscriptions.remove(streamer);
+        }
+        self.save_notify_subscriptions();
+        true
+    }
+
+    /// drop the subscriptions made by subscriber from guild_id, the ones made from other
+    /// guilds are kept, returns false if there were none
+    pub fn remove_guild_notify_subscriptions(
+        &mut self,
+        guild_id: GuildId,
+        subscriber: &UserId,
+    ) -> bool {
+        let mut changed = false;
+        for subscribers in self.notify_subscriptions.values_mut() {
+            if let Some(guilds) = subscribers.get_mut(subscriber) {
+                changed |= guilds.remove(&guild_id);
+                if guilds.is_empty() {
+                    subscribers.remove(subscriber);
+                }
+            }
+        }
+        if changed {
+            self.notify_subscriptions.retain(|_, s| !s.is_empty());
+            self.save_notify_subscriptions();
+        }
+        changed
+    }
+
+    /// persist the snoozes that haven't expired
+    pub fn save_snoozes(&self) {
+        let snoozes: HashMap<UserId, SystemTime> = self
+            .users
+            .values()
+            .filter(|u| u.is_snoozed())
+            .filter_map(|u| u.snoozed_until.map(|t| (u.discord_id, t)))
+            .collect();
+        self.persist(SNOOZES_KEY, &snoozes, "snoozes");
+    }
+
+    pub fn save_tuning(&self) {
+        self.persist(TUNING_KEY, &self.tuning, "tuning");
+    }
+
+    /// persist the templates set by streamers
+    pub fn save_user_templates(&self) {
+        let templates: HashMap<UserId, String> = self
+            .users
+            .values()
+            .filter_map(|u| u.custom_template.clone().map(|t| (u.discord_id, t)))
+            .collect();
+        self.persist(USER_TEMPLATES_KEY, &templates, "user templates");
+    }
+
+    /// persist the go-live announces, so that they can be edited when the stream ends after
+    /// a restart
+    pub fn save_announce_messages(&self) {
+        let announce_messages: HashMap<UserId, MessageId> = self
+            .users
+            .values()
+            .filter_map(|u| u.announce_message_id.map(|m| (u.discord_id, m)))
+            .collect();
+        self.persist(
+            ANNOUNCE_MESSAGES_KEY,
+            &announce_messages,
+            "announce messages",
+        );
+    }
+
+    /// persist the forum threads of the current streams, so that the end of the stream is
+    /// posted in them after a restart
+    pub fn save_announce_threads(&self) {
+        let announce_threads: HashMap<UserId, ChannelId> = self
+            .users
+            .values()
+            .filter_map(|u| u.announce_thread_id.map(|t| (u.discord_id, t)))
+            .collect();
+        self.persist(ANNOUNCE_THREADS_KEY, &announce_threads, "announce threads");
+    }
+
+    /// number of monitored streams that are live
+    pub fn live_count(&self) -> usize {
+        self.users
+            .values()
+            .filter(|u| u.twitch_is_streaming == Some(true))
+            .count()
+            + self.unlinked_live.len()
+    }
+
+    /// ask for the presence to show the new live count
+    pub fn request_presence_update(&self) {
+        if let Some(live_presence) = &self.live_presence {
+            live_presence.notify_one();
+        }
+    }
+
     pub fn find_user_by_twitch_id_mut(&mut self, twitch_id: u64) -> Option<&mut User> {
         self.users
             .iter_mut()
             .find(|f| f.1.twitch_id == twitch_id)
             .map(|m| m.1)
     }
-    pub fn find_user_in_channel(&self, channel_id: ChannelId) -> Vec<&User> {
-        self.users
+    /// channels renamed because of the stream of discord_user_id
+    pub fn find_channels_renamed_for(&self, discord_user_id: &UserId) -> Vec<ChannelId> {
+        self.channels
             .iter()
-            .filter(|f| match f.1.current_channel_id {
-                Some(channel) => channel == channel_id,
-                None => false,
+            .filter(|(_, c)| c.streamer.as_ref() == Some(discord_user_id))
+            .map(|(channel_id, _)| *channel_id)
+            .collect()
+    }
+
+    /// channels renamed for discord_user_id, and the ones of their stream session which are
+    /// still renamed, like a channel renamed for another streamer they stayed in
+    pub fn find_session_channels(&self, discord_user_id: &UserId) -> Vec<ChannelId> {
+        let mut channel_ids = self.find_channels_renamed_for(discord_user_id);
+        if let Some(user) = self.users.get(discord_user_id) {
+            for channel_id in &user.session_channels {
+                if self.channels.contains_key(channel_id) && !channel_ids.contains(channel_id) {
+                    channel_ids.push(*channel_id);
+                }
+            }
+        }
+        channel_ids
+    }
+
+    /// a monitored user left guild_id, in_guild_voice being true if their voice channel is in it
+    /// their renamed channels of the guild without a live streamer are forgotten and returned
+    /// with the original name to give back, the user is forgotten with forget_departed_streamers
+    pub fn depart_streamer(
+        &mut self,
+        discord_user_id: &UserId,
+        guild_id: GuildId,
+        in_guild_voice: bool,
+    ) -> Vec<(ChannelId, String)> {
+        // the user isn't in a voice channel of the guild anymore, so they don't keep it renamed
+        if in_guild_voice || self.forget_departed_streamers {
+            self.set_user_voice_channel(discord_user_id, None);
+        }
+        let channel_ids: Vec<ChannelId> = self
+            .find_session_channels(discord_user_id)
+            .into_iter()
+            .filter(|channel_id| {
+                self.channels
+                    .get(channel_id)
+                    .is_some_and(|c| c.guild_id.is_none_or(|g| g == guild_id))
             })
-            .map(|m| m.1)
+            .collect();
+        let mut restored = vec![];
+        for channel_id in channel_ids {
+            if let Some(user) = self.users.get_mut(discord_user_id) {
+                user.session_channels.remove(&channel_id);
+            }
+            // kept renamed for the streamers still live in it
+            if self.live_users_in_channel(channel_id) > 0 {
+                continue;
+            }
+            if let Some(channel) = self.channels.remove(&channel_id) {
+                restored.push((channel_id, channel.original_name));
+            }
+        }
+        if !restored.is_empty() {
+            self.save_channels();
+        }
+        if self.forget_departed_streamers && self.users.remove(discord_user_id).is_some() {
+            info!(
+                "Forgot monitored user {}, their streams are only announced",
+                discord_user_id
+            );
+            self.request_presence_update();
+            self.save_announce_messages();
+            self.save_announce_threads();
+        }
+        restored
+    }
+
+    /// renamed channels without a live streamer in them, with the streamer they were renamed for
+    /// channels with the offline name aren't stuck, they aren't expected to have one
+    pub fn find_stuck_channels(&self) -> Vec<(ChannelId, Option<UserId>)> {
+        self.channels
+            .iter()
+            .filter(|(channel_id, c)| {
+                c.naming == ChannelNaming::Live && self.live_users_in_channel(**channel_id) == 0
+            })
+            .map(|(channel_id, channel)| (*channel_id, channel.streamer))
             .collect()
     }
+
+    /// number of users in channel_id whose stream is live, users who aren't streaming or whose
+    /// status isn't known yet aren't counted
+    pub fn live_users_in_channel(&self, channel_id: ChannelId) -> usize {
+        self.find_user_in_channel(channel_id)
+            .iter()
+            .filter(|u| u.twitch_is_streaming == Some(true))
+            .count()
+    }
+
+    pub fn find_user_in_channel(&self, channel_id: ChannelId) -> Vec<&User> {
+        match self.users_by_channel.get(&channel_id) {
+            Some(user_ids) => user_ids
+                .iter()
+                .filter_map(|user_id| self.users.get(user_id))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// count a voice channel move of discord_user_id, returns the number of the move to give
+    /// to settle_voice_move once rename_cooldown is elapsed
+    pub fn record_voice_move(&mut self, discord_user_id: UserId) -> u64 {
+        let move_count = self.voice_moves.entry(discord_user_id).or_default();
+        *move_count += 1;
+        *move_count
+    }
+
+    /// true if move_count is the last move of discord_user_id, whose channels can be renamed,
+    /// false if they moved again meanwhile
+    pub fn settle_voice_move(&mut self, discord_user_id: UserId, move_count: u64) -> bool {
+        if self.voice_moves.get(&discord_user_id) != Some(&move_count) {
+            return false;
+        }
+        self.voice_moves.remove(&discord_user_id);
+        true
+    }
+
+    /// set the current voice channel of a monitored user and keep users_by_channel in sync
+    /// returns false if the user isn't monitored
+    pub fn set_user_voice_channel(
+        &mut self,
+        discord_user_id: &UserId,
+        channel_id: Option<ChannelId>,
+    ) -> bool {
+        let Some(user) = self.users.get_mut(discord_user_id) else {
+            return false;
+        };
+        user.has_been_part_of_voice_state_event = true;
+        let old_channel_id = std::mem::replace(&mut user.current_channel_id, channel_id);
+        if old_channel_id == channel_id {
+            return true;
+        }
+
+        if let Some(old_channel_id) = old_channel_id {
+            if let Some(user_ids) = self.users_by_channel.get_mut(&old_channel_id) {
+                user_ids.remove(discord_user_id);
+                if user_ids.is_empty() {
+                    self.users_by_channel.remove(&old_channel_id);
+                }
+            }
+        }
+        if let Some(channel_id) = channel_id {
+            self.users_by_channel
+                .entry(channel_id)
+                .or_default()
+                .insert(*discord_user_id);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discord::fixtures::{channel_id, renamed_channel, user, watcher, GUILD_ID};
+
+    /// streamer 1 live in channel 10, renamed for them
+    fn live_streamer() -> DiscordTwitchWatcher {
+        let mut watcher = watcher();
+        let streamer = UserId::new(1);
+        let mut user = user(1, 100);
+        user.twitch_is_streaming = Some(true);
+        user.session_channels.insert(channel_id(10));
+        watcher.users.insert(streamer, user);
+        watcher.set_user_voice_channel(&streamer, Some(channel_id(10)));
+        watcher
+            .channels
+            .insert(channel_id(10), renamed_channel("general", streamer));
+        watcher.offline_channel_name = Some(String::from("offline"));
+        watcher
+    }
+
+    #[test]
+    fn voice_channel_index_follows_moves() {
+        let mut watcher = watcher();
+        let first = UserId::new(1);
+        let second = UserId::new(2);
+        watcher.users.insert(first, user(1, 100));
+        watcher.users.insert(second, user(2, 200));
+        let in_channel = |watcher: &DiscordTwitchWatcher, id: u64| {
+            let mut ids: Vec<UserId> = watcher
+                .find_user_in_channel(channel_id(id))
+                .iter()
+                .map(|u| u.discord_id)
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        assert!(watcher.set_user_voice_channel(&first, Some(channel_id(10))));
+        assert!(watcher.set_user_voice_channel(&second, Some(channel_id(10))));
+        assert_eq!(in_channel(&watcher, 10), vec![first, second]);
+
+        watcher.set_user_voice_channel(&first, Some(channel_id(11)));
+        assert_eq!(in_channel(&watcher, 10), vec![second]);
+        assert_eq!(in_channel(&watcher, 11), vec![first]);
+
+        watcher.set_user_voice_channel(&second, None);
+        watcher.set_user_voice_channel(&first, None);
+        assert!(watcher.users_by_channel.is_empty());
+        assert_eq!(watcher.users[&first].current_channel_id, None);
+
+        assert!(!watcher.set_user_voice_channel(&UserId::new(3), Some(channel_id(10))));
+        assert!(watcher.users_by_channel.is_empty());
+    }
+
+    #[test]
+    fn leaving_while_live_restores_the_original_name() {
+        let mut watcher = live_streamer();
+        let streamer = UserId::new(1);
+
+        let restored = watcher.depart_streamer(&streamer, GUILD_ID, true);
+
+        assert_eq!(restored, vec![(channel_id(10), String::from("general"))]);
+        assert!(watcher.channels.is_empty());
+        let user = &watcher.users[&streamer];
+        assert!(user.session_channels.is_empty());
+        assert_eq!(user.current_channel_id, None);
+        assert!(watcher.users_by_channel.is_empty());
+    }
+
+    #[test]
+    fn forgotten_streamer_leaves_no_index_entry() {
+        let mut watcher = live_streamer();
+        watcher.forget_departed_streamers = true;
+        let streamer = UserId::new(1);
+
+        watcher.depart_streamer(&streamer, GUILD_ID, false);
+
+        assert!(!watcher.users.contains_key(&streamer));
+        assert!(watcher.users_by_channel.is_empty());
+        assert!(watcher.channels.is_empty());
+    }
+
+    #[test]
+    fn channel_stays_renamed_for_another_live_streamer() {
+        let mut watcher = live_streamer();
+        let other = UserId::new(2);
+        let mut other_user = user(2, 200);
+        other_user.twitch_is_streaming = Some(true);
+        watcher.users.insert(other, other_user);
+        watcher.set_user_voice_channel(&other, Some(channel_id(10)));
+
+        let restored = watcher.depart_streamer(&UserId::new(1), GUILD_ID, true);
+
+        assert!(restored.is_empty());
+        assert!(watcher.channels.contains_key(&channel_id(10)));
+    }
+
+    #[test]
+    fn channel_left_before_going_offline_is_still_restored() {
+        let mut watcher = live_streamer();
+        let streamer = UserId::new(1);
+
+        watcher.set_user_voice_channel(&streamer, None);
+        watcher
+            .users
+            .get_mut(&streamer)
+            .unwrap()
+            .twitch_is_streaming = Some(false);
+
+        assert!(watcher.find_user_in_channel(channel_id(10)).is_empty());
+        assert_eq!(
+            watcher.find_session_channels(&streamer),
+            vec![channel_id(10)]
+        );
+    }
+
+    #[test]
+    fn quick_moves_settle_once_in_the_last_channel() {
+        let mut watcher = live_streamer();
+        let streamer = UserId::new(1);
+
+        let moves: Vec<u64> = [11, 12, 13]
+            .into_iter()
+            .map(|id| {
+                watcher.set_user_voice_channel(&streamer, Some(channel_id(id)));
+                watcher.record_voice_move(streamer)
+            })
+            .collect();
+
+        assert!(!watcher.settle_voice_move(streamer, moves[0]));
+        assert!(!watcher.settle_voice_move(streamer, moves[1]));
+        assert!(watcher.settle_voice_move(streamer, moves[2]));
+        assert!(watcher.voice_moves.is_empty());
+        // the channel left first is still restored once settled
+        assert_eq!(
+            watcher.users[&streamer].current_channel_id,
+            Some(channel_id(13))
+        );
+        assert_eq!(
+            watcher.find_session_channels(&streamer),
+            vec![channel_id(10)]
+        );
+    }
+
+    #[test]
+    fn only_live_users_keep_a_channel_renamed() {
+        let mut watcher = live_streamer();
+        let streamer = UserId::new(1);
+        let mut not_streaming = user(2, 200);
+        not_streaming.twitch_is_streaming = Some(false);
+        watcher.users.insert(UserId::new(2), not_streaming);
+        watcher.set_user_voice_channel(&UserId::new(2), Some(channel_id(10)));
+        watcher.users.insert(UserId::new(3), user(3, 300));
+        watcher.set_user_voice_channel(&UserId::new(3), Some(channel_id(10)));
+        assert_eq!(watcher.live_users_in_channel(channel_id(10)), 1);
+
+        // the status of a streamer going offline is updated before their channel
+        watcher
+            .users
+            .get_mut(&streamer)
+            .unwrap()
+            .twitch_is_streaming = Some(false);
+
+        assert_eq!(watcher.find_user_in_channel(channel_id(10)).len(), 3);
+        assert_eq!(watcher.live_users_in_channel(channel_id(10)), 0);
+        assert_eq!(
+            watcher.find_stuck_channels(),
+            vec![(channel_id(10), Some(streamer))]
+        );
+    }
+
+    #[test]
+    fn every_channel_renamed_during_the_session_is_restored() {
+        let mut watcher = live_streamer();
+        let streamer = UserId::new(1);
+        for id in [11, 12] {
+            watcher.set_user_voice_channel(&streamer, Some(channel_id(id)));
+            let user = watcher.users.get_mut(&streamer).unwrap();
+            user.session_channels.insert(channel_id(id));
+            watcher
+                .channels
+                .insert(channel_id(id), renamed_channel("general", streamer));
+        }
+        // a channel of the session which has already been restored
+        let user = watcher.users.get_mut(&streamer).unwrap();
+        user.session_channels.insert(channel_id(13));
+
+        let mut channel_ids = watcher.find_session_channels(&streamer);
+        channel_ids.sort();
+
+        assert_eq!(
+            channel_ids,
+            vec![channel_id(10), channel_id(11), channel_id(12)]
+        );
+    }
+
+    #[test]
+    fn renamed_name_precedence() {
+        let mut watcher = watcher();
+        let streamer = UserId::new(1);
+        watcher.users.insert(streamer, user(1, 100));
+        let name = |watcher: &DiscordTwitchWatcher| {
+            watcher.renamed_name_for(&channel_id(10), GUILD_ID, &streamer)
+        };
+
+        watcher.renamed_channel_name = String::from("global");
+        assert_eq!(name(&watcher), "global");
+        watcher
+            .renamed_channel_names
+            .insert(GUILD_ID, String::from("guild"));
+        assert_eq!(name(&watcher), "guild");
+        watcher
+            .channel_templates
+            .insert(channel_id(10), String::from("channel"));
+        assert_eq!(name(&watcher), "channel");
+        let user = watcher.users.get_mut(&streamer).unwrap();
+        user.rename_override = Some(String::from("override"));
+        assert_eq!(name(&watcher), "override");
+        let user = watcher.users.get_mut(&streamer).unwrap();
+        user.custom_template = Some(String::from("custom"));
+        assert_eq!(name(&watcher), "custom");
+    }
+
+    #[test]
+    fn guild_name_only_applies_to_its_guild() {
+        let mut watcher = watcher();
+        watcher.renamed_channel_name = String::from("global");
+        watcher
+            .renamed_channel_names
+            .insert(GUILD_ID, String::from("guild"));
+
+        assert_eq!(watcher.renamed_name_of_guild(GuildId::new(2000)), "global");
+    }
+
+    #[test]
+    fn leaving_a_guild_keeps_the_subscriptions_of_other_guilds() {
+        let mut watcher = watcher();
+        let subscriber = UserId::new(5);
+        let other_guild = GuildId::new(2000);
+        let subscribers = HashMap::from([
+            (subscriber, HashSet::from([GUILD_ID, other_guild])),
+            (UserId::new(6), HashSet::from([GUILD_ID])),
+        ]);
+        watcher
+            .notify_subscriptions
+            .insert(UserId::new(1), subscribers);
+        watcher.notify_subscriptions.insert(
+            UserId::new(2),
+            HashMap::from([(subscriber, HashSet::from([GUILD_ID]))]),
+        );
+
+        assert!(watcher.remove_guild_notify_subscriptions(GUILD_ID, &subscriber));
+
+        let subscribers = &watcher.notify_subscriptions[&UserId::new(1)];
+        assert_eq!(subscribers[&subscriber], HashSet::from([other_guild]));
+        assert!(subscribers.contains_key(&UserId::new(6)));
+        assert!(!watcher.notify_subscriptions.contains_key(&UserId::new(2)));
+        assert!(!watcher.remove_guild_notify_subscriptions(GUILD_ID, &subscriber));
+    }
+
+    #[test]
+    fn announce_threads_are_persisted() {
+        let mut watcher = watcher();
+        let mut streamer = user(1, 100);
+        streamer.announce_thread_id = Some(channel_id(20));
+        watcher.users.insert(UserId::new(1), streamer);
+        watcher.users.insert(UserId::new(2), user(2, 200));
+
+        watcher.save_announce_threads();
+
+        let stored: HashMap<UserId, ChannelId> =
+            watcher.store.load(ANNOUNCE_THREADS_KEY).unwrap().unwrap();
+        assert_eq!(stored, HashMap::from([(UserId::new(1), channel_id(20))]));
+    }
 }
@@ -0,0 +1,504 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use poise::serenity_prelude as serenity;
+use serenity::all::{
+    Channel, ChannelId, ChannelType, CreateAllowedMentions, CreateEmbed, CreateForumPost,
+    CreateMessage, EditMessage, HttpError, Mentionable, MessageId, Permissions, UserId,
+};
+use tokio::sync::RwLock;
+use tokio::time::sleep;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::config::AnnounceStyle;
+use crate::discord::sink::{NotificationSink, StreamEvent};
+use crate::discord::twitch::send_twitch_request;
+use crate::discord::DiscordTwitchWatcher;
+use crate::inter_comm::TwitchRequest;
+
+// twitch purple
+const EMBED_COLOUR: u32 = 0x9146FF;
+// delay before retrying an announce failed because of the sink, doubled after each failure
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(5);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(120);
+// an announce late by more than this is dropped
+const ANNOUNCE_STALE_AFTER: Duration = Duration::from_secs(600);
+// preview of a live stream, same as the thumbnail_url of helix streams
+const THUMBNAIL_URL: &str =
+    "https://static-cdn.jtvnw.net/previews-ttv/live_user_{login}-{width}x{height}.jpg";
+const THUMBNAIL_WIDTH: u32 = 1280;
+const THUMBNAIL_HEIGHT: u32 = 720;
+
+/// post a message about a stream going online or offline in the announce channel
+pub async fn announce_stream_event(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    event: &StreamEvent,
+) -> anyhow::Result<()> {
+    let discord_user_id = event.discord_user_id.as_ref();
+    let streamer_user_login = event.streamer_user_login.as_str();
+    let is_streaming = event.is_streaming;
+    let (announce_channel_id, announce_style, edit_ended_announce) = {
+        let reader = twitch.read().await;
+        match reader.announce_channel_id {
+            Some(channel_id) if reader.announce_channel_problem.is_none() => (
+                channel_id,
+                reader.announce_style,
+                reader.edit_ended_announce,
+            ),
+            Some(channel_id) => {
+                trace!(
+                    "Announce channel {} is invalid, skipping announce",
+                    channel_id
+                );
+                return Ok(());
+            }
+            None => {
+                trace!("No announce channel configured");
+                return Ok(());
+            }
+        }
+    };
+
+    if !is_streaming && edit_ended_announce && announce_style != AnnounceStyle::ForumThread {
+        if let Some(discord_user_id) = discord_user_id {
+            if let Some(message_id) =
+                set_announce_message(twitch.clone(), discord_user_id, None).await
+            {
+                match edit_ended_announce_message(
+                    ctx,
+                    twitch.clone(),
+                    announce_channel_id,
+                    message_id,
+                    announce_style,
+                    event,
+                )
+                .await
+                {
+                    Ok(()) => return Ok(()),
+                    Err(why) => warn!(
+                        "Can't edit the announce of {}, posting a new one {}",
+                        streamer_user_login, why
+                    ),
+                }
+            }
+        }
+    }
+
+    // only the role of the streamer is pinged, and only when the stream goes live
+    let notify_role_id = match (is_streaming, discord_user_id) {
+        (true, Some(discord_user_id)) => twitch
+            .read()
+            .await
+            .users
+            .get(discord_user_id)
+            .and_then(|u| u.notify_role_id),
+        _ => None,
+    };
+    let mut text = event.text.clone();
+    let mut message = CreateMessage::new();
+    let mut allowed_mentions = CreateAllowedMentions::new();
+    if let Some(notify_role_id) = notify_role_id {
+        allowed_mentions = allowed_mentions.roles([notify_role_id]);
+    }
+    message = message.allowed_mentions(allowed_mentions);
+
+    debug!("Announcing {} in {}", text, announce_channel_id);
+    match announce_style {
+        AnnounceStyle::Message => {
+            if let Some(notify_role_id) = notify_role_id {
+                text = format!("{} {}", notify_role_id.mention(), text);
+            }
+            let posted = announce_channel_id
+                .send_message(&ctx.http, message.content(text))
+                .await?;
+            if let Some(discord_user_id) = discord_user_id {
+                set_announce_message(twitch, discord_user_id, is_streaming.then_some(posted.id))
+                    .await;
+            }
+        }
+        AnnounceStyle::Embed => {
+            let mut embed = CreateEmbed::new().title(text).colour(EMBED_COLOUR);
+            if is_streaming {
+                embed = embed.url(event.url()).image(fresh_thumbnail_url(
+                    &THUMBNAIL_URL.replace("{login}", streamer_user_login),
+                ));
+            }
+            // mentions in embeds don't ping
+            if let Some(notify_role_id) = notify_role_id {
+                message = message.content(notify_role_id.mention().to_string());
+            }
+            let posted = announce_channel_id
+                .send_message(&ctx.http, message.embed(embed))
+                .await?;
+            if let Some(discord_user_id) = discord_user_id {
+                let message_id = is_streaming.then_some(posted.id);
+                set_announce_message(twitch.clone(), discord_user_id, message_id).await;
+                let announce_refresh = twitch.read().await.announce_refresh;
+                if let (Some(message_id), Some(every)) = (message_id, announce_refresh) {
+                    tokio::spawn(refresh_announce(
+                        ctx.clone(),
+                        twitch,
+                        *discord_user_id,
+                        announce_channel_id,
+                        message_id,
+                        event.clone(),
+                        every,
+                    ));
+                }
+            }
+        }
+        AnnounceStyle::ForumThread => {
+            if is_streaming {
+                if let Some(notify_role_id) = notify_role_id {
+                    text = format!("{} {}", notify_role_id.mention(), text);
+                }
+                let thread = announce_channel_id
+                    .create_forum_post(
+                        &ctx.http,
+                        CreateForumPost::new(
+                            format!("{} stream", streamer_user_login),
+                            message.content(text),
+                        ),
+                    )
+                    .await?;
+                if let Some(discord_user_id) = discord_user_id {
+                    set_announce_thread(twitch, discord_user_id, Some(thread.id)).await;
+                }
+            } else {
+                let thread_id = match discord_user_id {
+                    Some(discord_user_id) => {
+                        set_announce_thread(twitch, discord_user_id, None).await
+                    }
+                    None => None,
+                };
+                match thread_id {
+                    Some(thread_id) => {
+                        thread_id
+                            .send_message(&ctx.http, CreateMessage::new().content(text))
+                            .await?;
+                    }
+                    None => warn!(
+                        "No announce thread found for the stream of {}",
+                        streamer_user_login
+                    ),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// edit the go-live announce of a stream which ended, its text is struck through and followed
+/// by the one of the ended stream
+async fn edit_ended_announce_message(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    announce_style: AnnounceStyle,
+    event: &StreamEvent,
+) -> anyhow::Result<()> {
+    let online_text = StreamEvent::new(
+        event.discord_user_id,
+        &event.streamer_user_login,
+        true,
+        &twitch.read().await.announce_templates,
+        event.guild_ids.clone(),
+    )
+    .text;
+    let edit = match announce_style {
+        AnnounceStyle::Embed => EditMessage::new().content("").embed(
+            CreateEmbed::new()
+                .title(&event.text)
+                .description(format!("~~{}~~", online_text))
+                .colour(EMBED_COLOUR),
+        ),
+        _ => EditMessage::new().content(format!("~~{}~~\n{}", online_text, event.text)),
+    };
+    debug!(
+        "Editing the announce of {} as ended",
+        event.streamer_user_login
+    );
+    channel_id.edit_message(&ctx.http, message_id, edit).await?;
+    Ok(())
+}
+
+/// edit the embed announce of a live stream every so often with a fresh thumbnail and the
+/// viewer count, until the stream goes offline or another announce replaces it
+async fn refresh_announce(
+    ctx: serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    discord_user_id: UserId,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    event: StreamEvent,
+    every: Duration,
+) {
+    loop {
+        sleep(every).await;
+        let (twitch_id, twitch_requests) = {
+            let reader = twitch.read().await;
+            let Some(user) = reader.users.get(&discord_user_id) else {
+                return;
+            };
+            if user.twitch_is_streaming != Some(true)
+                || user.announce_message_id != Some(message_id)
+            {
+                debug!(
+                    "Stopping the announce refresh of {}",
+                    event.streamer_user_login
+                );
+                return;
+            }
+            (user.twitch_id, reader.twitch_requests.clone())
+        };
+        let stream =
+            match send_twitch_request(&twitch_requests, |reply| TwitchRequest::StreamInfo {
+                user_id: twitch_id.to_string(),
+                reply,
+            })
+            .await
+            {
+                Ok(Some(stream)) => stream,
+                // twitch may not list a stream which has just started yet, the offline event
+                // stops the refresh otherwise
+                Ok(None) => continue,
+                Err(why) => {
+                    warn!(
+                        "Can't get the stream of {} to refresh its announce {}",
+                        event.streamer_user_login, why
+                    );
+                    continue;
+                }
+            };
+        let embed = CreateEmbed::new()
+            .title(&event.text)
+            .url(event.url())
+            .colour(EMBED_COLOUR)
+            .description(format!("{}\n{} viewers", stream.title, stream.viewer_count))
+            .image(fresh_thumbnail_url(&stream.thumbnail_url));
+        trace!("Refreshing the announce of {}", event.streamer_user_login);
+        if let Err(why) = channel_id
+            .edit_message(&ctx.http, message_id, EditMessage::new().embed(embed))
+            .await
+        {
+            let why = anyhow::Error::from(why);
+            if is_transient_error(&why) {
+                warn!(
+                    "Can't refresh the announce of {} {}",
+                    event.streamer_user_login, why
+                );
+                continue;
+            }
+            // the message has probably been deleted
+            error!(
+                "Stopping the announce refresh of {} {}",
+                event.streamer_user_login, why
+            );
+            return;
+        }
+    }
+}
+
+/// thumbnail_url with its size set and a query param so that Discord doesn't show a cached image,
+/// twitch updates the image behind the url every few minutes
+fn fresh_thumbnail_url(thumbnail_url: &str) -> String {
+    let url = thumbnail_url
+        .replace("{width}", &THUMBNAIL_WIDTH.to_string())
+        .replace("{height}", &THUMBNAIL_HEIGHT.to_string());
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}t={}", url, separator, now)
+}
+
+/// check that the announce channel can receive announces, they are skipped until restart otherwise
+pub async fn validate_announce_channel(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+) {
+    let (announce_channel_id, announce_style) = {
+        let reader = twitch.read().await;
+        match reader.announce_channel_id {
+            Some(channel_id) => (channel_id, reader.announce_style),
+            None => return,
+        }
+    };
+    let problem = match announce_channel_id.to_channel(ctx).await {
+        Ok(channel) => announce_channel_problem(ctx, channel, announce_style),
+        Err(why) => {
+            let why = anyhow::Error::from(why);
+            if is_transient_error(&why) {
+                warn!(
+                    "Can't check announce channel {}, assuming it's valid {}",
+                    announce_channel_id, why
+                );
+                return;
+            }
+            Some(format!("it can't be fetched : {}", why))
+        }
+    };
+    match &problem {
+        Some(problem) => error!(
+            "Announce channel {} is invalid, streams won't be announced in it : {}",
+            announce_channel_id, problem
+        ),
+        None => debug!("Announce channel {} is valid", announce_channel_id),
+    }
+    twitch.write().await.announce_channel_problem = problem;
+}
+
+/// the reason why announces can't be posted in channel, None if they can
+fn announce_channel_problem(
+    ctx: &serenity::Context,
+    channel: Channel,
+    announce_style: AnnounceStyle,
+) -> Option<String> {
+    let Some(channel) = channel.guild() else {
+        return Some(String::from("it isn't a server channel"));
+    };
+    let expected_kinds: &[ChannelType] = match announce_style {
+        AnnounceStyle::ForumThread => &[ChannelType::Forum],
+        AnnounceStyle::Message | AnnounceStyle::Embed => &[ChannelType::Text, ChannelType::News],
+    };
+    if !expected_kinds.contains(&channel.kind) {
+        return Some(format!(
+            "it is a {} channel, {} is expected for the announce style {:?}",
+            channel.kind.name(),
+            expected_kinds
+                .iter()
+                .map(|k| k.name())
+                .collect::<Vec<&str>>()
+                .join(" or "),
+            announce_style
+        ));
+    }
+    let bot_id = ctx.cache.current_user().id;
+    let permissions = ctx.cache.guild(channel.guild_id).and_then(|guild| {
+        let member = guild.members.get(&bot_id)?;
+        Some(guild.user_permissions_in(&channel, member))
+    });
+    match permissions {
+        Some(permissions)
+            if permissions.contains(Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES) =>
+        {
+            None
+        }
+        Some(_) => Some(String::from("the bot can't send messages in it")),
+        None => Some(String::from(
+            "its permissions can't be checked, the bot isn't in the cache of its guild",
+        )),
+    }
+}
+
+/// true if the announce failed because of the sink being unavailable, so it may succeed later
+pub fn is_transient_error(why: &anyhow::Error) -> bool {
+    if let Some(why) = why.downcast_ref::<reqwest::Error>() {
+        return why.is_timeout()
+            || why.is_connect()
+            || why.status().is_some_and(|s| s.is_server_error());
+    }
+    match why.downcast_ref::<serenity::Error>() {
+        Some(serenity::Error::Http(HttpError::UnsuccessfulRequest(response))) => {
+            response.status_code.is_server_error()
+        }
+        Some(serenity::Error::Http(HttpError::Request(_))) => true,
+        _ => false,
+    }
+}
+
+/// retry an announce which failed with a transient error until it succeeds or becomes stale
+/// returns the last error if the announce has been dropped
+pub async fn retry_announce_stream_event(
+    sink: Arc<dyn NotificationSink>,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    event: &StreamEvent,
+    since: Instant,
+) -> anyhow::Result<()> {
+    let discord_user_id = event.discord_user_id.as_ref();
+    let streamer_user_login = event.streamer_user_login.as_str();
+    let is_streaming = event.is_streaming;
+    let mut delay = RETRY_BASE_DELAY;
+    loop {
+        sleep(delay).await;
+        let current_status = {
+            let reader = twitch.read().await;
+            match discord_user_id {
+                Some(discord_user_id) => reader
+                    .users
+                    .get(discord_user_id)
+                    .and_then(|u| u.twitch_is_streaming),
+                None => None,
+            }
+        };
+        if discord_user_id.is_some() && current_status != Some(is_streaming) {
+            debug!(
+                "Stream of {} changed state since the announce failed, dropping it",
+                streamer_user_login
+            );
+            return Ok(());
+        }
+
+        match sink.notify(event).await {
+            Ok(()) => {
+                info!(
+                    "Announced stream of {} to {} after {}s",
+                    streamer_user_login,
+                    sink.name(),
+                    since.elapsed().as_secs()
+                );
+                return Ok(());
+            }
+            Err(why) if is_transient_error(&why) && since.elapsed() < ANNOUNCE_STALE_AFTER => {
+                debug!("Announce retry failed {}", why);
+                delay = (delay * 2).min(RETRY_MAX_DELAY);
+            }
+            Err(why) => {
+                warn!(
+                    "Dropping announce of the stream of {} to {} after {}s",
+                    streamer_user_login,
+                    sink.name(),
+                    since.elapsed().as_secs()
+                );
+                return Err(why);
+            }
+        }
+    }
+}
+
+/// set the go-live message of the current stream, returns the previous one
+async fn set_announce_message(
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    discord_user_id: &UserId,
+    message_id: Option<MessageId>,
+) -> Option<MessageId> {
+    let mut writer = twitch.write().await;
+    let previous = writer
+        .users
+        .get_mut(discord_user_id)
+        .and_then(|u| std::mem::replace(&mut u.announce_message_id, message_id));
+    if previous != message_id {
+        writer.save_announce_messages();
+    }
+    previous
+}
+
+/// replace the announce thread of the user, returning the previous one
+async fn set_announce_thread(
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    discord_user_id: &UserId,
+    thread_id: Option<ChannelId>,
+) -> Option<ChannelId> {
+    let mut writer = twitch.write().await;
+    let previous = writer
+        .users
+        .get_mut(discord_user_id)
+        .and_then(|u| std::mem::replace(&mut u.announce_thread_id, thread_id));
+    if previous != thread_id {
+        writer.save_announce_threads();
+    }
+    previous
+}
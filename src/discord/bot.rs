@@ -1,33 +1,90 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env::var;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use poise::builtins::on_error;
 use poise::serenity_prelude as serenity;
-use rand::seq::SliceRandom;
-use serenity::all::{ActivityData, GuildId, UserId};
+use serenity::all::{ActivityData, ChannelId, GuildId, MessageId, RoleId, UserId};
 use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::sync::{Mutex, RwLock};
-use tracing::{error, info, trace};
+use tokio::sync::{watch, Mutex, Notify, RwLock};
+use tracing::{error, info, trace, warn};
 
-use crate::config::Config;
-use crate::discord::message_response::handle_message;
-use crate::discord::random_stuff::{echo, ping, random_number};
+use crate::config::{AnswersConfig, Config, PresenceMode};
+use crate::discord::announce::validate_announce_channel;
+use crate::discord::broadcast::broadcast;
+use crate::discord::builtins::{help, register, servers};
+use crate::discord::chat_relay::ChatRelay;
+use crate::discord::config_show::{config_show, guild_settings};
+use crate::discord::diag::diag;
+use crate::discord::error_notifier::ErrorNotifier;
+use crate::discord::events::{events, EventHistory};
+use crate::discord::feedback::{feedback, Feedback};
+use crate::discord::guilds::{guilds, GuildAllowlist};
+use crate::discord::logs::logs;
+use crate::discord::message_response::{
+    handle_direct_message, handle_message, reload_answers, test_response, Answers, RandomPool,
+};
+use crate::discord::moderation::{ban, kick, move_member};
+use crate::discord::notify::{notify_me, notify_me_stop, notify_role, remove_subscriber};
+use crate::discord::permissions::{check_perms, invite};
+use crate::discord::presence::run_live_presence;
+use crate::discord::random_stuff::{echo, mock, mock_message, ping, random_number};
+use crate::discord::rename_audit::rename_audit;
+use crate::discord::rename_queue::{
+    rename_queue, rename_queue_flush, run_rename_queue, RenameQueue,
+};
+use crate::discord::scheduler::{
+    run_scheduler, schedule_add, schedule_cancel, schedule_list, schedule_once,
+};
+use crate::discord::shutdown::stop_on_shutdown;
+use crate::discord::sink::build_sinks;
+use crate::discord::snooze::snooze;
+use crate::discord::stats::stats;
+use crate::discord::telemetry::{run_telemetry, Telemetry};
+use crate::discord::tuning::{apply_tuning, tuning_set, tuning_show};
 use crate::discord::twitch::{
-    rename_channel, status, twitch_event_handler, update_streaming_status,
+    channel_template_set, handle_streamer_departure, handle_streamer_move, link_status,
+    my_template, reconcile_voice_states, run_held_announces, run_offline_restore, set_voice,
+    status, stream_info, subscribe_test, supervise_twitch_event_handler, twitch_prune,
+    twitch_ratelimit, twitch_reconcile_dryrun, twitch_refresh, twitch_scopes, twitch_subs,
+    twitch_token_status, update_streaming_status, update_twitch_streaming_status,
 };
 use crate::discord::{Data, DiscordTwitchWatcher, Error, User};
-use crate::inter_comm::InterComm;
+use crate::inter_comm::{InterComm, TwitchRequest};
+use crate::quiet_hours::QuietHours;
+use crate::store::{
+    Store, ANNOUNCE_MESSAGES_KEY, ANNOUNCE_THREADS_KEY, CHANNEL_TEMPLATES_KEY,
+    NOTIFY_SUBSCRIPTIONS_KEY, RENAMED_CHANNELS_KEY, SCHEDULES_KEY, SNOOZES_KEY, TUNING_KEY,
+    USER_TEMPLATES_KEY,
+};
+use crate::templates::AnnounceTemplates;
+use crate::twitch::health::TwitchHealth;
+
+// timeout of the http requests of the discord side outside of Discord, like telemetry
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub async fn run(
     sender: Sender<InterComm>,
     receiver: Receiver<InterComm>,
+    twitch_requests: Sender<TwitchRequest>,
     config: &Config,
+    store: Arc<dyn Store>,
+    twitch_health: Arc<TwitchHealth>,
+    stopping: watch::Receiver<bool>,
 ) -> Result<(), Error> {
     let discord_token = var("DISCORD_TOKEN").expect("Missing DISCORD_TOKEN");
 
-    let intents =
-        serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::MESSAGE_CONTENT;
+    let mut intents = serenity::GatewayIntents::non_privileged();
+    let content_intent = has_message_content_intent(&discord_token).await;
+    if content_intent {
+        intents |= serenity::GatewayIntents::MESSAGE_CONTENT;
+    } else {
+        warn!(
+            "The message content intent isn't enabled for the bot, \
+            messages naming the bot won't be answered, only direct mentions will"
+        );
+    }
 
     let config = config.to_owned();
     let framework = poise::Framework::builder()
@@ -35,13 +92,75 @@ pub async fn run(
             event_handler: |ctx, event, framework, _| {
                 Box::pin(event_handler(ctx, event, framework))
             },
+            // commands are ignored in guilds not in allowed_guilds
+            command_check: Some(|ctx| {
+                Box::pin(async move {
+                    Ok(ctx
+                        .guild_id()
+                        .is_none_or(|g| ctx.data().guild_allowlist.allows(g)))
+                })
+            }),
             commands: vec![
+                help(),
+                register(),
+                servers(),
                 ping(),
                 echo(),
                 random_number(),
+                mock(),
+                mock_message(),
+                test_response(),
+                reload_answers(),
+                feedback(),
                 status(),
+                channel_template_set(),
+                my_template(),
+                rename_queue(),
+                rename_queue_flush(),
+                rename_audit(),
+                diag(),
+                logs(),
+                config_show(),
+                guild_settings(),
+                events(),
+                schedule_add(),
+                schedule_once(),
+                schedule_list(),
+                schedule_cancel(),
+                stats(),
+                check_perms(),
+                invite(),
+                link_status(),
+                notify_me(),
+                notify_me_stop(),
+                notify_role(),
+                snooze(),
+                kick(),
+                ban(),
+                move_member(),
                 update_streaming_status(),
+                update_twitch_streaming_status(),
+                set_voice(),
+                twitch_refresh(),
+                twitch_prune(),
+                twitch_subs(),
+                twitch_reconcile_dryrun(),
+                subscribe_test(),
+                twitch_token_status(),
+                twitch_scopes(),
+                stream_info(),
+                guilds(),
+                broadcast(),
+                twitch_ratelimit(),
+                tuning_show(),
+                tuning_set(),
             ],
+            pre_command: |ctx| {
+                Box::pin(async move {
+                    let telemetry = ctx.data().twitch.read().await.telemetry.clone();
+                    telemetry.record_command(&ctx.command().qualified_name);
+                })
+            },
             on_error: |error| {
                 Box::pin(async move {
                     if let Err(e) = on_error(error).await {
@@ -53,8 +172,19 @@ pub async fn run(
         })
         .setup(move |ctx, _ready, framework| {
             Box::pin(async move {
-                poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                if config.register_commands_globally {
+                    poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                }
+                let telemetry = Arc::new(Telemetry::default());
                 let mut users: HashMap<UserId, User> = HashMap::new();
+                let snoozes: HashMap<UserId, SystemTime> =
+                    store.load(SNOOZES_KEY)?.unwrap_or_default();
+                let announce_messages: HashMap<UserId, MessageId> =
+                    store.load(ANNOUNCE_MESSAGES_KEY)?.unwrap_or_default();
+                let announce_threads: HashMap<UserId, ChannelId> =
+                    store.load(ANNOUNCE_THREADS_KEY)?.unwrap_or_default();
+                let user_templates: HashMap<UserId, String> =
+                    store.load(USER_TEMPLATES_KEY)?.unwrap_or_default();
                 for m in &config.twitch_watcher.channels {
                     users.insert(
                         UserId::from(m.discord_id),
@@ -63,31 +193,167 @@ pub async fn run(
                             discord_id: UserId::from(m.discord_id),
                             current_channel_id: None,
                             twitch_is_streaming: None,
+                            twitch_login: None,
                             has_been_part_of_voice_state_event: false,
                             last_twitch_is_streaming_update: None,
+                            announce_thread_id: announce_threads
+                                .get(&UserId::from(m.discord_id))
+                                .copied(),
+                            announce_message_id: announce_messages
+                                .get(&UserId::from(m.discord_id))
+                                .copied(),
+                            notify_role_id: m.notify_role_id.map(RoleId::from),
+                            snoozed_until: snoozes.get(&UserId::from(m.discord_id)).copied(),
+                            rename_override: m.rename_override.clone(),
+                            custom_template: user_templates
+                                .get(&UserId::from(m.discord_id))
+                                .cloned(),
+                            session_channels: HashSet::new(),
+                            twitch_category: None,
                         },
                     );
                 }
-                Ok(Data {
+                let twitch = Arc::new(RwLock::new(DiscordTwitchWatcher {
+                    channels: store.load(RENAMED_CHANNELS_KEY)?.unwrap_or_default(),
+                    users,
+                    users_by_channel: HashMap::new(),
+                    renamed_channel_name: config.twitch_watcher.renamed_channel_name.clone(),
+                    renamed_channel_names: config
+                        .twitch_watcher
+                        .renamed_channel_names
+                        .iter()
+                        .map(|(k, v)| (GuildId::from(*k), v.clone()))
+                        .collect(),
+                    rename_mode: config.twitch_watcher.rename_mode,
+                    rename_enabled: config.twitch_watcher.rename_enabled,
+                    voice_status: config.twitch_watcher.voice_status.clone(),
+                    channel_templates: store.load(CHANNEL_TEMPLATES_KEY)?.unwrap_or_default(),
+                    reapply_template: config.twitch_watcher.reapply_template,
+                    forget_departed_streamers: config.twitch_watcher.forget_departed_streamers,
+                    offline_channel_name: config.twitch_watcher.offline_channel_name.clone(),
+                    offline_channel_names: config
+                        .twitch_watcher
+                        .offline_channel_names
+                        .iter()
+                        .map(|(k, v)| (GuildId::from(*k), v.clone()))
+                        .collect(),
+                    offline_restore: config
+                        .twitch_watcher
+                        .offline_restore_seconds
+                        .map(Duration::from_secs),
+                    tuning: store.load(TUNING_KEY)?.unwrap_or_default(),
+                    schedules: store.load(SCHEDULES_KEY)?.unwrap_or_default(),
+                    enabled: config.twitch_watcher.enabled,
+                    servers: config
+                        .twitch_watcher
+                        .servers
+                        .iter()
+                        .map(|v| GuildId::from(*v))
+                        .collect(),
+                    notify_subscriptions: store.load(NOTIFY_SUBSCRIPTIONS_KEY)?.unwrap_or_default(),
+                    store,
+                    announce_channel_id: config
+                        .twitch_watcher
+                        .announce_channel_id
+                        .map(ChannelId::from),
+                    announce_style: config.twitch_watcher.announce_style,
+                    announce_enabled: config.twitch_watcher.announce_enabled,
+                    announce_templates: AnnounceTemplates::new(
+                        config.twitch_watcher.announce_templates.clone(),
+                    ),
+                    announce_refresh: config
+                        .twitch_watcher
+                        .announce_refresh_seconds
+                        .map(Duration::from_secs),
+                    quiet_hours: config
+                        .twitch_watcher
+                        .quiet_hours
+                        .as_ref()
+                        .map(QuietHours::new)
+                        .transpose()?,
+                    held_announces: HashMap::new(),
+                    edit_ended_announce: config.twitch_watcher.edit_ended_announce,
+                    announce_channel_problem: None,
+                    error_notifier: Arc::new(ErrorNotifier::new(
+                        config.error_channel_id.map(ChannelId::from),
+                    )),
+                    rename_queue: RenameQueue::default(),
+                    rename_cooldown: Duration::from_secs(
+                        config.twitch_watcher.rename_cooldown_seconds,
+                    ),
+                    voice_moves: HashMap::new(),
+                    min_stream_duration: Duration::from_secs(
+                        config.twitch_watcher.min_stream_duration_seconds,
+                    ),
+                    defer_rename: config.twitch_watcher.defer_rename,
+                    deferred_announces: HashMap::new(),
+                    last_deferred_announce_id: 0,
+                    unlinked_live: HashSet::new(),
+                    event_history: EventHistory::new(config.event_history_size),
+                    live_presence: match config.presence_mode {
+                        PresenceMode::Activity => None,
+                        PresenceMode::LiveCount => Some(Arc::new(Notify::new())),
+                    },
+                    telemetry: telemetry.clone(),
+                    notification_sinks: build_sinks(&config.twitch_watcher.notification_sinks)?,
+                    twitch_requests: twitch_requests.clone(),
+                    chat_relay: config
+                        .twitch_watcher
+                        .chat_relay
+                        .as_ref()
+                        .map(|c| ChatRelay::start(ctx.clone(), c)),
+                }));
+                apply_tuning(&mut *twitch.write().await);
+                tokio::spawn(run_scheduler(ctx.clone(), twitch.clone()));
+                if config
+                    .twitch_watcher
+                    .quiet_hours
+                    .as_ref()
+                    .is_some_and(|q| q.hold)
+                {
+                    tokio::spawn(run_held_announces(ctx.clone(), twitch.clone()));
+                }
+                // offline_restore can be enabled by /tuning_set
+                tokio::spawn(run_offline_restore(ctx.clone(), twitch.clone()));
+                tokio::spawn(stop_on_shutdown(
+                    ctx.clone(),
+                    twitch.clone(),
+                    sender.clone(),
+                    framework.shard_manager().clone(),
+                    stopping,
+                ));
+                let mut answers = Answers::new(&config, content_intent)?;
+                if let Some(answers_path) = &config.answers_path {
+                    answers = answers.with_pools(&AnswersConfig::load(answers_path)?)?;
+                }
+                let data = Data {
                     trusted_users_ids: Arc::new(config.trusted_users.clone()),
-                    twitch: Arc::new(RwLock::new(DiscordTwitchWatcher {
-                        channels: HashMap::new(),
-                        users,
-                        renamed_channel_name: config.twitch_watcher.renamed_channel_name.clone(),
-                        enabled: config.twitch_watcher.enabled,
-                        servers: config
-                            .twitch_watcher
-                            .servers
-                            .iter()
-                            .map(|v| GuildId::from(*v))
-                            .collect(),
-                    })),
+                    twitch,
                     receiver: Mutex::new(Some(receiver)),
                     sender: Mutex::new(sender),
-                    activity_messages: config.activity_messages.clone(),
-                    question_answers: Arc::new(config.question_answers.clone()),
-                    random_answers: Arc::new(config.random_answers.clone()),
-                })
+                    twitch_requests,
+                    activity_messages: RandomPool::new(&config.activity_messages)?,
+                    answers: RwLock::new(Arc::new(answers)),
+                    answers_path: config.answers_path(),
+                    twitch_health,
+                    ack_style: config.ack_style,
+                    feedback: Feedback::new(config.feedback_channel_id.map(ChannelId::from)),
+                    direct_messages: config.direct_messages.clone(),
+                    guild_allowlist: GuildAllowlist::new(
+                        &config.allowed_guilds,
+                        config.leave_disallowed_guilds,
+                    ),
+                    http_client: reqwest::Client::builder().timeout(HTTP_TIMEOUT).build()?,
+                };
+                if let Some(telemetry_config) = config.telemetry.clone() {
+                    tokio::spawn(run_telemetry(
+                        ctx.clone(),
+                        data.http_client.clone(),
+                        telemetry,
+                        telemetry_config,
+                    ));
+                }
+                Ok(data)
             })
         })
         .build();
@@ -101,44 +367,204 @@ pub async fn run(
     Ok(())
 }
 
+/// true if the application is allowed to receive the content of messages
+/// assumed to be allowed if it can't be checked, the gateway will reject the connection if not
+async fn has_message_content_intent(discord_token: &str) -> bool {
+    let http = serenity::Http::new(discord_token);
+    match http.get_current_application_info().await {
+        Ok(info) => info.flags.is_some_and(|flags| {
+            flags.intersects(
+                serenity::ApplicationFlags::GATEWAY_MESSAGE_CONTENT
+                    | serenity::ApplicationFlags::GATEWAY_MESSAGE_CONTENT_LIMITED,
+            )
+        }),
+        Err(why) => {
+            warn!("Can't check the intents of the application {}", why);
+            true
+        }
+    }
+}
+
+/// where an event comes from, to drop the ones of disallowed guilds
+enum EventScope {
+    /// not tied to a guild, like the gateway events and direct messages
+    Global,
+    Guild(GuildId),
+    /// events not listed in event_scope, dropped so that a newly handled event can't bypass
+    /// the allowlist
+    Unknown,
+}
+
+fn scope_of(guild_id: Option<GuildId>) -> EventScope {
+    match guild_id {
+        Some(guild_id) => EventScope::Guild(guild_id),
+        None => EventScope::Global,
+    }
+}
+
+fn event_scope(event: &serenity::FullEvent) -> EventScope {
+    use serenity::FullEvent as E;
+    match event {
+        // interactions are checked by the command check, a guild being created is checked by
+        // the allowlist itself, which leaves disallowed guilds
+        E::Ready { .. }
+        | E::CacheReady { .. }
+        | E::ShardsReady { .. }
+        | E::ShardStageUpdate { .. }
+        | E::Resume { .. }
+        | E::Ratelimit { .. }
+        | E::UserUpdate { .. }
+        | E::InteractionCreate { .. }
+        | E::GuildCreate { .. } => EventScope::Global,
+        E::Message { new_message } => scope_of(new_message.guild_id),
+        E::MessageUpdate { event, .. } => scope_of(event.guild_id),
+        E::MessageDelete { guild_id, .. } | E::MessageDeleteBulk { guild_id, .. } => {
+            scope_of(*guild_id)
+        }
+        E::ReactionAdd { add_reaction } => scope_of(add_reaction.guild_id),
+        E::ReactionRemove { removed_reaction }
+        | E::ReactionRemoveEmoji {
+            removed_reactions: removed_reaction,
+        } => scope_of(removed_reaction.guild_id),
+        E::TypingStart { event } => scope_of(event.guild_id),
+        E::PresenceUpdate { new_data } => scope_of(new_data.guild_id),
+        E::ChannelPinsUpdate { pin } => scope_of(pin.guild_id),
+        E::VoiceStateUpdate { new, .. } => scope_of(new.guild_id),
+        E::VoiceServerUpdate { event } => scope_of(event.guild_id),
+        E::ChannelCreate { channel } | E::ChannelDelete { channel, .. } => {
+            EventScope::Guild(channel.guild_id)
+        }
+        E::ChannelUpdate { new, .. } | E::ThreadUpdate { new, .. } => {
+            EventScope::Guild(new.guild_id)
+        }
+        E::CategoryCreate { category } | E::CategoryDelete { category } => {
+            EventScope::Guild(category.guild_id)
+        }
+        E::ThreadCreate { thread } => EventScope::Guild(thread.guild_id),
+        E::ThreadDelete { thread, .. } => EventScope::Guild(thread.guild_id),
+        E::ThreadListSync { thread_list_sync } => EventScope::Guild(thread_list_sync.guild_id),
+        E::ThreadMembersUpdate {
+            thread_members_update,
+        } => EventScope::Guild(thread_members_update.guild_id),
+        E::GuildDelete { incomplete, .. } => EventScope::Guild(incomplete.id),
+        E::GuildUpdate { new_data, .. } => EventScope::Guild(new_data.id),
+        E::GuildMemberAddition { new_member } => EventScope::Guild(new_member.guild_id),
+        E::GuildMemberUpdate { event, .. } => EventScope::Guild(event.guild_id),
+        E::GuildMembersChunk { chunk } => EventScope::Guild(chunk.guild_id),
+        E::GuildRoleCreate { new } | E::GuildRoleUpdate { new, .. } => {
+            EventScope::Guild(new.guild_id)
+        }
+        E::GuildScheduledEventCreate { event }
+        | E::GuildScheduledEventUpdate { event }
+        | E::GuildScheduledEventDelete { event } => EventScope::Guild(event.guild_id),
+        E::AutoModRuleCreate { rule }
+        | E::AutoModRuleUpdate { rule }
+        | E::AutoModRuleDelete { rule } => EventScope::Guild(rule.guild_id),
+        E::AutoModActionExecution { execution } => EventScope::Guild(execution.guild_id),
+        E::StageInstanceCreate { stage_instance }
+        | E::StageInstanceUpdate { stage_instance }
+        | E::StageInstanceDelete { stage_instance } => EventScope::Guild(stage_instance.guild_id),
+        E::InviteCreate { data } => scope_of(data.guild_id),
+        E::InviteDelete { data } => scope_of(data.guild_id),
+        E::GuildMemberRemoval { guild_id, .. }
+        | E::GuildBanAddition { guild_id, .. }
+        | E::GuildBanRemoval { guild_id, .. }
+        | E::GuildRoleDelete { guild_id, .. }
+        | E::GuildEmojisUpdate { guild_id, .. }
+        | E::GuildStickersUpdate { guild_id, .. }
+        | E::GuildIntegrationsUpdate { guild_id }
+        | E::GuildAuditLogEntryCreate { guild_id, .. }
+        | E::IntegrationDelete { guild_id, .. }
+        | E::VoiceChannelStatusUpdate { guild_id, .. }
+        | E::WebhookUpdate { guild_id, .. } => EventScope::Guild(*guild_id),
+        _ => EventScope::Unknown,
+    }
+}
+
 async fn event_handler(
     ctx: &serenity::Context,
     event: &serenity::FullEvent,
     framework: poise::FrameworkContext<'_, Data, Error>,
 ) -> Result<(), Error> {
+    match event_scope(event) {
+        EventScope::Global => {}
+        EventScope::Guild(guild_id) => {
+            if !framework.user_data.guild_allowlist.allows(guild_id) {
+                trace!("Ignoring event of disallowed guild {}", guild_id);
+                return Ok(());
+            }
+        }
+        EventScope::Unknown => {
+            trace!(
+                "Ignoring {} event, its guild isn't known",
+                event.snake_case_name()
+            );
+            return Ok(());
+        }
+    }
     match event {
         serenity::FullEvent::Ready { data_about_bot, .. } => {
             info!("Logged in as {}", data_about_bot.user.name);
 
-            ctx.set_activity(
-                framework
-                    .user_data
-                    .activity_messages
-                    .choose(&mut rand::thread_rng())
-                    .map(|m| Some(ActivityData::custom(m)))
-                    .unwrap_or(None),
-            );
+            for server in &framework.user_data.twitch.read().await.servers {
+                if !data_about_bot.guilds.iter().any(|g| g.id == *server) {
+                    warn!(
+                        "Configured server {} isn't a guild the bot is in, its channels won't be renamed",
+                        server
+                    );
+                }
+            }
+
+            let live_presence = framework
+                .user_data
+                .twitch
+                .read()
+                .await
+                .live_presence
+                .clone();
+            if live_presence.is_none() {
+                ctx.set_activity(Some(ActivityData::custom(
+                    framework.user_data.activity_messages.choose(),
+                )));
+            }
 
             if framework.user_data.twitch.read().await.enabled {
-                let receiver = framework.user_data.receiver.lock().await.take();
-                let twitch = framework.user_data.twitch.clone();
-                let ctx = ctx.clone();
-                tokio::spawn(async move {
-                    twitch_event_handler(&ctx, &mut receiver.unwrap(), twitch)
-                        .await
-                        .unwrap();
-                });
+                // Ready is sent again on reconnection, the handlers are already running then
+                if let Some(receiver) = framework.user_data.receiver.lock().await.take() {
+                    let twitch = framework.user_data.twitch.clone();
+                    tokio::spawn(run_rename_queue(ctx.clone(), twitch.clone()));
+                    if let Some(live_presence) = live_presence {
+                        tokio::spawn(run_live_presence(
+                            ctx.clone(),
+                            twitch.clone(),
+                            live_presence,
+                        ));
+                    }
+                    tokio::spawn(supervise_twitch_event_handler(
+                        ctx.clone(),
+                        receiver,
+                        twitch,
+                    ));
+                }
             }
         }
+        serenity::FullEvent::CacheReady { .. } => {
+            validate_announce_channel(ctx, framework.user_data.twitch.clone()).await;
+        }
         serenity::FullEvent::Message { new_message } => {
             if new_message.author.bot {
                 trace!("Skipping message sent by bot {}", new_message.author.name);
+            } else if new_message.guild_id.is_none() {
+                trace!("Received direct message {:?}", new_message);
+                if let Err(why) = handle_direct_message(ctx, framework.user_data, new_message).await
+                {
+                    error!("Error on handling direct message {}", why);
+                }
             } else {
                 trace!("Received message {:?}", new_message);
                 if let Err(why) = handle_message(
                     ctx,
-                    framework.user_data.question_answers.clone(),
-                    framework.user_data.random_answers.clone(),
+                    framework.user_data.answers.read().await.clone(),
                     new_message,
                 )
                 .await
@@ -152,52 +578,29 @@ async fn event_handler(
             let mut is_known_user = false;
             {
                 let mut data = framework.user_data.twitch.write().await;
-                match data.users.get_mut(&new.user_id) {
-                    Some(user) => {
-                        is_known_user = true;
-                        user.has_been_part_of_voice_state_event = true;
-                        user.current_channel_id = new.channel_id;
-                    }
-                    None => trace!("User {} isn't monitored", new.user_id),
+                if data.set_user_voice_channel(&new.user_id, new.channel_id) {
+                    is_known_user = true;
+                } else {
+                    trace!("User {} isn't monitored", new.user_id);
                 }
             }
             if is_known_user {
-                let twitch = framework.user_data.twitch.clone();
-                let is_streaming: Option<bool> = twitch
-                    .read()
-                    .await
-                    .users
-                    .get(&new.user_id)
-                    .map_or_else(|| None, |v| v.twitch_is_streaming);
-                if let Some(is_streaming) = is_streaming {
-                    if is_streaming {
-                        if let Some(old) = old {
-                            if let Some(channel_id) = old.channel_id {
-                                // We want to rename the old channel back
-                                rename_channel(
-                                    ctx,
-                                    twitch.clone(),
-                                    &old.user_id,
-                                    &channel_id,
-                                    false,
-                                )
-                                .await?;
-                            }
-                        }
-                        if let Some(channel_id) = new.channel_id {
-                            rename_channel(
-                                ctx,
-                                twitch.clone(),
-                                &new.user_id,
-                                &channel_id,
-                                is_streaming,
-                            )
-                            .await?;
-                        }
-                    }
-                }
+                handle_streamer_move(ctx, framework.user_data.twitch.clone(), new.user_id).await?;
             }
         }
+        serenity::FullEvent::Resume { .. } => {
+            info!("Discord gateway session resumed, reconciling voice states");
+            reconcile_voice_states(ctx, framework.user_data.twitch.clone()).await;
+        }
+        serenity::FullEvent::GuildCreate { guild, .. } => {
+            framework.user_data.guild_allowlist.check(ctx, guild).await;
+        }
+        serenity::FullEvent::GuildMemberRemoval { guild_id, user, .. } => {
+            trace!("User {} left guild {}", user.id, guild_id);
+            remove_subscriber(framework.user_data.twitch.clone(), *guild_id, &user.id).await;
+            handle_streamer_departure(ctx, framework.user_data.twitch.clone(), *guild_id, user.id)
+                .await?;
+        }
         _ => {}
     }
     Ok(())
@@ -0,0 +1,92 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{
+    ChannelId, CreateAllowedMentions, CreateForumPost, CreateMessage, Mentionable,
+};
+use tracing::{info, warn};
+
+use crate::config::AnnounceStyle;
+use crate::discord::random_stuff::{is_trusted, split_message};
+use crate::discord::{DiscordContext, Error};
+
+// title of the thread created for a broadcast when announces are forum threads
+const BROADCAST_THREAD_TITLE: &str = "Announcement";
+
+/// post a message from the operators in the announce channels, without mentions
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn broadcast(
+    ctx: DiscordContext<'_>,
+    #[description = "Message to post"] message: String,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let (channel_ids, announce_style): (Vec<ChannelId>, AnnounceStyle) = {
+        let reader = ctx.data().twitch.read().await;
+        (
+            reader.announce_channel_id.into_iter().collect(),
+            reader.announce_style,
+        )
+    };
+    if channel_ids.is_empty() {
+        ctx.say("No announce channel configured").await?;
+        return Ok(());
+    }
+
+    let lines: Vec<String> = message.lines().map(String::from).collect();
+    let chunks = split_message(&lines);
+    let mut failures: Vec<String> = vec![];
+    for channel_id in &channel_ids {
+        match post_broadcast(ctx.serenity_context(), *channel_id, announce_style, &chunks).await {
+            Ok(()) => info!("Broadcast posted in {}", channel_id),
+            Err(why) => {
+                warn!("Error on posting broadcast in {} {}", channel_id, why);
+                failures.push(format!("{} : {}", channel_id.mention(), why));
+            }
+        }
+    }
+
+    let mut lines = vec![format!(
+        "Broadcast posted in {}/{} channels",
+        channel_ids.len() - failures.len(),
+        channel_ids.len()
+    )];
+    lines.extend(failures);
+    for text in split_message(&lines) {
+        ctx.say(text).await?;
+    }
+    Ok(())
+}
+
+async fn post_broadcast(
+    ctx: &serenity::Context,
+    channel_id: ChannelId,
+    announce_style: AnnounceStyle,
+    chunks: &[String],
+) -> anyhow::Result<()> {
+    let message = |content: &str| {
+        CreateMessage::new()
+            .content(content)
+            .allowed_mentions(CreateAllowedMentions::new())
+    };
+    let Some((first, rest)) = chunks.split_first() else {
+        return Ok(());
+    };
+    // forums only accept posts
+    let target = match announce_style {
+        AnnounceStyle::ForumThread => {
+            channel_id
+                .create_forum_post(
+                    &ctx.http,
+                    CreateForumPost::new(BROADCAST_THREAD_TITLE, message(first)),
+                )
+                .await?
+                .id
+        }
+        AnnounceStyle::Message | AnnounceStyle::Embed => {
+            channel_id.send_message(&ctx.http, message(first)).await?;
+            channel_id
+        }
+    };
+    for chunk in rest {
+        target.send_message(&ctx.http, message(chunk)).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use crate::discord::{random_stuff::is_trusted, DiscordContext, Error};
+
+/// show the available commands, or the help of a command
+#[poise::command(slash_command)]
+pub async fn help(
+    ctx: DiscordContext<'_>,
+    #[description = "Command to show the help of"]
+    #[autocomplete = "poise::builtins::autocomplete_command"]
+    command: Option<String>,
+) -> Result<(), Error> {
+    if command.is_some() {
+        let config = poise::builtins::HelpConfiguration {
+            ephemeral: true,
+            ..Default::default()
+        };
+        poise::builtins::help(ctx, command.as_deref(), config).await?;
+        return Ok(());
+    }
+
+    // the builtin listing ignores checks, commands with one being gated behind is_trusted
+    let is_trusted = ctx
+        .data()
+        .trusted_users_ids
+        .contains(&ctx.author().id.get());
+    let text = ctx
+        .framework()
+        .options()
+        .commands
+        .iter()
+        .filter(|c| !c.hide_in_help && c.slash_action.is_some())
+        .filter(|c| is_trusted || c.checks.is_empty())
+        .map(|c| match &c.description {
+            Some(description) => format!("/{} : {}", c.name, description),
+            None => format!("/{}", c.name),
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    ctx.send(poise::CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// register or unregister the commands globally or in this guild
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn register(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    poise::builtins::register_application_commands_buttons(ctx).await?;
+    Ok(())
+}
+
+/// list the guilds the bot is in
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn servers(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    poise::builtins::servers(ctx).await?;
+    Ok(())
+}
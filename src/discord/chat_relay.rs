@@ -0,0 +1,140 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, CreateAllowedMentions, CreateMessage, MessageBuilder};
+use tokio::sync::mpsc::error::{TryRecvError, TrySendError};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, error, trace, warn};
+
+use crate::config::ChatRelayConfig;
+use crate::discord::random_stuff::split_message;
+use crate::inter_comm::ChatMessage;
+
+// longer chat messages are cut
+const LINE_MAX_LENGTH: usize = 400;
+
+/// posts the twitch chat messages in the relay channel, grouped in batches so that a busy chat
+/// doesn't hit the Discord rate limit
+#[derive(Debug)]
+pub struct ChatRelay {
+    lines: Sender<String>,
+    // messages dropped since the last batch because the relay was full
+    dropped: Arc<AtomicUsize>,
+}
+
+impl ChatRelay {
+    pub fn start(ctx: serenity::Context, config: &ChatRelayConfig) -> ChatRelay {
+        let max_batch = config.max_batch.max(1);
+        let (lines, receiver) = mpsc::channel(max_batch);
+        let dropped = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(run_chat_relay(
+            ctx,
+            ChannelId::from(config.channel_id),
+            receiver,
+            dropped.clone(),
+            Duration::from_secs(config.batch_seconds.max(1)),
+        ));
+        ChatRelay { lines, dropped }
+    }
+
+    /// queue a message for the next batch, it is dropped if the batch is full
+    pub fn relay(&self, streamer_user_login: &str, message: &ChatMessage) {
+        match self
+            .lines
+            .try_send(format_line(streamer_user_login, message))
+        {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                trace!(
+                    "Chat relay is full, dropping a message of {}",
+                    streamer_user_login
+                );
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Closed(_)) => warn!("Chat relay is stopped"),
+        }
+    }
+}
+
+/// post the queued lines every batch_interval
+async fn run_chat_relay(
+    ctx: serenity::Context,
+    channel_id: ChannelId,
+    mut lines: Receiver<String>,
+    dropped: Arc<AtomicUsize>,
+    batch_interval: Duration,
+) {
+    let mut ticker = interval(batch_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let mut batch = vec![];
+        let mut closed = false;
+        loop {
+            match lines.try_recv() {
+                Ok(line) => batch.push(line),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    closed = true;
+                    break;
+                }
+            }
+        }
+        let dropped = dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            batch.push(format!("*{} messages not relayed*", dropped));
+        }
+        if !batch.is_empty() {
+            debug!("Relaying {} chat lines", batch.len());
+        }
+        for content in split_message(&batch) {
+            if let Err(why) = channel_id
+                .send_message(
+                    &ctx.http,
+                    CreateMessage::new()
+                        .content(content)
+                        .allowed_mentions(CreateAllowedMentions::new()),
+                )
+                .await
+            {
+                error!("Error on relaying chat messages {}", why);
+            }
+        }
+        if closed {
+            debug!("Chat relay stopped");
+            return;
+        }
+    }
+}
+
+fn format_line(streamer_user_login: &str, message: &ChatMessage) -> String {
+    let badges: String = message
+        .badges
+        .iter()
+        .filter_map(|b| badge_emoji(b))
+        .collect();
+    let mut text = message.text.clone();
+    if text.chars().count() > LINE_MAX_LENGTH {
+        text = text.chars().take(LINE_MAX_LENGTH).collect::<String>() + "…";
+    }
+    MessageBuilder::new()
+        .push(format!("`{}` ", streamer_user_login))
+        .push(badges)
+        .push_bold_safe(&message.author)
+        .push(" : ")
+        .push_safe(text)
+        .build()
+}
+
+fn badge_emoji(set_id: &str) -> Option<&'static str> {
+    match set_id {
+        "broadcaster" => Some("🎥"),
+        "moderator" => Some("🛡️"),
+        "vip" => Some("💎"),
+        "subscriber" => Some("⭐"),
+        _ => None,
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, CreateEmbed, Mentionable, UserId};
+
+use crate::discord::{random_stuff::is_trusted, DiscordContext, Error};
+
+// twitch purple, like announces
+const EMBED_COLOUR: u32 = 0x9146FF;
+// shown in place of values that must not leak in Discord
+const REDACTED: &str = "*redacted*";
+
+fn channel_or_none(channel_id: Option<ChannelId>) -> String {
+    channel_id.map_or(String::from("None"), |c| c.mention().to_string())
+}
+
+/// show the configuration the bot is running with, secrets are redacted
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn config_show(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let data = ctx.data();
+    let answers = data.answers.read().await.clone();
+    let mut embed = CreateEmbed::new()
+        .title("Configuration")
+        .colour(EMBED_COLOUR)
+        .description(format!(
+            "Fields marked {} are secrets which aren't shown",
+            REDACTED
+        ));
+    {
+        let reader = data.twitch.read().await;
+        let sinks = reader
+            .notification_sinks
+            .iter()
+            .map(|s| format!("{} ({})", s.name(), REDACTED))
+            .collect::<Vec<String>>();
+        embed = embed
+            .field("Twitch watcher", reader.enabled.to_string(), true)
+            .field("Watched streamers", reader.users.len().to_string(), true)
+            .field("Servers", reader.servers.len().to_string(), true)
+            .field(
+                "Renamed channel name",
+                reader.renamed_channel_name.clone(),
+                true,
+            )
+            .field("Renames", reader.rename_enabled.to_string(), true)
+            .field("Rename mode", format!("{:?}", reader.rename_mode), true)
+            .field(
+                "Channel templates",
+                reader.channel_templates.len().to_string(),
+                true,
+            )
+            .field(
+                "Rename cooldown",
+                format!("{}s", reader.rename_cooldown.as_secs()),
+                true,
+            )
+            .field("Announces", reader.announce_enabled.to_string(), true)
+            .field(
+                "Announce channel",
+                channel_or_none(reader.announce_channel_id),
+                true,
+            )
+            .field(
+                "Announce style",
+                format!("{:?}", reader.announce_style),
+                true,
+            )
+            .field(
+                "Notification sinks",
+                match sinks.is_empty() {
+                    true => String::from("None"),
+                    false => sinks.join(", "),
+                },
+                true,
+            );
+    }
+    let pools = answers
+        .pool_sizes()
+        .into_iter()
+        .map(|(name, size)| format!("{} : {}", name, size))
+        .collect::<Vec<String>>()
+        .join("\n");
+    embed = embed
+        .field(
+            "Trusted users",
+            data.trusted_users_ids.len().to_string(),
+            true,
+        )
+        .field(
+            "Auto-responder",
+            format!(
+                "{} ({} server overrides)",
+                answers.enabled_by_default(),
+                answers.guild_override_count()
+            ),
+            true,
+        )
+        .field(
+            "Message content intent",
+            answers.has_content_intent().to_string(),
+            true,
+        )
+        .field("Answer pools", pools, false)
+        .field("Ack style", format!("{:?}", data.ack_style), true)
+        .field(
+            "Feedback channel",
+            channel_or_none(data.feedback.channel_id()),
+            true,
+        )
+        .field(
+            "Direct messages",
+            format!(
+                "enabled : {}, fixed reply : {}, forwarded to : {}",
+                data.direct_messages.enabled,
+                data.direct_messages.reply.is_some(),
+                channel_or_none(data.direct_messages.forward_channel_id.map(ChannelId::from))
+            ),
+            false,
+        )
+        .field("Discord token", REDACTED, true)
+        .field("Twitch credentials", REDACTED, true);
+    ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// show the twitch watcher settings that apply to this server only
+#[poise::command(slash_command, guild_only, check = "is_trusted")]
+pub async fn guild_settings(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let (guild_name, channel_ids, member_ids) = {
+        let guild = ctx.guild().ok_or("Guild isn't in cache")?;
+        (
+            guild.name.clone(),
+            guild
+                .channels
+                .keys()
+                .copied()
+                .collect::<HashSet<ChannelId>>(),
+            guild.members.keys().copied().collect::<HashSet<UserId>>(),
+        )
+    };
+    let embed = {
+        let reader = ctx.data().twitch.read().await;
+        let is_watched = reader.servers.contains(&guild_id);
+        let templates = reader
+            .channel_templates
+            .iter()
+            .filter(|(channel_id, _)| channel_ids.contains(channel_id))
+            .map(|(channel_id, template)| format!("{} : {}", channel_id.mention(), template))
+            .collect::<Vec<String>>();
+        // the announce channel of another server isn't shown
+        let announce_channel = match reader.announce_channel_id {
+            Some(channel_id) if channel_ids.contains(&channel_id) => {
+                channel_id.mention().to_string()
+            }
+            Some(_) => String::from("In another server"),
+            None => String::from("None"),
+        };
+        let members = reader
+            .users
+            .keys()
+            .filter(|user_id| member_ids.contains(user_id))
+            .count();
+        let in_voice = reader
+            .users
+            .values()
+            .filter(|user| {
+                user.current_channel_id
+                    .is_some_and(|channel_id| channel_ids.contains(&channel_id))
+            })
+            .count();
+        CreateEmbed::new()
+            .title(format!("Twitch watcher in {}", guild_name))
+            .colour(EMBED_COLOUR)
+            .field("Watched", (reader.enabled && is_watched).to_string(), true)
+            .field("Renames", reader.rename_enabled.to_string(), true)
+            .field("Announces", reader.announce_enabled.to_string(), true)
+            .field(
+                "Renamed channel name",
+                reader.renamed_name_of_guild(guild_id),
+                false,
+            )
+            .field(
+                "Offline channel name",
+                reader.offline_name_for(guild_id).unwrap_or("None"),
+                false,
+            )
+            .field(
+                "Channel templates",
+                match templates.is_empty() {
+                    true => String::from("None"),
+                    false => templates.join("\n"),
+                },
+                false,
+            )
+            .field("Announce channel", announce_channel, true)
+            .field(
+                "Monitored users",
+                format!("{} members, {} in voice", members, in_voice),
+                true,
+            )
+    };
+    ctx.send(poise::CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::Mentionable;
+
+use crate::discord::twitch::{format_duration, format_rate_limit, request_twitch};
+use crate::discord::{random_stuff::is_trusted, DiscordContext, Error};
+use crate::inter_comm::TwitchRequest;
+
+/// show the state of the connections used by the bot
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn diag(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let twitch_health = &ctx.data().twitch_health;
+    let missing_servers = {
+        let guilds = ctx.cache().guilds();
+        let missing = ctx
+            .data()
+            .twitch
+            .read()
+            .await
+            .servers
+            .iter()
+            .filter(|s| !guilds.contains(s))
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+        match missing.is_empty() {
+            true => String::from("None"),
+            false => missing.join(", "),
+        }
+    };
+    let announce_channel = {
+        let reader = ctx.data().twitch.read().await;
+        match (reader.announce_channel_id, &reader.announce_channel_problem) {
+            (None, _) => String::from("None"),
+            (Some(channel_id), None) => channel_id.mention().to_string(),
+            (Some(channel_id), Some(problem)) => {
+                format!("{} is invalid, {}", channel_id.mention(), problem)
+            }
+        }
+    };
+    let token_expiry = match ctx.data().twitch.read().await.enabled {
+        true => {
+            match request_twitch(ctx.data(), |reply| TwitchRequest::TokenExpiry { reply }).await {
+                Ok(expires_in) => format!("expires in {}", format_duration(expires_in)),
+                Err(why) => format!("unknown, {}", why),
+            }
+        }
+        false => String::from("Twitch is disabled"),
+    };
+    let rate_limit = match ctx.data().twitch.read().await.enabled {
+        true => {
+            match request_twitch(ctx.data(), |reply| TwitchRequest::RateLimit { reply }).await {
+                Ok(rate_limit) => format_rate_limit(rate_limit),
+                Err(why) => format!("unknown, {}", why),
+            }
+        }
+        false => String::from("Twitch is disabled"),
+    };
+    let text = format!(
+        "Twitch API : {}\n\
+        Twitch token : {}\n\
+        Twitch rate limit : {}\n\
+        Last Twitch message : {}s ago\n\
+        Configured servers the bot isn't in : {}\n\
+        Announce channel : {}",
+        twitch_health.api_breaker.state(),
+        token_expiry,
+        rate_limit,
+        twitch_health.time_since_message().as_secs(),
+        missing_servers,
+        announce_channel
+    );
+    ctx.say(text).await?;
+    Ok(())
+}
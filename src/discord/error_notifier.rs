@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, CreateMessage};
+use tracing::{error, trace};
+
+// an error of the same kind is posted at most once per window
+const NOTIFICATION_WINDOW: Duration = Duration::from_secs(600);
+
+#[derive(Debug)]
+struct Throttle {
+    last_sent: Instant,
+    // count of errors not posted since last_sent
+    suppressed: u32,
+}
+
+/// post significant errors in the operator channel
+/// repeated errors of the same kind are collapsed into a single notification
+#[derive(Debug)]
+pub struct ErrorNotifier {
+    error_channel_id: Option<ChannelId>,
+    throttles: Mutex<HashMap<String, Throttle>>,
+}
+
+impl ErrorNotifier {
+    pub fn new(error_channel_id: Option<ChannelId>) -> ErrorNotifier {
+        ErrorNotifier {
+            error_channel_id,
+            throttles: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// post message unless an error of the same kind has been posted recently
+    pub async fn notify(&self, http: &serenity::Http, kind: &str, message: &str) {
+        let Some(error_channel_id) = self.error_channel_id else {
+            return;
+        };
+
+        let suppressed = {
+            let mut throttles = self.throttles.lock().unwrap();
+            match throttles.get_mut(kind) {
+                Some(throttle) if throttle.last_sent.elapsed() < NOTIFICATION_WINDOW => {
+                    throttle.suppressed += 1;
+                    trace!("Error notification {} throttled", kind);
+                    return;
+                }
+                Some(throttle) => {
+                    let suppressed = throttle.suppressed;
+                    throttle.last_sent = Instant::now();
+                    throttle.suppressed = 0;
+                    suppressed
+                }
+                None => {
+                    throttles.insert(
+                        kind.to_owned(),
+                        Throttle {
+                            last_sent: Instant::now(),
+                            suppressed: 0,
+                        },
+                    );
+                    0
+                }
+            }
+        };
+
+        let text = match suppressed {
+            0 => format!("⚠️ {}", message),
+            n => format!(
+                "⚠️ {} (occurred {} times since last notification)",
+                message,
+                n + 1
+            ),
+        };
+        if let Err(why) = error_channel_id
+            .send_message(http, CreateMessage::new().content(text))
+            .await
+        {
+            error!("Error on posting error notification {}", why);
+        }
+    }
+}
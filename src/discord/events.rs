@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+use crate::discord::random_stuff::{is_trusted, MESSAGE_MAX_LENGTH};
+use crate::discord::{DiscordContext, Error};
+use crate::inter_comm::{InterComm, MessageType};
+
+/// last events received from the twitch side, oldest first
+#[derive(Debug)]
+pub struct EventHistory {
+    size: usize,
+    events: VecDeque<(SystemTime, String)>,
+}
+
+impl EventHistory {
+    pub fn new(size: usize) -> EventHistory {
+        EventHistory {
+            size,
+            events: VecDeque::with_capacity(size),
+        }
+    }
+
+    pub fn record(&mut self, item: &InterComm) {
+        if self.size == 0 {
+            return;
+        }
+        if self.events.len() == self.size {
+            self.events.pop_front();
+        }
+        let item = match item {
+            InterComm::Streamer(item) => item,
+            InterComm::Error { message, .. } => {
+                self.events
+                    .push_back((SystemTime::now(), format!("error : {}", message)));
+                return;
+            }
+        };
+        let description = match &item.message_type {
+            MessageType::TwitchStreamOnline {
+                is_backlog,
+                category,
+            } => format!(
+                "{} ({}) online{}{}",
+                item.streamer_user_login,
+                item.streamer_user_id,
+                category
+                    .as_ref()
+                    .map(|c| format!(" in {}", c))
+                    .unwrap_or_default(),
+                if *is_backlog { " (already live)" } else { "" }
+            ),
+            MessageType::TwitchStreamOffline => format!(
+                "{} ({}) offline",
+                item.streamer_user_login, item.streamer_user_id
+            ),
+            MessageType::TwitchCategoryUpdate { category } => format!(
+                "{} ({}) category {}",
+                item.streamer_user_login, item.streamer_user_id, category
+            ),
+            MessageType::ChatMessage(message) => format!(
+                "chat message of {} in {}",
+                message.author, item.streamer_user_login
+            ),
+        };
+        self.events.push_back((SystemTime::now(), description));
+    }
+}
+
+/// show the last events received from the twitch side
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn events(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let text = {
+        let reader = ctx.data().twitch.read().await;
+        let mut lines = reader
+            .event_history
+            .events
+            .iter()
+            .map(|(time, description)| {
+                let time = time
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                format!("<t:{}:T> {}", time, description)
+            })
+            .collect::<Vec<String>>();
+        // keep the most recent events that fit in a message
+        while lines.iter().map(|l| l.len() + 1).sum::<usize>() > MESSAGE_MAX_LENGTH {
+            lines.remove(0);
+        }
+        match lines.is_empty() {
+            true => String::from("No event received"),
+            false => lines.join("\n"),
+        }
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
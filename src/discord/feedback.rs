@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, CreateAllowedMentions, CreateMessage, Mentionable, UserId};
+use tracing::{error, info};
+
+use crate::discord::{DiscordContext, Error};
+
+// time a user has to wait between two feedbacks
+const FEEDBACK_COOLDOWN: Duration = Duration::from_secs(300);
+// maximum length of a feedback
+const FEEDBACK_MAX_LENGTH: usize = 1500;
+
+/// where feedbacks are forwarded, and when each user last sent one
+#[derive(Debug)]
+pub struct Feedback {
+    channel_id: Option<ChannelId>,
+    last_sent: Mutex<HashMap<UserId, Instant>>,
+}
+
+impl Feedback {
+    pub fn new(channel_id: Option<ChannelId>) -> Feedback {
+        Feedback {
+            channel_id,
+            last_sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// channel where feedbacks are forwarded, /feedback is disabled if None
+    pub fn channel_id(&self) -> Option<ChannelId> {
+        self.channel_id
+    }
+
+    /// returns the time left before the user can send a feedback, recording the feedback if none
+    fn try_send(&self, user_id: UserId) -> Option<Duration> {
+        let mut last_sent = self.last_sent.lock().unwrap();
+        let now = Instant::now();
+        if let Some(last) = last_sent.get(&user_id) {
+            let elapsed = now - *last;
+            if elapsed < FEEDBACK_COOLDOWN {
+                return Some(FEEDBACK_COOLDOWN - elapsed);
+            }
+        }
+        last_sent.insert(user_id, now);
+        None
+    }
+}
+
+/// send feedback or a bug report to the bot operators
+#[poise::command(slash_command)]
+pub async fn feedback(
+    ctx: DiscordContext<'_>,
+    #[description = "Your feedback"] text: String,
+) -> Result<(), Error> {
+    let feedback = &ctx.data().feedback;
+    let reply = if let Some(channel_id) = feedback.channel_id {
+        if text.trim().is_empty() {
+            String::from("Feedback is empty")
+        } else if text.chars().count() > FEEDBACK_MAX_LENGTH {
+            format!(
+                "Feedback is too long (max {} characters)",
+                FEEDBACK_MAX_LENGTH
+            )
+        } else if let Some(wait) = feedback.try_send(ctx.author().id) {
+            format!(
+                "You already sent a feedback recently, try again in {}s",
+                wait.as_secs()
+            )
+        } else {
+            let origin = match ctx.guild_id() {
+                Some(guild_id) => format!("guild {}", guild_id),
+                None => String::from("direct message"),
+            };
+            let content = format!(
+                "Feedback from {} ({}) in {} :\n{}",
+                ctx.author().mention(),
+                ctx.author().name,
+                origin,
+                text
+            );
+            let message = CreateMessage::new()
+                .content(content)
+                .allowed_mentions(CreateAllowedMentions::new());
+            match channel_id.send_message(ctx.http(), message).await {
+                Ok(_) => {
+                    info!("Forwarded feedback of {}", ctx.author().id);
+                    String::from("Thanks, your feedback has been sent")
+                }
+                Err(why) => {
+                    error!("Error on forwarding feedback {}", why);
+                    String::from("Your feedback couldn't be sent, try again later")
+                }
+            }
+        }
+    } else {
+        String::from("Feedback isn't enabled")
+    };
+    ctx.send(poise::CreateReply::default().content(reply).ephemeral(true))
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,229 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use poise::serenity_prelude as serenity;
+use serde_json::{json, Value};
+use serenity::all::{
+    ChannelId, GatewayIntents, GuildId, HttpBuilder, Shard, ShardId, ShardInfo, ShardManager,
+    ShardManagerOptions, ShardMessenger, ShardRunner, ShardRunnerOptions, UserId,
+};
+use serenity::prelude::TypeMap;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Mutex, RwLock};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+use crate::discord::error_notifier::ErrorNotifier;
+use crate::discord::events::EventHistory;
+use crate::discord::{Channel, ChannelNaming, DiscordTwitchWatcher, User};
+use crate::store::memory::MemoryStore;
+use crate::templates::AnnounceTemplates;
+
+pub const GUILD_ID: GuildId = GuildId::new(1000);
+
+/// watcher of GUILD_ID without any user, storing in memory
+pub fn watcher() -> DiscordTwitchWatcher {
+    let (twitch_requests, _) = mpsc::channel(1);
+    DiscordTwitchWatcher {
+        channels: HashMap::new(),
+        users: HashMap::new(),
+        users_by_channel: HashMap::new(),
+        renamed_channel_name: String::from("🔴 En live"),
+        renamed_channel_names: HashMap::new(),
+        rename_mode: Default::default(),
+        rename_enabled: true,
+        voice_status: String::new(),
+        channel_templates: HashMap::new(),
+        reapply_template: false,
+        forget_departed_streamers: false,
+        offline_channel_name: None,
+        offline_channel_names: HashMap::new(),
+        offline_restore: None,
+        tuning: HashMap::new(),
+        enabled: true,
+        servers: vec![GUILD_ID],
+        store: Arc::new(MemoryStore::default()),
+        announce_channel_id: None,
+        announce_style: Default::default(),
+        announce_enabled: true,
+        announce_templates: AnnounceTemplates::new(HashMap::new()),
+        announce_refresh: None,
+        edit_ended_announce: false,
+        quiet_hours: None,
+        held_announces: HashMap::new(),
+        announce_channel_problem: None,
+        error_notifier: Arc::new(ErrorNotifier::new(None)),
+        notify_subscriptions: HashMap::new(),
+        rename_queue: Default::default(),
+        rename_cooldown: Duration::ZERO,
+        voice_moves: HashMap::new(),
+        min_stream_duration: Duration::ZERO,
+        defer_rename: false,
+        deferred_announces: HashMap::new(),
+        last_deferred_announce_id: 0,
+        unlinked_live: HashSet::new(),
+        event_history: EventHistory::new(10),
+        live_presence: None,
+        telemetry: Default::default(),
+        schedules: vec![],
+        notification_sinks: vec![],
+        twitch_requests,
+        chat_relay: None,
+    }
+}
+
+/// monitored user whose stream status isn't known yet
+pub fn user(discord_id: u64, twitch_id: u64) -> User {
+    User {
+        discord_id: UserId::new(discord_id),
+        current_channel_id: None,
+        has_been_part_of_voice_state_event: false,
+        twitch_id,
+        twitch_is_streaming: None,
+        twitch_login: None,
+        last_twitch_is_streaming_update: None,
+        announce_thread_id: None,
+        announce_message_id: None,
+        notify_role_id: None,
+        snoozed_until: None,
+        rename_override: None,
+        custom_template: None,
+        session_channels: HashSet::new(),
+        twitch_category: None,
+    }
+}
+
+/// channel of GUILD_ID renamed for streamer while live
+pub fn renamed_channel(original_name: &str, streamer: UserId) -> Channel {
+    Channel {
+        original_name: original_name.to_owned(),
+        naming: ChannelNaming::Live,
+        guild_id: Some(GUILD_ID),
+        streamer: Some(streamer),
+        applied_name: None,
+    }
+}
+
+pub fn channel_id(id: u64) -> ChannelId {
+    ChannelId::new(id)
+}
+
+/// context whose requests to Discord go to server, with an empty cache
+/// the gateway is a local websocket which is never read
+pub async fn context(server: &MockServer) -> serenity::Context {
+    let http = Arc::new(
+        HttpBuilder::new("token")
+            .proxy(server.uri())
+            .ratelimiter_disabled(true)
+            .build(),
+    );
+    let cache = Arc::new(serenity::Cache::new());
+    let data = Arc::new(RwLock::new(TypeMap::new()));
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+        while socket.next().await.is_some() {}
+    });
+    let ws_url = Arc::new(Mutex::new(ws_url));
+    let shard = Shard::new(
+        ws_url.clone(),
+        "token",
+        ShardInfo {
+            id: ShardId(0),
+            total: 1,
+        },
+        GatewayIntents::empty(),
+        None,
+    )
+    .await
+    .unwrap();
+    let (manager, _) = ShardManager::new(ShardManagerOptions {
+        data: data.clone(),
+        event_handlers: vec![],
+        raw_event_handlers: vec![],
+        framework: Default::default(),
+        shard_index: 0,
+        shard_init: 1,
+        shard_total: 1,
+        ws_url,
+        cache: cache.clone(),
+        http: http.clone(),
+        intents: GatewayIntents::empty(),
+        presence: None,
+    });
+    let runner = ShardRunner::new(ShardRunnerOptions {
+        data: data.clone(),
+        event_handlers: vec![],
+        raw_event_handlers: vec![],
+        framework: None,
+        manager,
+        shard,
+        cache: cache.clone(),
+        http: http.clone(),
+    });
+    serenity::Context {
+        data,
+        shard: ShardMessenger::new(&runner),
+        shard_id: ShardId(0),
+        http,
+        cache,
+    }
+}
+
+fn guild_channel_json(id: u64, name: &str) -> Value {
+    json!({
+        "id": id.to_string(),
+        "type": 2,
+        "guild_id": GUILD_ID.to_string(),
+        "name": name,
+        "position": 0,
+        "permission_overwrites": [],
+    })
+}
+
+/// voice channel of GUILD_ID named name on Discord, renames answer with the new name
+pub async fn mock_channel(server: &MockServer, id: u64, name: &str) {
+    let channel_path = format!("/api/v10/channels/{}", id);
+    Mock::given(method("GET"))
+        .and(path(channel_path.clone()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(guild_channel_json(id, name)))
+        .mount(server)
+        .await;
+    Mock::given(method("PATCH"))
+        .and(path(channel_path))
+        .respond_with(move |request: &Request| {
+            let body: Value = request.body_json().unwrap();
+            let name = body["name"].as_str().unwrap_or_default();
+            ResponseTemplate::new(200).set_body_json(guild_channel_json(id, name))
+        })
+        .mount(server)
+        .await;
+}
+
+/// renames received by server, in order, as channel id and name
+pub async fn renames(server: &MockServer) -> Vec<(u64, String)> {
+    server
+        .received_requests()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|request| request.method.as_str() == "PATCH")
+        .map(|request| {
+            let id = request
+                .url
+                .path()
+                .rsplit('/')
+                .next()
+                .unwrap()
+                .parse()
+                .unwrap();
+            let body: Value = request.body_json().unwrap();
+            (id, body["name"].as_str().unwrap_or_default().to_owned())
+        })
+        .collect()
+}
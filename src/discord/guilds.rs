@@ -0,0 +1,129 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{CreateAllowedMentions, Guild, GuildId};
+use tracing::{error, info, warn};
+
+use crate::discord::random_stuff::{is_trusted, split_message};
+use crate::discord::{DiscordContext, Error};
+
+/// guilds the bot operates in, set by allowed_guilds
+#[derive(Debug)]
+pub struct GuildAllowlist {
+    // every guild is allowed if empty
+    guild_ids: Vec<GuildId>,
+    // leave disallowed guilds instead of ignoring them
+    leave: bool,
+}
+
+impl GuildAllowlist {
+    pub fn new(guild_ids: &[u64], leave: bool) -> GuildAllowlist {
+        GuildAllowlist {
+            guild_ids: guild_ids.iter().map(|v| GuildId::from(*v)).collect(),
+            leave,
+        }
+    }
+
+    pub fn allows(&self, guild_id: GuildId) -> bool {
+        self.guild_ids.is_empty() || self.guild_ids.contains(&guild_id)
+    }
+
+    /// called when a guild becomes available, leaves it if it's disallowed and leave is set
+    pub async fn check(&self, ctx: &serenity::Context, guild: &Guild) {
+        if self.allows(guild.id) {
+            return;
+        }
+        if !self.leave {
+            warn!(
+                "Guild {} ({}) isn't in allowed_guilds, its events are ignored",
+                guild.name, guild.id
+            );
+            return;
+        }
+        match guild.id.leave(ctx).await {
+            Ok(()) => info!(
+                "Left guild {} ({}) which isn't in allowed_guilds",
+                guild.name, guild.id
+            ),
+            Err(why) => error!("Can't leave guild {} ({}) : {}", guild.name, guild.id, why),
+        }
+    }
+}
+
+/// list the guilds the bot is in, with the monitored users in their voice channels
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn guilds(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let (servers, monitored) = {
+        let reader = ctx.data().twitch.read().await;
+        (
+            reader.servers.clone(),
+            reader.users.keys().copied().collect::<Vec<_>>(),
+        )
+    };
+
+    let mut lines: Vec<String> = {
+        let cache = ctx.cache();
+        let mut guilds: Vec<(bool, String)> = cache
+            .guilds()
+            .iter()
+            .map(|guild_id| {
+                let is_watched = servers.contains(guild_id);
+                let line = match cache.guild(guild_id) {
+                    Some(guild) => {
+                        let in_voice = monitored
+                            .iter()
+                            .filter(|u| {
+                                guild
+                                    .voice_states
+                                    .get(u)
+                                    .is_some_and(|v| v.channel_id.is_some())
+                            })
+                            .count();
+                        format!(
+                            "{} {} ({}) : {} members, {} monitored users in voice",
+                            if is_watched { "👁️" } else { "▫️" },
+                            guild.name,
+                            guild_id,
+                            guild.member_count,
+                            in_voice
+                        )
+                    }
+                    None => format!(
+                        "{} {} : not cached",
+                        if is_watched { "👁️" } else { "▫️" },
+                        guild_id
+                    ),
+                };
+                (is_watched, line)
+            })
+            .collect();
+        // watched guilds first
+        guilds.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        guilds.into_iter().map(|(_, line)| line).collect()
+    };
+
+    let cached = ctx.cache().guilds();
+    let missing: Vec<String> = servers
+        .iter()
+        .filter(|g| !cached.contains(g))
+        .map(|g| g.to_string())
+        .collect();
+    lines.insert(
+        0,
+        format!("{} guilds, 👁️ are in twitch_watcher.servers", lines.len()),
+    );
+    if !missing.is_empty() {
+        lines.push(format!(
+            "Configured servers the bot isn't in : {}",
+            missing.join(", ")
+        ));
+    }
+
+    for text in split_message(&lines) {
+        ctx.send(
+            poise::CreateReply::default()
+                .content(text)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::CreateAttachment;
+
+use crate::discord::{random_stuff::is_trusted, DiscordContext, Error};
+use crate::log_buffer;
+
+/// send the last warnings and errors of the bot as a file
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn logs(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let Some(log_buffer) = log_buffer::global() else {
+        return Err("The log buffer isn't set up".into());
+    };
+    let lines = log_buffer.lines();
+    if lines.is_empty() {
+        ctx.send(
+            poise::CreateReply::default()
+                .content("No log line kept")
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!(
+                "Last {} log lines ({} kept at most)",
+                lines.len(),
+                log_buffer.capacity()
+            ))
+            .attachment(CreateAttachment::bytes(lines.join("\n"), "logs.txt"))
+            .ephemeral(true),
+    )
+    .await?;
+    Ok(())
+}
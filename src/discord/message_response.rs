@@ -1,36 +1,400 @@
-use anyhow::anyhow;
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use anyhow::anyhow;
 use poise::serenity_prelude as serenity;
-use rand::seq::SliceRandom;
-use serenity::all::{Message, UserId};
+use rand::distributions::{Distribution, WeightedIndex};
+use serenity::all::{ChannelId, CreateAllowedMentions, GuildId, Mentionable, Message, UserId};
 use serenity::builder::CreateMessage;
+use tracing::{info, trace, warn};
+
+use crate::config::{AnswersConfig, Config, PoolEntry};
+use crate::discord::{random_stuff::is_trusted, Data, DiscordContext, Error};
+
+/// pool of answers picked randomly according to their weight
+#[derive(Debug)]
+pub struct RandomPool {
+    entries: Vec<String>,
+    index: WeightedIndex<u32>,
+}
+
+impl RandomPool {
+    pub fn new(entries: &[PoolEntry]) -> anyhow::Result<RandomPool> {
+        if entries.is_empty() {
+            return Err(anyhow!("Array is empty"));
+        }
+        Ok(RandomPool {
+            entries: entries.iter().map(|e| e.value().to_owned()).collect(),
+            index: WeightedIndex::new(entries.iter().map(|e| e.weight()))?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn choose(&self) -> &str {
+        &self.entries[self.index.sample(&mut rand::thread_rng())]
+    }
+}
+
+/// pools used to answer messages naming the bot
+#[derive(Debug)]
+pub enum AnswerPools {
+    /// questions and other messages are answered from different pools
+    Split {
+        question_answers: RandomPool,
+        random_answers: RandomPool,
+    },
+    /// every message is answered from the same pool
+    Unified(RandomPool),
+}
+
+/// pools used to answer messages
+#[derive(Debug)]
+pub struct Answers {
+    pools: AnswerPools,
+    /// used when the bot is mentioned without being named, None to use pools instead
+    default_answers: Option<RandomPool>,
+    /// used for guilds not in enabled_by_guild and for DMs
+    enabled: bool,
+    /// false if message contents aren't received, so only mentions can be detected
+    content_intent: bool,
+    enabled_by_guild: HashMap<GuildId, bool>,
+    /// lowercase words naming the bot
+    trigger_words: Vec<String>,
+    /// fuzzy matching of trigger_words is disabled if 0
+    trigger_max_distance: usize,
+}
+
+impl Answers {
+    pub fn new(config: &Config, content_intent: bool) -> anyhow::Result<Answers> {
+        let (pools, default_answers) = build_pools(&config.answers(), config.unified_answers)?;
+        Ok(Answers {
+            pools,
+            default_answers,
+            enabled: config.auto_responder_enabled,
+            content_intent,
+            enabled_by_guild: config
+                .auto_responder_guilds
+                .iter()
+                .map(|(guild_id, enabled)| (GuildId::from(*guild_id), *enabled))
+                .collect(),
+            trigger_words: config
+                .trigger_words
+                .iter()
+                .map(|w| w.to_lowercase())
+                .collect(),
+            trigger_max_distance: config.trigger_max_distance,
+        })
+    }
+
+    /// copy of these answers with other pools
+    pub fn with_pools(&self, answers: &AnswersConfig) -> anyhow::Result<Answers> {
+        let unified_answers = matches!(self.pools, AnswerPools::Unified(_));
+        answers.validate(unified_answers)?;
+        let (pools, default_answers) = build_pools(answers, unified_answers)?;
+        Ok(Answers {
+            pools,
+            default_answers,
+            enabled: self.enabled,
+            content_intent: self.content_intent,
+            enabled_by_guild: self.enabled_by_guild.clone(),
+            trigger_words: self.trigger_words.clone(),
+            trigger_max_distance: self.trigger_max_distance,
+        })
+    }
+
+    /// true if content contains a trigger word, or a word close to one if fuzzy matching is on
+    pub fn is_named(&self, content: &str) -> bool {
+        let content = content.to_lowercase();
+        if self
+            .trigger_words
+            .iter()
+            .any(|w| content.contains(w.as_str()))
+        {
+            return true;
+        }
+        if self.trigger_max_distance == 0 {
+            return false;
+        }
+        content
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .any(|word| {
+                self.trigger_words
+                    .iter()
+                    .any(|w| edit_distance(word, w) <= self.trigger_max_distance)
+            })
+    }
+
+    /// used for guilds without an override and for DMs
+    pub fn enabled_by_default(&self) -> bool {
+        self.enabled
+    }
+
+    /// number of guilds where enabled_by_default is overridden
+    pub fn guild_override_count(&self) -> usize {
+        self.enabled_by_guild.len()
+    }
+
+    pub fn has_content_intent(&self) -> bool {
+        self.content_intent
+    }
+
+    /// number of entries of each pool, with the name of its config key
+    pub fn pool_sizes(&self) -> Vec<(&'static str, usize)> {
+        let mut sizes = match &self.pools {
+            AnswerPools::Split {
+                question_answers,
+                random_answers,
+            } => vec![
+                ("question_answers", question_answers.len()),
+                ("random_answers", random_answers.len()),
+            ],
+            AnswerPools::Unified(pool) => vec![("question_answers and random_answers", pool.len())],
+        };
+        sizes.push((
+            "default_answers",
+            self.default_answers.as_ref().map_or(0, |p| p.len()),
+        ));
+        sizes
+    }
+
+    /// true if messages sent in guild_id should be answered, None being a DM
+    pub fn is_enabled(&self, guild_id: Option<GuildId>) -> bool {
+        guild_id
+            .and_then(|g| self.enabled_by_guild.get(&g).copied())
+            .unwrap_or(self.enabled)
+    }
+
+    /// select the pool used to answer content
+    /// is_named tells if content contains the bot name, a plain mention uses default_answers
+    pub fn pool_for(&self, content: &str, is_named: bool) -> &RandomPool {
+        self.select(content, is_named).1
+    }
+
+    /// the pool used to answer content, with the name of its config key
+    fn select(&self, content: &str, is_named: bool) -> (&'static str, &RandomPool) {
+        if !is_named {
+            if let Some(default_answers) = &self.default_answers {
+                return ("default_answers", default_answers);
+            }
+        }
+        match &self.pools {
+            AnswerPools::Split {
+                question_answers,
+                random_answers,
+            } => match content.contains('?') {
+                true => ("question_answers", question_answers),
+                false => ("random_answers", random_answers),
+            },
+            AnswerPools::Unified(pool) => ("question_answers and random_answers", pool),
+        }
+    }
+}
+
+fn build_pools(
+    answers: &AnswersConfig,
+    unified_answers: bool,
+) -> anyhow::Result<(AnswerPools, Option<RandomPool>)> {
+    let pools = match unified_answers {
+        true => AnswerPools::Unified(RandomPool::new(&answers.merged_answers())?),
+        false => AnswerPools::Split {
+            question_answers: RandomPool::new(&answers.question_answers)?,
+            random_answers: RandomPool::new(&answers.random_answers)?,
+        },
+    };
+    let default_answers = match answers.default_answers.is_empty() {
+        true => None,
+        false => Some(RandomPool::new(&answers.default_answers)?),
+    };
+    Ok((pools, default_answers))
+}
 
 pub async fn handle_message(
     ctx: &serenity::Context,
-    question_answers: Arc<Vec<String>>,
-    random_answers: Arc<Vec<String>>,
+    answers: Arc<Answers>,
     message: &Message,
 ) -> anyhow::Result<()> {
-    if is_samousse_mentioned(ctx.cache.current_user().id, message) {
-        let is_question: bool = message.content.contains('?');
-        let msg = CreateMessage::new().content(select_random_entry(match is_question {
-            true => question_answers,
-            false => random_answers,
-        })?);
+    if !answers.is_enabled(message.guild_id) {
+        return Ok(());
+    }
+    let bot_user_id = ctx.cache.current_user().id;
+    let is_answered = match answers.content_intent {
+        true => is_samousse_mentioned(&answers, bot_user_id, message),
+        false => message.mentions.iter().any(|m| m.id == bot_user_id),
+    };
+    if is_answered {
+        let is_named = answers.content_intent && answers.is_named(&message.content);
+        let pool = answers.pool_for(&message.content, is_named);
+        let msg = CreateMessage::new().content(pool.choose());
         message.channel_id.send_message(&ctx.http, msg).await?;
     }
     Ok(())
 }
 
-fn select_random_entry(arr: Arc<Vec<String>>) -> anyhow::Result<String> {
-    if arr.is_empty() {
-        return Err(anyhow!("Array is empty"));
+/// forward and answer a direct message according to the direct_messages config
+pub async fn handle_direct_message(
+    ctx: &serenity::Context,
+    data: &Data,
+    message: &Message,
+) -> anyhow::Result<()> {
+    let config = &data.direct_messages;
+    if !config.enabled {
+        trace!("Ignoring direct message of {}", message.author.id);
+        return Ok(());
+    }
+    if let Some(forward_channel_id) = config.forward_channel_id {
+        let content = format!(
+            "Direct message from {} ({}) :\n{}",
+            message.author.mention(),
+            message.author.name,
+            message.content
+        );
+        ChannelId::from(forward_channel_id)
+            .send_message(
+                &ctx.http,
+                CreateMessage::new()
+                    .content(content)
+                    .allowed_mentions(CreateAllowedMentions::new()),
+            )
+            .await?;
+    }
+    match &config.reply {
+        Some(reply) => {
+            message
+                .channel_id
+                .send_message(&ctx.http, CreateMessage::new().content(reply))
+                .await?;
+        }
+        None => handle_message(ctx, data.answers.read().await.clone(), message).await?,
+    }
+    Ok(())
+}
+
+/// show how a message sent here would be answered, without sending the answer
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn test_response(
+    ctx: DiscordContext<'_>,
+    #[description = "Message to test"] message: String,
+) -> Result<(), Error> {
+    let answers = ctx.data().answers.read().await.clone();
+    let bot_user_id = ctx.cache().current_user().id;
+    let is_mentioned = message.contains(&format!("<@{}>", bot_user_id))
+        || message.contains(&format!("<@!{}>", bot_user_id));
+    let is_named = answers.is_named(&message);
+    let text = if !answers.is_enabled(ctx.guild_id()) {
+        String::from("The auto-responder is disabled here")
+    } else if !is_mentioned && !is_named {
+        String::from("The message neither mentions nor names the bot, it wouldn't be answered")
+    } else {
+        let (name, pool) = answers.select(&message, is_named);
+        format!("Answered from {} : {}", name, pool.choose())
+    };
+    ctx.send(poise::CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// replace the answer pools by the ones of the answers file
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn reload_answers(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let Some(path) = &ctx.data().answers_path else {
+        ctx.say(
+            "Can't reload answers, the config isn't read from a file, set answers_path to the \
+            file to read them from",
+        )
+        .await?;
+        return Ok(());
+    };
+    let current = ctx.data().answers.read().await.clone();
+    let answers = match AnswersConfig::load(path).and_then(|a| current.with_pools(&a)) {
+        Ok(answers) => answers,
+        Err(why) => {
+            warn!("Error on reloading answers from {} {}", path.display(), why);
+            ctx.say(format!(
+                "Can't reload answers from {} : {}",
+                path.display(),
+                why
+            ))
+            .await?;
+            return Ok(());
+        }
+    };
+    let changes = current
+        .pool_sizes()
+        .into_iter()
+        .zip(answers.pool_sizes())
+        .map(|((name, before), (_, after))| format!("{} : {} -> {}", name, before, after))
+        .collect::<Vec<_>>()
+        .join("\n");
+    *ctx.data().answers.write().await = Arc::new(answers);
+    info!("Answers reloaded from {}", path.display());
+    ctx.say(format!("Answers reloaded\n{}", changes)).await?;
+    Ok(())
+}
+
+fn is_samousse_mentioned(answers: &Answers, bot_user_id: UserId, msg: &Message) -> bool {
+    msg.mentions.iter().any(|m| m.id == bot_user_id) || answers.is_named(&msg.content)
+}
+
+/// number of single character insertions, deletions or substitutions to turn a into b
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
     }
-    Ok(arr.choose(&mut rand::thread_rng()).unwrap().clone())
+    previous[b.len()]
 }
 
-fn is_samousse_mentioned(bot_user_id: UserId, msg: &Message) -> bool {
-    msg.mentions.iter().any(|m| m.id == bot_user_id)
-        || msg.content.to_lowercase().contains("samousse")
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answers(trigger_max_distance: usize) -> Answers {
+        let pool = RandomPool::new(&[PoolEntry::Plain(String::from("oui"))]).unwrap();
+        Answers {
+            pools: AnswerPools::Unified(pool),
+            default_answers: None,
+            enabled: true,
+            content_intent: true,
+            enabled_by_guild: HashMap::new(),
+            trigger_words: vec![String::from("samousse")],
+            trigger_max_distance,
+        }
+    }
+
+    #[test]
+    fn edit_distance_counts_single_character_edits() {
+        assert_eq!(edit_distance("samousse", "samousse"), 0);
+        assert_eq!(edit_distance("samouse", "samousse"), 1);
+        assert_eq!(edit_distance("samoussse", "samousse"), 1);
+        assert_eq!(edit_distance("samoisse", "samousse"), 1);
+        assert_eq!(edit_distance("smaousse", "samousse"), 2);
+        assert_eq!(edit_distance("", "samousse"), 8);
+    }
+
+    #[test]
+    fn close_words_name_the_bot() {
+        let answers = answers(1);
+        assert!(answers.is_named("Salut Samousse !"));
+        assert!(answers.is_named("t'es là samouse ?"));
+        assert!(answers.is_named("SAMOISSE"));
+        assert!(!answers.is_named("smaousse"));
+        assert!(!answers.is_named("samedi"));
+    }
+
+    #[test]
+    fn only_exact_words_name_the_bot_without_fuzzy_matching() {
+        let answers = answers(0);
+        assert!(answers.is_named("les samousses"));
+        assert!(!answers.is_named("samouse"));
+    }
 }
@@ -0,0 +1,195 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelType, Guild, Member};
+use tracing::info;
+
+use crate::discord::{random_stuff::is_trusted, DiscordContext, Error};
+
+/// kick a member from the server
+#[poise::command(slash_command, guild_only, check = "is_trusted")]
+pub async fn kick(
+    ctx: DiscordContext<'_>,
+    #[description = "Member to kick"] user: serenity::User,
+    #[description = "Reason shown in the audit log"] reason: Option<String>,
+) -> Result<(), Error> {
+    let text = match check_can_moderate(ctx, &user).await? {
+        Err(why) => why,
+        Ok(()) => {
+            let guild_id = ctx.guild_id().unwrap();
+            let result = match &reason {
+                Some(reason) => guild_id.kick_with_reason(ctx, user.id, reason).await,
+                None => guild_id.kick(ctx, user.id).await,
+            };
+            match result {
+                Ok(()) => {
+                    info!(
+                        target: "audit",
+                        "{} ({}) kicked {} ({}) from {}, reason : {:?}",
+                        ctx.author().name,
+                        ctx.author().id,
+                        user.name,
+                        user.id,
+                        guild_id,
+                        reason
+                    );
+                    format!("{} has been kicked", user.name)
+                }
+                Err(why) => format!("Can't kick {} : {}", user.name, why),
+            }
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// ban a member from the server
+#[poise::command(slash_command, guild_only, check = "is_trusted")]
+pub async fn ban(
+    ctx: DiscordContext<'_>,
+    #[description = "Member to ban"] user: serenity::User,
+    #[description = "Reason shown in the audit log"] reason: Option<String>,
+    #[description = "Delete messages sent in the last days"]
+    #[min = 0]
+    #[max = 7]
+    delete_message_days: Option<u8>,
+) -> Result<(), Error> {
+    let text = match check_can_moderate(ctx, &user).await? {
+        Err(why) => why,
+        Ok(()) => {
+            let guild_id = ctx.guild_id().unwrap();
+            let delete_message_days = delete_message_days.unwrap_or(0);
+            let result = match &reason {
+                Some(reason) => {
+                    guild_id
+                        .ban_with_reason(ctx, user.id, delete_message_days, reason)
+                        .await
+                }
+                None => guild_id.ban(ctx, user.id, delete_message_days).await,
+            };
+            match result {
+                Ok(()) => {
+                    info!(
+                        target: "audit",
+                        "{} ({}) banned {} ({}) from {}, reason : {:?}, deleted {} days of messages",
+                        ctx.author().name,
+                        ctx.author().id,
+                        user.name,
+                        user.id,
+                        guild_id,
+                        reason,
+                        delete_message_days
+                    );
+                    format!("{} has been banned", user.name)
+                }
+                Err(why) => format!("Can't ban {} : {}", user.name, why),
+            }
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// move a member to another voice channel
+#[poise::command(slash_command, rename = "move", check = "is_trusted")]
+pub async fn move_member(
+    ctx: DiscordContext<'_>,
+    #[description = "Member to move"] user: serenity::User,
+    #[description = "Voice channel to move the member to"] channel: serenity::GuildChannel,
+) -> Result<(), Error> {
+    let text = match check_can_move(ctx, &user, &channel).await {
+        Err(why) => why,
+        // the voice tracking is updated by the VoiceStateUpdate that follows the move
+        Ok(()) => match channel.guild_id.move_member(ctx, user.id, channel.id).await {
+            Ok(_) => {
+                info!(
+                    target: "audit",
+                    "{} ({}) moved {} ({}) to {} in {}",
+                    ctx.author().name,
+                    ctx.author().id,
+                    user.name,
+                    user.id,
+                    channel.id,
+                    channel.guild_id
+                );
+                format!("{} has been moved to {}", user.name, channel)
+            }
+            Err(why) => format!("Can't move {} : {}", user.name, why),
+        },
+    };
+    ctx.send(poise::CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// check that channel is a voice channel of a watched server and that user is in voice there
+/// the error is the reason to show to the invoker
+async fn check_can_move(
+    ctx: DiscordContext<'_>,
+    user: &serenity::User,
+    channel: &serenity::GuildChannel,
+) -> Result<(), String> {
+    if !matches!(channel.kind, ChannelType::Voice | ChannelType::Stage) {
+        return Err(format!("{} isn't a voice channel", channel));
+    }
+    if !ctx
+        .data()
+        .twitch
+        .read()
+        .await
+        .servers
+        .contains(&channel.guild_id)
+    {
+        return Err(format!("{} isn't in a configured server", channel));
+    }
+    let Some(guild) = ctx.cache().guild(channel.guild_id) else {
+        return Err(String::from("Guild isn't in cache"));
+    };
+    match guild
+        .voice_states
+        .get(&user.id)
+        .and_then(|state| state.channel_id)
+    {
+        None => Err(format!("{} isn't in a voice channel", user.name)),
+        Some(current) if current == channel.id => {
+            Err(format!("{} is already in {}", user.name, channel))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// check that user is a member of the server the bot is allowed to act on
+/// the inner error is the reason to show to the invoker
+async fn check_can_moderate(
+    ctx: DiscordContext<'_>,
+    user: &serenity::User,
+) -> Result<Result<(), String>, Error> {
+    let guild_id = ctx.guild_id().ok_or("Not in a guild")?;
+    let Ok(target) = guild_id.member(ctx, user.id).await else {
+        return Ok(Err(format!("{} isn't a member of this server", user.name)));
+    };
+    let bot_id = ctx.cache().current_user().id;
+    let bot = guild_id.member(ctx, bot_id).await?;
+
+    let guild = ctx.guild().ok_or("Guild isn't in cache")?;
+    if target.user.id == guild.owner_id {
+        return Ok(Err(String::from("The server owner can't be moderated")));
+    }
+    if highest_role_position(&guild, &target) >= highest_role_position(&guild, &bot) {
+        return Ok(Err(format!(
+            "{} has a role higher or equal to the bot's highest role",
+            user.name
+        )));
+    }
+    Ok(Ok(()))
+}
+
+fn highest_role_position(guild: &Guild, member: &Member) -> u16 {
+    member
+        .roles
+        .iter()
+        .filter_map(|role_id| guild.roles.get(role_id))
+        .map(|role| role.position)
+        .max()
+        .unwrap_or(0)
+}
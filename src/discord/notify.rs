@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use poise::serenity_prelude as serenity;
+use serenity::all::{CreateMessage, GuildId, Mentionable, UserId};
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::discord::{DiscordContext, DiscordTwitchWatcher, Error};
+
+/// get a direct message when a streamer goes live, until you leave this server
+#[poise::command(slash_command, guild_only)]
+pub async fn notify_me(
+    ctx: DiscordContext<'_>,
+    #[description = "Streamer to follow"] streamer: serenity::User,
+) -> Result<(), Error> {
+    let text = {
+        let mut writer = ctx.data().twitch.write().await;
+        if !writer.users.contains_key(&streamer.id) {
+            format!("{} isn't a monitored streamer", streamer.name)
+        } else {
+            writer
+                .notify_subscriptions
+                .entry(streamer.id)
+                .or_default()
+                .entry(ctx.author().id)
+                .or_default()
+                .extend(ctx.guild_id());
+            writer.save_notify_subscriptions();
+            format!("You will be notified when {} goes live", streamer.name)
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// stop getting a direct message when a streamer goes live
+#[poise::command(slash_command)]
+pub async fn notify_me_stop(
+    ctx: DiscordContext<'_>,
+    #[description = "Streamer to stop following"] streamer: serenity::User,
+) -> Result<(), Error> {
+    let removed = ctx
+        .data()
+        .twitch
+        .write()
+        .await
+        .remove_notify_subscription(&streamer.id, &ctx.author().id);
+    let text = match removed {
+        true => format!(
+            "You won't be notified anymore when {} goes live",
+            streamer.name
+        ),
+        false => format!("You weren't notified when {} goes live", streamer.name),
+    };
+    ctx.send(poise::CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// get or remove the role pinged when a streamer goes live
+#[poise::command(slash_command, guild_only)]
+pub async fn notify_role(
+    ctx: DiscordContext<'_>,
+    #[description = "Streamer whose role to toggle"] streamer: serenity::User,
+) -> Result<(), Error> {
+    let notify_role_id = ctx
+        .data()
+        .twitch
+        .read()
+        .await
+        .users
+        .get(&streamer.id)
+        .and_then(|u| u.notify_role_id);
+    let Some(notify_role_id) = notify_role_id else {
+        ctx.send(
+            poise::CreateReply::default()
+                .content(format!("{} has no notification role", streamer.name))
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    };
+    let Some(member) = ctx.author_member().await else {
+        return Err("Can't get your server member".into());
+    };
+    let text = if member.roles.contains(&notify_role_id) {
+        member.remove_role(ctx.http(), notify_role_id).await?;
+        format!(
+            "You won't be pinged anymore when {} goes live",
+            streamer.name
+        )
+    } else {
+        member.add_role(ctx.http(), notify_role_id).await?;
+        format!("You will be pinged when {} goes live", streamer.name)
+    };
+    info!(
+        "{} toggled notification role {} of {}",
+        ctx.author().id,
+        notify_role_id,
+        streamer.id
+    );
+    ctx.send(poise::CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// send a direct message to every user that asked to be notified when the streamer goes live
+pub async fn notify_subscribers(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    streamer_discord_id: &UserId,
+    streamer_user_login: &str,
+) {
+    let subscribers: Vec<UserId> = match twitch
+        .read()
+        .await
+        .notify_subscriptions
+        .get(streamer_discord_id)
+    {
+        Some(subscribers) => subscribers.keys().copied().collect(),
+        None => return,
+    };
+    debug!(
+        "Notifying {} users that {} is live",
+        subscribers.len(),
+        streamer_user_login
+    );
+
+    let text = format!(
+        "{} is live! https://twitch.tv/{}",
+        streamer_discord_id.mention(),
+        streamer_user_login
+    );
+    for subscriber in subscribers {
+        let result = match subscriber.create_dm_channel(ctx).await {
+            Ok(channel) => channel
+                .send_message(ctx, CreateMessage::new().content(&text))
+                .await
+                .map(|_| ()),
+            Err(why) => Err(why),
+        };
+        if let Err(why) = result {
+            warn!("Can't notify user {} : {}", subscriber, why);
+        }
+    }
+}
+
+/// a user that left the guild doesn't want to be notified anymore of the subscriptions made
+/// in it
+pub async fn remove_subscriber(
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    guild_id: GuildId,
+    user_id: &UserId,
+) {
+    let removed = twitch
+        .write()
+        .await
+        .remove_guild_notify_subscriptions(guild_id, user_id);
+    if removed {
+        info!(
+            "Removed notify subscriptions of user {} made in guild {}",
+            user_id, guild_id
+        );
+    }
+}
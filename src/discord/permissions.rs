@@ -0,0 +1,107 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::Permissions;
+
+use crate::discord::{random_stuff::is_trusted, DiscordContext, Error};
+
+/// permissions needed by the bot in channels and the feature that needs them
+pub const CHANNEL_PERMISSIONS: [(Permissions, &str); 10] = [
+    (Permissions::VIEW_CHANNEL, "View Channel"),
+    (Permissions::MANAGE_CHANNELS, "Manage Channels (renames)"),
+    (Permissions::SEND_MESSAGES, "Send Messages (announcements)"),
+    (
+        Permissions::EMBED_LINKS,
+        "Embed Links (embed announcements)",
+    ),
+    (
+        Permissions::CREATE_PUBLIC_THREADS,
+        "Create Public Threads (forum announcements)",
+    ),
+    (
+        Permissions::SEND_MESSAGES_IN_THREADS,
+        "Send Messages in Threads (forum announcements)",
+    ),
+    (
+        Permissions::MENTION_EVERYONE,
+        "Mention @everyone (announcement pings)",
+    ),
+    (Permissions::ADD_REACTIONS, "Add Reactions"),
+    (Permissions::MOVE_MEMBERS, "Move Members (/move)"),
+    (Permissions::MANAGE_ROLES, "Manage Roles (/notify_role)"),
+];
+
+/// permissions needed by the bot which only apply to the whole guild
+pub const GUILD_PERMISSIONS: [(Permissions, &str); 2] = [
+    (Permissions::KICK_MEMBERS, "Kick Members (/kick)"),
+    (Permissions::BAN_MEMBERS, "Ban Members (/ban)"),
+];
+
+// scopes needed to register the bot with its slash commands
+const INVITE_SCOPES: &str = "bot%20applications.commands";
+
+/// every permission of CHANNEL_PERMISSIONS and GUILD_PERMISSIONS
+pub fn required_permissions() -> Permissions {
+    CHANNEL_PERMISSIONS
+        .iter()
+        .chain(GUILD_PERMISSIONS.iter())
+        .fold(Permissions::empty(), |acc, (permission, _)| {
+            acc | *permission
+        })
+}
+
+fn format_permissions(permissions: Permissions, required: &[(Permissions, &str)]) -> String {
+    required
+        .iter()
+        .map(|(permission, name)| {
+            format!(
+                "{} {}",
+                match permissions.contains(*permission) {
+                    true => "✅",
+                    false => "❌",
+                },
+                name
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// post the url inviting the bot with the permissions it needs
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn invite(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let url = format!(
+        "https://discord.com/oauth2/authorize?client_id={}&scope={}&permissions={}",
+        ctx.framework().bot_id,
+        INVITE_SCOPES,
+        required_permissions().bits()
+    );
+    ctx.say(format!("<{}>", url)).await?;
+    Ok(())
+}
+
+/// check that the bot has the permissions it needs in a channel and in its guild
+#[poise::command(slash_command, guild_only, check = "is_trusted")]
+pub async fn check_perms(
+    ctx: DiscordContext<'_>,
+    #[description = "Channel to check"] channel: serenity::GuildChannel,
+) -> Result<(), Error> {
+    let bot_id = ctx.cache().current_user().id;
+    let member = channel.guild_id.member(ctx, bot_id).await?;
+    let (permissions, guild_permissions) = {
+        let guild = ctx
+            .cache()
+            .guild(channel.guild_id)
+            .ok_or("The guild isn't in cache")?;
+        (
+            guild.user_permissions_in(&channel, &member),
+            guild.member_permissions(&member),
+        )
+    };
+    let text = format!(
+        "Permissions in {} :\n{}\nPermissions in the server :\n{}",
+        channel,
+        format_permissions(permissions, &CHANNEL_PERMISSIONS),
+        format_permissions(guild_permissions, &GUILD_PERMISSIONS)
+    );
+    ctx.say(text).await?;
+    Ok(())
+}
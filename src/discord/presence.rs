@@ -0,0 +1,31 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use serenity::all::ActivityData;
+use tokio::sync::{Notify, RwLock};
+use tokio::time::sleep;
+use tracing::debug;
+
+use crate::discord::DiscordTwitchWatcher;
+
+// changes happening within this delay are shown in a single presence update
+const PRESENCE_DEBOUNCE: Duration = Duration::from_secs(10);
+
+/// show the number of live monitored streams as the bot presence, updated when notified
+pub async fn run_live_presence(
+    ctx: serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    update: Arc<Notify>,
+) {
+    loop {
+        let live_count = twitch.read().await.live_count();
+        debug!("Updating presence, {} live streams", live_count);
+        ctx.set_activity(Some(ActivityData::watching(match live_count {
+            1 => String::from("1 live stream"),
+            n => format!("{} live streams", n),
+        })));
+        update.notified().await;
+        sleep(PRESENCE_DEBOUNCE).await;
+    }
+}
@@ -1,7 +1,14 @@
+use crate::config::AckStyle;
 use crate::discord::{DiscordContext, Error};
+use poise::serenity_prelude as serenity;
 use rand::{Rng, thread_rng};
 use tracing::debug;
 
+// maximum length of a text handled by mock
+const MOCK_MAX_LENGTH: usize = 1000;
+// maximum length of a Discord message
+pub const MESSAGE_MAX_LENGTH: usize = 2000;
+
 // this function is used in poise::command attributes to check if the user is trustworthy
 // copy-paste from https://github.com/serenity-rs/poise/blob/current/examples/feature_showcase/checks.rs#L47
 pub async fn is_trusted(ctx: DiscordContext<'_>) -> Result<bool, Error> {
@@ -20,15 +27,73 @@ pub async fn is_trusted(ctx: DiscordContext<'_>) -> Result<bool, Error> {
     Ok(ret)
 }
 
+/// tell the user that the command succeeded, in the configured ack_style
+/// commands whose result has to be read should reply with it instead
+pub async fn acknowledge(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let reply = match ctx.data().ack_style {
+        AckStyle::Text => poise::CreateReply::default().content("ok"),
+        AckStyle::Checkmark => poise::CreateReply::default().content("✅"),
+        AckStyle::Ephemeral => poise::CreateReply::default().content("ok").ephemeral(true),
+    };
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+/// join lines in as few messages as possible, lines too long for a message are cut
+pub fn split_message(lines: &[String]) -> Vec<String> {
+    let mut messages: Vec<String> = vec![];
+    let mut current = String::new();
+    for line in lines {
+        if !current.is_empty() && current.len() + 1 + line.len() > MESSAGE_MAX_LENGTH {
+            messages.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        for c in line.chars() {
+            if current.len() + c.len_utf8() > MESSAGE_MAX_LENGTH {
+                messages.push(std::mem::take(&mut current));
+            }
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        messages.push(current);
+    }
+    messages
+}
+
 #[poise::command(slash_command)]
 pub async fn ping(ctx: DiscordContext<'_>) -> Result<(), Error> {
     ctx.say("pong !").await?;
     Ok(())
 }
 
+/// mentions allowed in text relayed from a user, everyone and role mentions are only allowed
+/// for trusted users so that the bot can't be used to ping with its own permissions
+fn relay_allowed_mentions(ctx: DiscordContext<'_>) -> serenity::CreateAllowedMentions {
+    let is_trusted = ctx
+        .data()
+        .trusted_users_ids
+        .contains(&ctx.author().id.get());
+    allowed_mentions_for(is_trusted)
+}
+
+fn allowed_mentions_for(is_trusted: bool) -> serenity::CreateAllowedMentions {
+    serenity::CreateAllowedMentions::new()
+        .all_users(true)
+        .everyone(is_trusted)
+        .all_roles(is_trusted)
+}
+
 #[poise::command(slash_command)]
 pub async fn echo(ctx: DiscordContext<'_>, message: String) -> Result<(), Error> {
-    ctx.say(message).await?;
+    ctx.send(
+        poise::CreateReply::default()
+            .content(message)
+            .allowed_mentions(relay_allowed_mentions(ctx)),
+    )
+    .await?;
     Ok(())
 }
 #[poise::command(
@@ -67,3 +132,94 @@ pub async fn random_number(
     ctx.say(result).await?;
     Ok(())
 }
+
+#[poise::command(
+    slash_command,
+    description_localized("en-US", "Write the provided text wItH aLtErNaTiNg CaSe"),
+    description_localized("fr", "Écrit le texte fourni eN aLtErNaNt La CaSsE")
+)]
+pub async fn mock(
+    ctx: DiscordContext<'_>,
+    #[description = "Text to mock"]
+    #[description_localized("fr", "Texte à moquer")]
+    text: String,
+) -> Result<(), Error> {
+    reply_mock(ctx, &text).await
+}
+
+#[poise::command(context_menu_command = "Mock")]
+pub async fn mock_message(
+    ctx: DiscordContext<'_>,
+    #[description = "Message to mock"] message: serenity::Message,
+) -> Result<(), Error> {
+    reply_mock(ctx, &message.content).await
+}
+
+async fn reply_mock(ctx: DiscordContext<'_>, text: &str) -> Result<(), Error> {
+    let error = if text.trim().is_empty() {
+        Some(String::from("Nothing to mock"))
+    } else if text.chars().count() > MOCK_MAX_LENGTH {
+        Some(format!("Text is too long (max {} characters)", MOCK_MAX_LENGTH))
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        ctx.send(poise::CreateReply::default().content(error).ephemeral(true))
+            .await?;
+        return Ok(());
+    }
+    ctx.send(
+        poise::CreateReply::default()
+            .content(mock_text(text))
+            .allowed_mentions(relay_allowed_mentions(ctx)),
+    )
+    .await?;
+    Ok(())
+}
+
+/// alternate the case of every letter of text, other characters are kept as is
+fn mock_text(text: &str) -> String {
+    let mut upper = false;
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_alphabetic() {
+            if upper {
+                ret.extend(c.to_uppercase());
+            } else {
+                ret.extend(c.to_lowercase());
+            }
+            upper = !upper;
+        } else {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// mention types discord parses in a message sent with allowed_mentions
+    fn parsed_mentions(allowed_mentions: serenity::CreateAllowedMentions) -> Vec<String> {
+        let json = serde_json::to_value(allowed_mentions).unwrap();
+        serde_json::from_value(json["parse"].clone()).unwrap()
+    }
+
+    #[test]
+    fn everyone_doesnt_ping_for_untrusted_users() {
+        let reply = poise::CreateReply::default()
+            .content("@everyone @here free nitro")
+            .allowed_mentions(allowed_mentions_for(false));
+
+        let parsed = parsed_mentions(reply.allowed_mentions.unwrap());
+        assert_eq!(parsed, vec![String::from("users")]);
+    }
+
+    #[test]
+    fn trusted_users_can_ping_everyone() {
+        let parsed = parsed_mentions(allowed_mentions_for(true));
+        assert!(parsed.contains(&String::from("everyone")));
+        assert!(parsed.contains(&String::from("roles")));
+    }
+}
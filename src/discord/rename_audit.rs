@@ -0,0 +1,74 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::Mentionable;
+use tracing::{error, info};
+
+use crate::discord::twitch::rename_channel;
+use crate::discord::{random_stuff::is_trusted, DiscordContext, Error};
+
+/// list the renamed channels without a live streamer in them, and optionally restore them
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn rename_audit(
+    ctx: DiscordContext<'_>,
+    #[description = "Restore the channels found"] fix: Option<bool>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let twitch = ctx.data().twitch.clone();
+    let (stuck, unknown_voice_channels) = {
+        let reader = twitch.read().await;
+        let unknown_voice_channels = reader
+            .users
+            .values()
+            .filter(|u| !u.has_been_part_of_voice_state_event)
+            .count();
+        (reader.find_stuck_channels(), unknown_voice_channels)
+    };
+    if stuck.is_empty() {
+        ctx.say("No stuck renamed channel").await?;
+        return Ok(());
+    }
+    let mut lines = Vec::new();
+    for (channel_id, streamer) in &stuck {
+        let renamed_for = match streamer {
+            Some(streamer) => streamer.mention().to_string(),
+            None => String::from("an unknown streamer"),
+        };
+        lines.push(format!(
+            "{} renamed for {} has no live streamer",
+            channel_id.mention(),
+            renamed_for
+        ));
+    }
+    if unknown_voice_channels > 0 {
+        lines.push(format!(
+            "The voice channel of {} streamers isn't known yet, they may be in these channels",
+            unknown_voice_channels
+        ));
+    }
+    if fix.unwrap_or(false) {
+        info!(
+            "{} restores {} stuck renamed channels",
+            ctx.author().id,
+            stuck.len()
+        );
+        for (channel_id, streamer) in &stuck {
+            let streamer = streamer.unwrap_or(ctx.author().id);
+            if let Err(why) = rename_channel(
+                ctx.serenity_context(),
+                twitch.clone(),
+                &streamer,
+                channel_id,
+                false,
+            )
+            .await
+            {
+                error!("Error on restoring channel {} {}", channel_id, why);
+                lines.push(format!("Can't restore {} : {}", channel_id.mention(), why));
+            }
+        }
+        lines.push(String::from(
+            "Restores have been applied or queued, see /rename_queue",
+        ));
+    }
+    ctx.say(lines.join("\n")).await?;
+    Ok(())
+}
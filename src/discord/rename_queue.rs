@@ -0,0 +1,225 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, Mentionable};
+use tokio::sync::RwLock;
+use tokio::time::sleep;
+use tracing::{debug, info};
+
+use crate::discord::twitch::apply_rename;
+use crate::discord::{random_stuff::is_trusted, DiscordContext, DiscordTwitchWatcher, Error};
+
+// Discord allows this many renames of a channel per RENAME_WINDOW
+const RENAMES_PER_WINDOW: usize = 2;
+const RENAME_WINDOW: Duration = Duration::from_secs(600);
+// how often the queue is checked for renames to apply
+const QUEUE_TICK: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub struct PendingRename {
+    pub name: String,
+    pub reason: Option<String>,
+    pub apply_at: Instant,
+}
+
+/// renames deferred to stay under the Discord rename rate limit
+#[derive(Debug, Default)]
+pub struct RenameQueue {
+    // time of the renames made in the last RENAME_WINDOW for each channel
+    recent: HashMap<ChannelId, VecDeque<Instant>>,
+    pub pending: HashMap<ChannelId, PendingRename>,
+}
+
+impl RenameQueue {
+    /// returns true if the rename can be made now, in which case it is accounted for,
+    /// otherwise the rename is queued, replacing any rename already pending for the channel
+    pub fn schedule(
+        &mut self,
+        channel_id: ChannelId,
+        name: String,
+        reason: Option<String>,
+    ) -> bool {
+        let now = Instant::now();
+        let recent = self.recent.entry(channel_id).or_default();
+        while recent.front().is_some_and(|t| now - *t >= RENAME_WINDOW) {
+            recent.pop_front();
+        }
+
+        if let Some(pending) = self.pending.get_mut(&channel_id) {
+            pending.name = name;
+            pending.reason = reason;
+            return false;
+        }
+        if recent.len() < RENAMES_PER_WINDOW {
+            recent.push_back(now);
+            return true;
+        }
+        let apply_at = recent[recent.len() - RENAMES_PER_WINDOW] + RENAME_WINDOW;
+        self.pending.insert(
+            channel_id,
+            PendingRename {
+                name,
+                reason,
+                apply_at,
+            },
+        );
+        false
+    }
+
+    /// remove and return the renames the rate limit of their channel allows now, they are
+    /// accounted for
+    pub fn take_due(&mut self) -> Vec<(ChannelId, PendingRename)> {
+        let now = Instant::now();
+        let due: Vec<ChannelId> = self
+            .pending
+            .keys()
+            .filter(|channel_id| {
+                self.recent.get(channel_id).map_or(0, |recent| {
+                    recent.iter().filter(|t| now - **t < RENAME_WINDOW).count()
+                }) < RENAMES_PER_WINDOW
+            })
+            .copied()
+            .collect();
+        due.into_iter()
+            .filter_map(|channel_id| {
+                let pending = self.pending.remove(&channel_id)?;
+                self.recent.entry(channel_id).or_default().push_back(now);
+                Some((channel_id, pending))
+            })
+            .collect()
+    }
+}
+
+/// apply the queued renames when they are due
+pub async fn run_rename_queue(ctx: serenity::Context, twitch: Arc<RwLock<DiscordTwitchWatcher>>) {
+    loop {
+        sleep(QUEUE_TICK).await;
+        let due = twitch.write().await.rename_queue.take_due();
+        apply_renames(&ctx, twitch.clone(), due).await;
+    }
+}
+
+async fn apply_renames(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    renames: Vec<(ChannelId, PendingRename)>,
+) {
+    for (channel_id, pending) in renames {
+        debug!("Applying queued rename of channel {}", channel_id);
+        apply_rename(
+            ctx,
+            twitch.clone(),
+            channel_id,
+            &pending.name,
+            pending.reason.as_deref(),
+        )
+        .await;
+    }
+}
+
+/// show the channel renames waiting for the rate limit
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn rename_queue(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let text: String;
+    {
+        let reader = ctx.data().twitch.read().await;
+        let now = Instant::now();
+        let mut lines = reader
+            .rename_queue
+            .pending
+            .iter()
+            .map(|(channel_id, pending)| {
+                let apply_at = SystemTime::now() + pending.apply_at.saturating_duration_since(now);
+                let apply_at = apply_at
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                format!(
+                    "{} -> {} <t:{}:R>",
+                    channel_id.mention(),
+                    pending.name,
+                    apply_at
+                )
+            })
+            .collect::<Vec<String>>();
+        lines.sort();
+        text = if lines.is_empty() {
+            String::from("No pending rename")
+        } else {
+            lines.join("\n")
+        };
+    }
+    ctx.say(text).await?;
+    Ok(())
+}
+
+/// apply now the pending channel renames the rate limit allows, the others stay queued
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn rename_queue_flush(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let twitch = ctx.data().twitch.clone();
+    let (renames, remaining) = {
+        let mut writer = twitch.write().await;
+        let renames = writer.rename_queue.take_due();
+        (renames, writer.rename_queue.pending.len())
+    };
+    let count = renames.len();
+    info!("{} flushed {} queued renames", ctx.author().id, count);
+    apply_renames(ctx.serenity_context(), twitch, renames).await;
+    ctx.say(format!(
+        "Applied {} pending renames, {} still wait for the rate limit",
+        count, remaining
+    ))
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel_id() -> ChannelId {
+        ChannelId::new(10)
+    }
+
+    #[test]
+    fn renames_over_the_limit_are_queued() {
+        let mut queue = RenameQueue::default();
+        assert!(queue.schedule(channel_id(), String::from("a"), None));
+        assert!(queue.schedule(channel_id(), String::from("b"), None));
+        assert!(!queue.schedule(channel_id(), String::from("c"), None));
+        assert!(!queue.schedule(channel_id(), String::from("d"), None));
+
+        assert_eq!(queue.pending[&channel_id()].name, "d");
+    }
+
+    #[test]
+    fn rate_limited_renames_are_not_due() {
+        let mut queue = RenameQueue::default();
+        for name in ["a", "b", "c"] {
+            queue.schedule(channel_id(), String::from(name), None);
+        }
+
+        assert!(queue.take_due().is_empty());
+        assert!(queue.pending.contains_key(&channel_id()));
+    }
+
+    #[test]
+    fn renames_are_due_when_the_channel_has_room() {
+        let mut queue = RenameQueue::default();
+        queue.pending.insert(
+            channel_id(),
+            PendingRename {
+                name: String::from("a"),
+                reason: None,
+                apply_at: Instant::now(),
+            },
+        );
+
+        assert_eq!(queue.take_due().len(), 1);
+        assert!(queue.pending.is_empty());
+        assert_eq!(queue.recent[&channel_id()].len(), 1);
+    }
+}
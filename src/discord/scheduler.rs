@@ -0,0 +1,244 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, CreateAllowedMentions, CreateMessage, Mentionable};
+use tokio::sync::RwLock;
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+use crate::discord::{random_stuff::is_trusted, DiscordContext, DiscordTwitchWatcher, Error};
+
+// how often the schedules are checked
+const SCHEDULER_TICK: Duration = Duration::from_secs(30);
+// a message missed by more than this, because the bot was down, isn't sent
+const SCHEDULE_MAX_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ScheduleKind {
+    /// cron expression with seconds, in UTC
+    Recurring(String),
+    Once,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScheduledMessage {
+    pub id: u32,
+    pub channel_id: ChannelId,
+    pub content: String,
+    pub kind: ScheduleKind,
+    pub next_run: DateTime<Utc>,
+}
+
+/// next time a cron expression fires after now
+fn next_cron_run(expression: &str) -> anyhow::Result<DateTime<Utc>> {
+    Schedule::from_str(expression)?
+        .upcoming(Utc)
+        .next()
+        .ok_or(anyhow::anyhow!("{} never fires", expression))
+}
+
+/// post the scheduled messages when they are due
+pub async fn run_scheduler(ctx: serenity::Context, twitch: Arc<RwLock<DiscordTwitchWatcher>>) {
+    loop {
+        sleep(SCHEDULER_TICK).await;
+        let now = Utc::now();
+        let mut due = Vec::new();
+        {
+            let mut writer = twitch.write().await;
+            let mut changed = false;
+            writer.schedules.retain_mut(|schedule| {
+                if schedule.next_run > now {
+                    return true;
+                }
+                changed = true;
+                if (now - schedule.next_run)
+                    .to_std()
+                    .is_ok_and(|late| late <= SCHEDULE_MAX_DELAY)
+                {
+                    due.push((schedule.id, schedule.channel_id, schedule.content.clone()));
+                } else {
+                    warn!(
+                        "Scheduled message {} was due at {}, skipping it",
+                        schedule.id, schedule.next_run
+                    );
+                }
+                match &schedule.kind {
+                    ScheduleKind::Once => false,
+                    ScheduleKind::Recurring(expression) => match next_cron_run(expression) {
+                        Ok(next_run) => {
+                            schedule.next_run = next_run;
+                            true
+                        }
+                        Err(why) => {
+                            error!("Removing scheduled message {} : {}", schedule.id, why);
+                            false
+                        }
+                    },
+                }
+            });
+            if changed {
+                writer.save_schedules();
+            }
+        }
+
+        for (id, channel_id, content) in due {
+            debug!("Sending scheduled message {}", id);
+            let message = CreateMessage::new()
+                .content(content)
+                .allowed_mentions(CreateAllowedMentions::new());
+            if let Err(why) = channel_id.send_message(&ctx.http, message).await {
+                error!("Error on sending scheduled message {} {}", id, why);
+            }
+        }
+    }
+}
+
+/// add a message, next_run being when it is sent first
+async fn add_schedule(
+    ctx: DiscordContext<'_>,
+    channel_id: ChannelId,
+    content: String,
+    kind: ScheduleKind,
+    next_run: DateTime<Utc>,
+) -> u32 {
+    let mut writer = ctx.data().twitch.write().await;
+    let id = writer.schedules.iter().map(|s| s.id).max().unwrap_or(0) + 1;
+    info!(
+        "{} scheduled message {} in {} for {}",
+        ctx.author().id,
+        id,
+        channel_id,
+        next_run
+    );
+    writer.schedules.push(ScheduledMessage {
+        id,
+        channel_id,
+        content,
+        kind,
+        next_run,
+    });
+    writer.save_schedules();
+    id
+}
+
+/// send a message periodically, following a cron expression with seconds in UTC
+#[poise::command(slash_command, guild_only, check = "is_trusted")]
+pub async fn schedule_add(
+    ctx: DiscordContext<'_>,
+    #[description = "Channel to post in"] channel: serenity::GuildChannel,
+    #[description = "When to post, like 0 0 9 * * * for every day at 9:00 UTC"] cron: String,
+    #[description = "Message to post"] message: String,
+) -> Result<(), Error> {
+    let text = match next_cron_run(&cron) {
+        Err(why) => format!("Invalid cron expression {} : {}", cron, why),
+        Ok(next_run) => {
+            let id = add_schedule(
+                ctx,
+                channel.id,
+                message,
+                ScheduleKind::Recurring(cron),
+                next_run,
+            )
+            .await;
+            format!(
+                "Scheduled message {} will first be posted <t:{}:R>",
+                id,
+                next_run.timestamp()
+            )
+        }
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
+
+/// send a message once after a delay
+#[poise::command(slash_command, guild_only, check = "is_trusted")]
+pub async fn schedule_once(
+    ctx: DiscordContext<'_>,
+    #[description = "Channel to post in"] channel: serenity::GuildChannel,
+    #[description = "When to post, like 30m or 2h"] delay: String,
+    #[description = "Message to post"] message: String,
+) -> Result<(), Error> {
+    let next_run = humantime::parse_duration(&delay)
+        .map_err(anyhow::Error::from)
+        .and_then(|d| Ok(Utc::now() + chrono::Duration::from_std(d)?));
+    let text = match next_run {
+        Err(why) => format!("Invalid delay {} : {}", delay, why),
+        Ok(next_run) => {
+            let id = add_schedule(ctx, channel.id, message, ScheduleKind::Once, next_run).await;
+            format!(
+                "Scheduled message {} will be posted <t:{}:R>",
+                id,
+                next_run.timestamp()
+            )
+        }
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
+
+/// show the scheduled messages
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn schedule_list(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let text = {
+        let reader = ctx.data().twitch.read().await;
+        let lines = reader
+            .schedules
+            .iter()
+            .map(|s| {
+                let kind = match &s.kind {
+                    ScheduleKind::Recurring(expression) => format!("`{}`", expression),
+                    ScheduleKind::Once => String::from("once"),
+                };
+                format!(
+                    "{} : {} in {}, next <t:{}:R> : {}",
+                    s.id,
+                    kind,
+                    s.channel_id.mention(),
+                    s.next_run.timestamp(),
+                    s.content
+                )
+            })
+            .collect::<Vec<String>>();
+        match lines.is_empty() {
+            true => String::from("No scheduled message"),
+            false => lines.join("\n"),
+        }
+    };
+    ctx.send(
+        poise::CreateReply::default()
+            .content(text)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// cancel a scheduled message
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn schedule_cancel(
+    ctx: DiscordContext<'_>,
+    #[description = "Id shown by /schedule_list"] id: u32,
+) -> Result<(), Error> {
+    let removed = {
+        let mut writer = ctx.data().twitch.write().await;
+        let count = writer.schedules.len();
+        writer.schedules.retain(|s| s.id != id);
+        let removed = writer.schedules.len() != count;
+        if removed {
+            writer.save_schedules();
+        }
+        removed
+    };
+    let text = match removed {
+        true => format!("Scheduled message {} cancelled", id),
+        false => format!("No scheduled message {}", id),
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use serenity::all::ShardManager;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::{watch, RwLock};
+use tokio::time::{sleep, timeout};
+use tracing::{info, warn};
+
+use crate::discord::twitch::restore_all_channels;
+use crate::discord::DiscordTwitchWatcher;
+use crate::inter_comm::InterComm;
+
+// time given to the twitch events already received to be taken by the event handler
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// once stopping is true, let the twitch events already received be handled,
+/// restore the renamed channels and disconnect from Discord
+pub async fn stop_on_shutdown(
+    ctx: serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    sender: Sender<InterComm>,
+    shard_manager: Arc<ShardManager>,
+    mut stopping: watch::Receiver<bool>,
+) {
+    if stopping.wait_for(|stopping| *stopping).await.is_err() {
+        return;
+    }
+    // the channel is empty once every sent event has been received
+    let drain = async {
+        while sender.capacity() < sender.max_capacity() {
+            sleep(DRAIN_POLL_INTERVAL).await;
+        }
+    };
+    if timeout(DRAIN_TIMEOUT, drain).await.is_err() {
+        warn!(
+            "{} twitch events are left unhandled",
+            sender.max_capacity() - sender.capacity()
+        );
+    }
+    info!("Restoring renamed channels");
+    restore_all_channels(&ctx, twitch).await;
+    info!("Disconnecting from Discord");
+    shard_manager.shutdown_all().await;
+}
@@ -0,0 +1,290 @@
+use std::fmt::{Debug, Formatter};
+use std::num::NonZeroU64;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use poise::serenity_prelude as serenity;
+use serde_json::json;
+use serenity::all::{GuildId, UserId};
+use tokio::sync::RwLock;
+use url::Url;
+
+use crate::config::{EventKind, SinkConfig};
+use crate::discord::announce::announce_stream_event;
+use crate::discord::DiscordTwitchWatcher;
+use crate::templates::AnnounceTemplates;
+
+// a sink not answering within this delay is considered unavailable
+const SINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// a stream going online or offline
+#[derive(Clone, Debug)]
+pub struct StreamEvent {
+    // None for streamers without a discord user
+    pub discord_user_id: Option<UserId>,
+    pub streamer_user_login: String,
+    pub is_streaming: bool,
+    // announce text rendered from the template of the event kind
+    pub text: String,
+    // guilds where the bot knows the streamer, for the sinks of a single guild
+    pub guild_ids: Vec<GuildId>,
+}
+
+impl StreamEvent {
+    pub fn new(
+        discord_user_id: Option<UserId>,
+        streamer_user_login: &str,
+        is_streaming: bool,
+        templates: &AnnounceTemplates,
+        guild_ids: Vec<GuildId>,
+    ) -> StreamEvent {
+        let url = stream_url(streamer_user_login);
+        StreamEvent {
+            discord_user_id,
+            streamer_user_login: streamer_user_login.to_owned(),
+            is_streaming,
+            text: templates.render(
+                event_kind(is_streaming),
+                &[("login", streamer_user_login), ("url", &url)],
+            ),
+            guild_ids,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        stream_url(&self.streamer_user_login)
+    }
+
+    fn kind(&self) -> EventKind {
+        event_kind(self.is_streaming)
+    }
+
+    /// true if the event is of this kind and of a streamer of this guild, of any guild if None
+    fn matches(&self, events: &[EventKind], guild_id: Option<NonZeroU64>) -> bool {
+        events.contains(&self.kind())
+            && guild_id.is_none_or(|guild_id| self.guild_ids.contains(&GuildId::from(guild_id)))
+    }
+}
+
+fn event_kind(is_streaming: bool) -> EventKind {
+    match is_streaming {
+        true => EventKind::Online,
+        false => EventKind::Offline,
+    }
+}
+
+fn stream_url(streamer_user_login: &str) -> String {
+    format!("https://twitch.tv/{}", streamer_user_login)
+}
+
+/// destination of the stream events
+#[async_trait]
+pub trait NotificationSink: Send + Sync + Debug {
+    /// used in logs and error notifications
+    fn name(&self) -> &str;
+
+    /// false if the sink isn't configured to receive this event
+    fn accepts(&self, _event: &StreamEvent) -> bool {
+        true
+    }
+
+    async fn notify(&self, event: &StreamEvent) -> anyhow::Result<()>;
+}
+
+/// the announce channel, always enabled
+#[derive(Debug)]
+pub struct DiscordSink {
+    pub ctx: serenity::Context,
+    pub twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+}
+
+#[async_trait]
+impl NotificationSink for DiscordSink {
+    fn name(&self) -> &str {
+        "discord"
+    }
+
+    async fn notify(&self, event: &StreamEvent) -> anyhow::Result<()> {
+        announce_stream_event(&self.ctx, self.twitch.clone(), event).await
+    }
+}
+
+/// POST the event as json
+#[derive(Debug)]
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+    events: Vec<EventKind>,
+    guild_id: Option<NonZeroU64>,
+}
+
+#[async_trait]
+impl NotificationSink for WebhookSink {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn accepts(&self, event: &StreamEvent) -> bool {
+        event.matches(&self.events, self.guild_id)
+    }
+
+    async fn notify(&self, event: &StreamEvent) -> anyhow::Result<()> {
+        self.client
+            .post(self.url.as_str())
+            .json(&json!({
+                "streamer_user_login": event.streamer_user_login,
+                "is_streaming": event.is_streaming,
+                "url": event.url(),
+                "text": event.text,
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// post the event as a text message in a room, the user of access_token must have joined it
+pub struct MatrixSink {
+    client: reqwest::Client,
+    homeserver: String,
+    room_id: String,
+    access_token: String,
+    events: Vec<EventKind>,
+    guild_id: Option<NonZeroU64>,
+    // makes transaction ids unique for events sent in the same nanosecond
+    transaction_count: AtomicU64,
+}
+
+// access_token is left out of the logs
+impl Debug for MatrixSink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MatrixSink")
+            .field("homeserver", &self.homeserver)
+            .field("room_id", &self.room_id)
+            .field("events", &self.events)
+            .field("guild_id", &self.guild_id)
+            .finish()
+    }
+}
+
+impl MatrixSink {
+    fn send_url(&self) -> anyhow::Result<Url> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let transaction_id = format!(
+            "samousse-{}-{}",
+            nanos,
+            self.transaction_count.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut url = Url::parse(&self.homeserver)?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow!("Invalid matrix homeserver {}", self.homeserver))?
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                &self.room_id,
+                "send",
+                "m.room.message",
+                &transaction_id,
+            ]);
+        Ok(url)
+    }
+}
+
+#[async_trait]
+impl NotificationSink for MatrixSink {
+    fn name(&self) -> &str {
+        "matrix"
+    }
+
+    fn accepts(&self, event: &StreamEvent) -> bool {
+        event.matches(&self.events, self.guild_id)
+    }
+
+    async fn notify(&self, event: &StreamEvent) -> anyhow::Result<()> {
+        self.client
+            .put(self.send_url()?)
+            .bearer_auth(&self.access_token)
+            .json(&json!({
+                "msgtype": "m.text",
+                "body": event.text,
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// create the sinks other than Discord from the configuration
+pub fn build_sinks(configs: &[SinkConfig]) -> anyhow::Result<Vec<Arc<dyn NotificationSink>>> {
+    let client = reqwest::Client::builder().timeout(SINK_TIMEOUT).build()?;
+    Ok(configs
+        .iter()
+        .map(|config| -> Arc<dyn NotificationSink> {
+            match config {
+                SinkConfig::Webhook {
+                    url,
+                    events,
+                    guild_id,
+                } => Arc::new(WebhookSink {
+                    client: client.clone(),
+                    url: url.clone(),
+                    events: events.clone(),
+                    guild_id: *guild_id,
+                }),
+                SinkConfig::Matrix {
+                    homeserver,
+                    room_id,
+                    access_token,
+                    events,
+                    guild_id,
+                } => Arc::new(MatrixSink {
+                    client: client.clone(),
+                    homeserver: homeserver.clone(),
+                    room_id: room_id.clone(),
+                    access_token: access_token.clone(),
+                    events: events.clone(),
+                    guild_id: *guild_id,
+                    transaction_count: AtomicU64::new(0),
+                }),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(is_streaming: bool, guild_ids: Vec<GuildId>) -> StreamEvent {
+        StreamEvent::new(
+            None,
+            "streamer",
+            is_streaming,
+            &AnnounceTemplates::new(Default::default()),
+            guild_ids,
+        )
+    }
+
+    #[test]
+    fn matches_only_configured_events() {
+        let online = event(true, vec![]);
+        assert!(online.matches(&[EventKind::Online], None));
+        assert!(!online.matches(&[EventKind::Offline], None));
+    }
+
+    #[test]
+    fn matches_only_streamers_of_the_guild() {
+        let guild_id = NonZeroU64::new(42).unwrap();
+        assert!(event(true, vec![GuildId::new(42)]).matches(&[EventKind::Online], Some(guild_id)));
+        assert!(!event(true, vec![GuildId::new(7)]).matches(&[EventKind::Online], Some(guild_id)));
+        assert!(!event(true, vec![]).matches(&[EventKind::Online], Some(guild_id)));
+    }
+}
@@ -0,0 +1,55 @@
+use std::time::{Duration, SystemTime};
+
+use poise::serenity_prelude as serenity;
+use tracing::info;
+
+use crate::discord::{DiscordContext, Error};
+
+/// suppress the renames and announces of a streamer for a while
+#[poise::command(slash_command)]
+pub async fn snooze(
+    ctx: DiscordContext<'_>,
+    #[description = "Streamer to snooze"] streamer: serenity::User,
+    #[description = "How long, like 30m or 1h, 0s to stop the snooze"] duration: String,
+) -> Result<(), Error> {
+    let is_trusted = ctx
+        .data()
+        .trusted_users_ids
+        .contains(&ctx.author().id.get());
+    let text = if !is_trusted && ctx.author().id != streamer.id {
+        String::from("You can only snooze yourself")
+    } else {
+        match humantime::parse_duration(&duration) {
+            Err(why) => format!("Invalid duration {} : {}", duration, why),
+            Ok(duration) => set_snooze(ctx, &streamer, duration).await,
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+async fn set_snooze(
+    ctx: DiscordContext<'_>,
+    streamer: &serenity::User,
+    duration: Duration,
+) -> String {
+    let mut writer = ctx.data().twitch.write().await;
+    let Some(user) = writer.users.get_mut(&streamer.id) else {
+        return format!("{} isn't a monitored streamer", streamer.name);
+    };
+    let text = if duration.is_zero() {
+        user.snoozed_until = None;
+        format!("{} isn't snoozed anymore", streamer.name)
+    } else {
+        user.snoozed_until = Some(SystemTime::now() + duration);
+        format!(
+            "{} is snoozed for {}",
+            streamer.name,
+            humantime::format_duration(duration)
+        )
+    };
+    info!("{} : {}", ctx.author().id, text);
+    writer.save_snoozes();
+    text
+}
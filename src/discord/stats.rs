@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use sysinfo::{get_current_pid, System};
+
+use crate::discord::twitch::format_duration;
+use crate::discord::{random_stuff::is_trusted, DiscordContext, Error};
+
+/// show the resources used by the bot process
+/// tokio task count isn't available without tokio_unstable
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn stats(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let pid = get_current_pid()?;
+    let mut system = System::new();
+    system.refresh_process(pid);
+    let process = system
+        .process(pid)
+        .ok_or("Can't read the bot process stats")?;
+
+    let threads = match process.tasks() {
+        Some(tasks) => tasks.len().to_string(),
+        None => String::from("unknown"),
+    };
+    let (guild_count, channel_count) = {
+        let cache = ctx.cache();
+        let guilds = cache.guilds();
+        let channel_count: usize = guilds
+            .iter()
+            .filter_map(|g| cache.guild(g).map(|g| g.channels.len()))
+            .sum();
+        (guilds.len(), channel_count)
+    };
+
+    let text = format!(
+        "Memory : {} MiB\n\
+        Threads : {}\n\
+        Cached guilds : {}\n\
+        Cached channels : {}\n\
+        Uptime : {}",
+        process.memory() / 1024 / 1024,
+        threads,
+        guild_count,
+        channel_count,
+        format_duration(Duration::from_secs(process.run_time()))
+    );
+    ctx.say(text).await?;
+    Ok(())
+}
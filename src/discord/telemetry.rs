@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use serde::Serialize;
+use tokio::time::sleep;
+use tracing::{debug, info, warn};
+
+use crate::config::TelemetryConfig;
+
+/// aggregate usage counters, without any message content or user id
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    commands: Mutex<HashMap<String, u64>>,
+    events: AtomicU64,
+}
+
+#[derive(Serialize)]
+struct TelemetryReport {
+    version: &'static str,
+    commands: HashMap<String, u64>,
+    events: u64,
+    guilds: usize,
+}
+
+impl Telemetry {
+    pub fn record_command(&self, name: &str) {
+        *self
+            .commands
+            .lock()
+            .unwrap()
+            .entry(name.to_owned())
+            .or_default() += 1;
+    }
+
+    pub fn record_event(&self) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn report(&self, guilds: usize) -> TelemetryReport {
+        TelemetryReport {
+            version: env!("CARGO_PKG_VERSION"),
+            commands: self.commands.lock().unwrap().clone(),
+            events: self.events.load(Ordering::Relaxed),
+            guilds,
+        }
+    }
+}
+
+/// export the counters periodically to the configured destination
+pub async fn run_telemetry(
+    ctx: serenity::Context,
+    client: reqwest::Client,
+    telemetry: Arc<Telemetry>,
+    config: TelemetryConfig,
+) {
+    info!(
+        "Telemetry is enabled, exporting usage counters to {:?}",
+        config
+    );
+    loop {
+        sleep(Duration::from_secs(config.interval_seconds())).await;
+        let report = telemetry.report(ctx.cache.guild_count());
+        let result = match &config {
+            TelemetryConfig::File { path, .. } => serde_json::to_vec(&report)
+                .map_err(anyhow::Error::from)
+                .and_then(|json| std::fs::write(path, json).map_err(anyhow::Error::from)),
+            TelemetryConfig::Http { url, .. } => client
+                .post(url.as_str())
+                .json(&report)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map(|_| ())
+                .map_err(anyhow::Error::from),
+        };
+        match result {
+            Ok(()) => debug!("Telemetry exported"),
+            Err(why) => warn!("Error on exporting telemetry {}", why),
+        }
+    }
+}
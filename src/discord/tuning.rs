@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use poise::ChoiceParameter;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::discord::{random_stuff::is_trusted, DiscordContext, DiscordTwitchWatcher, Error};
+
+/// timer of the twitch watcher which can be changed without restarting the bot
+#[derive(ChoiceParameter, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TuningKey {
+    #[name = "rename_cooldown"]
+    RenameCooldown,
+    #[name = "min_stream_duration"]
+    MinStreamDuration,
+    #[name = "offline_restore"]
+    OfflineRestore,
+}
+
+impl TuningKey {
+    const ALL: [TuningKey; 3] = [
+        TuningKey::RenameCooldown,
+        TuningKey::MinStreamDuration,
+        TuningKey::OfflineRestore,
+    ];
+
+    /// longest value accepted
+    fn max(self) -> Duration {
+        match self {
+            TuningKey::RenameCooldown => Duration::from_secs(600),
+            TuningKey::MinStreamDuration => Duration::from_secs(3600),
+            TuningKey::OfflineRestore => Duration::from_secs(86400),
+        }
+    }
+
+    /// current value, None if it's disabled
+    fn get(self, twitch: &DiscordTwitchWatcher) -> Option<Duration> {
+        match self {
+            TuningKey::RenameCooldown => Some(twitch.rename_cooldown),
+            TuningKey::MinStreamDuration => Some(twitch.min_stream_duration),
+            TuningKey::OfflineRestore => twitch.offline_restore,
+        }
+    }
+
+    /// change the live value, 0 disables offline_restore
+    fn set(self, twitch: &mut DiscordTwitchWatcher, value: Duration) {
+        match self {
+            TuningKey::RenameCooldown => twitch.rename_cooldown = value,
+            TuningKey::MinStreamDuration => twitch.min_stream_duration = value,
+            TuningKey::OfflineRestore => {
+                twitch.offline_restore = Some(value).filter(|v| !v.is_zero())
+            }
+        }
+    }
+}
+
+/// apply the values set by /tuning_set over the ones of the config
+pub fn apply_tuning(twitch: &mut DiscordTwitchWatcher) {
+    for (key, seconds) in twitch.tuning.clone() {
+        key.set(twitch, Duration::from_secs(seconds));
+    }
+}
+
+fn format_value(value: Option<Duration>) -> String {
+    match value {
+        Some(value) => humantime::format_duration(value).to_string(),
+        None => String::from("disabled"),
+    }
+}
+
+/// show the timers of the twitch watcher that /tuning_set changes
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn tuning_show(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let text = {
+        let reader = ctx.data().twitch.read().await;
+        TuningKey::ALL
+            .iter()
+            .map(|key| {
+                format!(
+                    "{} : {}{}",
+                    key.name(),
+                    format_value(key.get(&reader)),
+                    match reader.tuning.contains_key(key) {
+                        true => " (set by /tuning_set)",
+                        false => "",
+                    }
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
+
+/// change a timer of the twitch watcher, kept over the config value across restarts
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn tuning_set(
+    ctx: DiscordContext<'_>,
+    #[description = "Timer to change"] key: TuningKey,
+    #[description = "New value, like 30s or 5m, 0s disables offline_restore"] value: String,
+) -> Result<(), Error> {
+    let text = match humantime::parse_duration(&value) {
+        Err(why) => format!("Invalid duration {} : {}", value, why),
+        Ok(value) if value > key.max() => format!(
+            "{} must not be longer than {}",
+            key.name(),
+            humantime::format_duration(key.max())
+        ),
+        Ok(value) => {
+            let mut writer = ctx.data().twitch.write().await;
+            key.set(&mut writer, value);
+            writer.tuning.insert(key, value.as_secs());
+            writer.save_tuning();
+            info!("{} set {} to {:?}", ctx.author().name, key.name(), value);
+            format!("{} is now {}", key.name(), format_value(key.get(&writer)))
+        }
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
@@ -1,18 +1,89 @@
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
+use chrono::Utc;
+use futures::future::join_all;
 use poise::serenity_prelude as serenity;
-use serenity::all::{ChannelId, EditChannel, UserId};
+use serenity::all::{ChannelId, ChannelType, EditChannel, GuildId, Mentionable, UserId};
 use serenity::http::Route;
-use tokio::sync::mpsc::Receiver;
-use tokio::sync::RwLock;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::{oneshot, Mutex, RwLock};
+use tokio::time::{sleep, timeout};
 use tracing::{debug, error, info, trace, warn};
 
+use crate::config::{validate_channel_name, AckStyle, RenameMode, CHANNEL_NAME_MAX_LENGTH};
+use crate::discord::announce::{is_transient_error, retry_announce_stream_event};
+use crate::discord::notify::notify_subscribers;
+use crate::discord::sink::{DiscordSink, NotificationSink, StreamEvent};
 use crate::discord::{
-    random_stuff::is_trusted, Channel, DiscordContext, DiscordTwitchWatcher, Error,
+    random_stuff::{acknowledge, is_trusted, split_message, MESSAGE_MAX_LENGTH},
+    Channel, ChannelNaming, Data, DiscordContext, DiscordTwitchWatcher, Error, HeldAnnounce,
 };
-use crate::inter_comm::{InterComm, MessageType};
+use crate::inter_comm::{InterComm, MessageType, RateLimitInfo, StreamerEvent, TwitchRequest};
+use crate::quiet_hours::QuietHours;
+use crate::twitch::chat::CHAT_SCOPE;
+
+// time to wait for the twitch side to answer a request
+const TWITCH_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+// how often channels with the offline name are checked for offline_restore
+const OFFLINE_RESTORE_TICK: Duration = Duration::from_secs(30);
+
+// how often the end of quiet hours is checked to send the held announces
+const QUIET_HOURS_TICK: Duration = Duration::from_secs(60);
+
+// twitch features with the scope they need, None if they need none, and whether the bot
+// uses them
+const TWITCH_FEATURES: [(&str, Option<&str>, bool); 5] = [
+    ("Stream online and offline events", None, true),
+    ("Chat relay", Some(CHAT_SCOPE), true),
+    ("Subscriptions", Some("channel:read:subscriptions"), false),
+    ("Follows", Some("moderator:read:followers"), false),
+    ("Bits", Some("bits:read"), false),
+];
+
+// maximum number of choices Discord accepts in an autocomplete
+const AUTOCOMPLETE_MAX_CHOICES: usize = 25;
+
+// time to wait before restarting the twitch event handler after it failed
+const EVENT_HANDLER_RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// run twitch_event_handler, restarting it if it fails or panics so that a single bad event
+/// doesn't stop the processing of the next ones
+pub async fn supervise_twitch_event_handler(
+    ctx: serenity::Context,
+    receiver: Receiver<InterComm>,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+) {
+    // shared so that the receiver survives a panic of the handler
+    let receiver = Arc::new(Mutex::new(receiver));
+    loop {
+        let handler = {
+            let ctx = ctx.clone();
+            let receiver = receiver.clone();
+            let twitch = twitch.clone();
+            tokio::spawn(async move {
+                twitch_event_handler(&ctx, &mut *receiver.lock().await, twitch).await
+            })
+        };
+        match handler.await {
+            Ok(Ok(())) => {
+                info!("Twitch event channel closed, stopping its handler");
+                return;
+            }
+            Ok(Err(why)) => error!("Twitch event handler failed, restarting it {}", why),
+            Err(why) if why.is_panic() => {
+                error!("Twitch event handler panicked, restarting it {}", why)
+            }
+            Err(why) => {
+                error!("Twitch event handler has been cancelled {}", why);
+                return;
+            }
+        }
+        sleep(EVENT_HANDLER_RESTART_DELAY).await;
+    }
+}
 
 pub async fn twitch_event_handler(
     ctx: &serenity::Context,
@@ -20,45 +91,163 @@ pub async fn twitch_event_handler(
     twitch: Arc<RwLock<DiscordTwitchWatcher>>,
 ) -> anyhow::Result<()> {
     while let Some(item) = receiver.recv().await {
+        // chat messages are too frequent to be kept in the history
+        if let InterComm::Streamer(StreamerEvent {
+            message_type: MessageType::ChatMessage(message),
+            streamer_user_login,
+            ..
+        }) = &item
+        {
+            if let Some(chat_relay) = &twitch.read().await.chat_relay {
+                chat_relay.relay(streamer_user_login, message);
+            }
+            continue;
+        }
+        {
+            let mut writer = twitch.write().await;
+            writer.event_history.record(&item);
+            writer.telemetry.record_event();
+        }
+        let item = match item {
+            InterComm::Streamer(item) => item,
+            InterComm::Error { kind, message } => {
+                let error_notifier = twitch.read().await.error_notifier.clone();
+                error_notifier
+                    .notify(
+                        &ctx.http,
+                        &format!("twitch {}", kind),
+                        &format!("Twitch : {}", message),
+                    )
+                    .await;
+                continue;
+            }
+        };
         match item.message_type {
-            MessageType::TwitchStreamOnline => {
-                debug!("Handling twitch stream online event");
+            MessageType::TwitchStreamOnline { .. } | MessageType::TwitchStreamOffline => {
+                let (is_streaming, is_backlog, category) = match item.message_type {
+                    MessageType::TwitchStreamOnline {
+                        is_backlog,
+                        category,
+                    } => (true, is_backlog, category),
+                    _ => (false, false, None),
+                };
+                debug!(
+                    "Handling twitch stream {} event for {}",
+                    if is_streaming { "online" } else { "offline" },
+                    item.streamer_user_login
+                );
+                let Ok(streamer_user_id) = item.streamer_user_id.parse() else {
+                    warn!(
+                        "Invalid twitch user id {} for {}",
+                        item.streamer_user_id, item.streamer_user_login
+                    );
+                    continue;
+                };
                 if let Err(why) = handle_stream_event(
                     ctx,
                     twitch.clone(),
-                    item.streamer_user_id.parse().unwrap(),
-                    true,
+                    streamer_user_id,
+                    &item.streamer_user_login,
+                    is_streaming,
+                    is_backlog,
+                    category,
                 )
                 .await
                 {
-                    error!("Error on twitch stream online event handling {}", why);
+                    error!("Error on twitch stream event handling {}", why);
                 }
             }
-            MessageType::TwitchStreamOffline => {
-                debug!("Handling twitch stream offline event");
-                if let Err(why) = handle_stream_event(
-                    ctx,
-                    twitch.clone(),
-                    item.streamer_user_id.parse().unwrap(),
-                    false,
-                )
-                .await
+            MessageType::TwitchCategoryUpdate { category } => {
+                let Ok(streamer_user_id) = item.streamer_user_id.parse() else {
+                    warn!(
+                        "Invalid twitch user id {} for {}",
+                        item.streamer_user_id, item.streamer_user_login
+                    );
+                    continue;
+                };
+                if let Err(why) =
+                    handle_category_update(ctx, twitch.clone(), streamer_user_id, category).await
                 {
-                    error!("Error on twitch stream online event handling {}", why);
+                    error!("Error on twitch category update handling {}", why);
                 }
             }
+            // relayed above
+            MessageType::ChatMessage(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// rename again the channels renamed for a live streamer whose category changed, so that
+/// {game} shows the new one, updates of streamers who aren't live are ignored
+pub async fn handle_category_update(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    streamer_user_id: u64,
+    category: String,
+) -> anyhow::Result<()> {
+    let discord_user_id = {
+        let mut writer = twitch.write().await;
+        let Some(user) = writer.find_user_by_twitch_id_mut(streamer_user_id) else {
+            trace!(
+                "Twitch user {} isn't linked to a discord user",
+                streamer_user_id
+            );
+            return Ok(());
+        };
+        if user.twitch_is_streaming != Some(true) {
+            debug!(
+                "Ignoring category update of {}, they aren't live",
+                user.discord_id
+            );
+            return Ok(());
+        }
+        if user.twitch_category.as_ref() == Some(&category) {
+            return Ok(());
+        }
+        user.twitch_category = Some(category);
+        if user.is_snoozed() {
+            return Ok(());
+        }
+        user.discord_id
+    };
+    let channel_ids = twitch
+        .read()
+        .await
+        .find_channels_renamed_for(&discord_user_id);
+    for channel_id in channel_ids {
+        if let Some((channel_id, name, reason)) = get_channel_new_name(
+            ctx,
+            twitch.clone(),
+            &discord_user_id,
+            &channel_id,
+            true,
+            true,
+        )
+        .await?
+        {
+            apply_new_name(ctx, twitch.clone(), channel_id, name, reason).await;
         }
     }
     Ok(())
 }
 
+/// is_backlog is true for streams already live before we first knew their status,
+/// they aren't announced since they aren't newly live
+/// category is the one a starting stream has, so the first rename already shows it
 pub async fn handle_stream_event(
     ctx: &serenity::Context,
     twitch: Arc<RwLock<DiscordTwitchWatcher>>,
     streamer_user_id: u64,
+    streamer_user_login: &str,
     is_streaming: bool,
+    is_backlog: bool,
+    category: Option<String>,
 ) -> anyhow::Result<()> {
     let mut discord_user_id: Option<UserId> = None;
+    // streaming status before this event, None if it wasn't known yet
+    let mut was_streaming: Option<bool> = None;
+    let mut is_snoozed = false;
     match twitch
         .write()
         .await
@@ -66,61 +255,458 @@ pub async fn handle_stream_event(
     {
         Some(u) => {
             discord_user_id = Some(u.discord_id);
+            was_streaming = u.twitch_is_streaming;
+            is_snoozed = u.is_snoozed();
+            u.twitch_login = Some(streamer_user_login.to_owned());
             if u.twitch_is_streaming != Some(is_streaming) {
-                u.twitch_is_streaming = Some(is_streaming);
+                u.set_twitch_is_streaming(Some(is_streaming));
+                if is_streaming {
+                    u.twitch_category = category;
+                }
             } else {
                 debug!(
-                    "Discord user {} twitch streaming status hasn't changed",
-                    u.discord_id
+                    "Discord user {} twitch streaming status hasn't changed since {:?}",
+                    u.discord_id, u.last_twitch_is_streaming_update
                 );
                 return Ok(());
             }
         }
-        None => warn!("Unknown user {:?} from twitch side", streamer_user_id),
+        None => debug!(
+            "Twitch user {} isn't linked to a discord user",
+            streamer_user_id
+        ),
     }
-    if let Some(discord_user_id) = discord_user_id {
-        if let Some(channel_id) =
-            find_current_user_voice_channel(ctx, twitch.clone(), &discord_user_id).await?
+    let Some(discord_user_id) = discord_user_id else {
+        // streamers without a discord user, like team members, are only announced
+        let was_streaming = {
+            let mut writer = twitch.write().await;
+            match is_streaming {
+                true => !writer.unlinked_live.insert(streamer_user_id),
+                false => writer.unlinked_live.remove(&streamer_user_id),
+            }
+        };
+        if was_streaming != is_streaming {
+            twitch.read().await.request_presence_update();
+        }
+        if was_streaming == is_streaming || is_backlog {
+            return Ok(());
+        }
+        let min_stream_duration = twitch.read().await.min_stream_duration;
+        if !is_streaming
+            && twitch
+                .write()
+                .await
+                .take_deferred_announce(streamer_user_id, None)
         {
-            rename_channel(ctx, twitch, &discord_user_id, &channel_id, is_streaming).await?;
+            debug!(
+                "Stream of {} ended before being announced",
+                streamer_user_login
+            );
+        } else if is_streaming && !min_stream_duration.is_zero() {
+            defer_announce(ctx, twitch, streamer_user_id, None, streamer_user_login).await;
         } else {
-            debug!("Discord user {} not found in channel", discord_user_id);
+            announce(ctx, twitch, None, streamer_user_login, is_streaming).await;
+        }
+        return Ok(());
+    };
+
+    twitch.read().await.request_presence_update();
+
+    if is_snoozed {
+        info!(
+            "Discord user {} is snoozed, skipping announce and rename",
+            discord_user_id
+        );
+        return Ok(());
+    }
+
+    let (min_stream_duration, defer_rename) = {
+        let reader = twitch.read().await;
+        (reader.min_stream_duration, reader.defer_rename)
+    };
+    let is_deferred = is_streaming && !is_backlog && !min_stream_duration.is_zero();
+    // a stream we never saw online hasn't been announced, so its end isn't either
+    if is_backlog {
+        debug!(
+            "Stream of {} was already live, not announcing it",
+            streamer_user_login
+        );
+    } else if !is_streaming
+        && twitch
+            .write()
+            .await
+            .take_deferred_announce(streamer_user_id, None)
+    {
+        debug!(
+            "Stream of {} ended before being announced",
+            streamer_user_login
+        );
+    } else if is_deferred {
+        defer_announce(
+            ctx,
+            twitch.clone(),
+            streamer_user_id,
+            Some(discord_user_id),
+            streamer_user_login,
+        )
+        .await;
+        if defer_rename {
+            return Ok(());
         }
+    } else if is_streaming || was_streaming.is_some() {
+        announce(
+            ctx,
+            twitch.clone(),
+            Some(discord_user_id),
+            streamer_user_login,
+            is_streaming,
+        )
+        .await;
+    }
+    if is_streaming && !is_backlog && !is_deferred {
+        notify_subscribers(ctx, twitch.clone(), &discord_user_id, streamer_user_login).await;
+    }
+    // the announce above doesn't depend on the streamer being in a voice channel,
+    // renaming it is only a side effect
+    let mut channel_ids = Vec::new();
+    if let Some(channel_id) =
+        find_current_user_voice_channel(ctx, twitch.clone(), &discord_user_id).await?
+    {
+        channel_ids.push(channel_id);
     } else {
-        return Err(anyhow!("Unknown twitch user id {}", streamer_user_id));
+        debug!("Discord user {} not found in channel", discord_user_id);
+    }
+    if !is_streaming {
+        // the streamer may have left the channels renamed during the stream before going
+        // offline, they are all restored and the session ends
+        let mut writer = twitch.write().await;
+        for channel_id in writer.find_session_channels(&discord_user_id) {
+            if !channel_ids.contains(&channel_id) {
+                channel_ids.push(channel_id);
+            }
+        }
+        if let Some(user) = writer.users.get_mut(&discord_user_id) {
+            user.session_channels.clear();
+        }
+    }
+    for channel_id in channel_ids {
+        rename_channel(
+            ctx,
+            twitch.clone(),
+            &discord_user_id,
+            &channel_id,
+            is_streaming,
+        )
+        .await?;
     }
     Ok(())
 }
 
+/// announce the stream once it has been online for min_stream_duration, unless it ended before
+/// renames are made at the same time if defer_rename is set
+async fn defer_announce(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    streamer_user_id: u64,
+    discord_user_id: Option<UserId>,
+    streamer_user_login: &str,
+) {
+    // registered before returning so that an offline event handled next cancels it
+    let (announce_id, min_stream_duration) = {
+        let mut writer = twitch.write().await;
+        (
+            writer.defer_announce(streamer_user_id),
+            writer.min_stream_duration,
+        )
+    };
+    let ctx = ctx.clone();
+    let streamer_user_login = streamer_user_login.to_owned();
+    tokio::spawn(async move {
+        debug!(
+            "Announce of {} deferred by {}s",
+            streamer_user_login,
+            min_stream_duration.as_secs()
+        );
+        sleep(min_stream_duration).await;
+        if !twitch
+            .write()
+            .await
+            .take_deferred_announce(streamer_user_id, Some(announce_id))
+        {
+            return;
+        }
+        announce(
+            &ctx,
+            twitch.clone(),
+            discord_user_id,
+            &streamer_user_login,
+            true,
+        )
+        .await;
+        let Some(discord_user_id) = discord_user_id else {
+            return;
+        };
+        notify_subscribers(&ctx, twitch.clone(), &discord_user_id, &streamer_user_login).await;
+        if twitch.read().await.defer_rename {
+            if let Err(why) = settle_streamer_channels(&ctx, twitch, &discord_user_id).await {
+                error!("Error on renaming channels after deferred announce {}", why);
+            }
+        }
+    });
+}
+
+/// send the stream event to Discord and the other matching sinks,
+/// retrying in background while a sink is unavailable
+/// during quiet hours the event is held until they end, or dropped
+async fn announce(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    discord_user_id: Option<UserId>,
+    streamer_user_login: &str,
+    is_streaming: bool,
+) {
+    {
+        let mut writer = twitch.write().await;
+        if let Some(quiet_hours) = writer.quiet_hours.clone() {
+            if quiet_hours.is_quiet(Utc::now()) {
+                hold_announce(
+                    &mut writer,
+                    &quiet_hours,
+                    discord_user_id,
+                    streamer_user_login,
+                    is_streaming,
+                );
+                return;
+            }
+        }
+    }
+    send_announce(
+        ctx,
+        twitch,
+        discord_user_id,
+        streamer_user_login,
+        is_streaming,
+    )
+    .await;
+}
+
+fn hold_announce(
+    writer: &mut DiscordTwitchWatcher,
+    quiet_hours: &QuietHours,
+    discord_user_id: Option<UserId>,
+    streamer_user_login: &str,
+    is_streaming: bool,
+) {
+    if !quiet_hours.hold {
+        info!(
+            "Quiet hours, dropping the announce of {}",
+            streamer_user_login
+        );
+        return;
+    }
+    // a stream that started and ended during quiet hours isn't announced at all
+    if !is_streaming
+        && writer
+            .held_announces
+            .get(streamer_user_login)
+            .is_some_and(|held| held.is_streaming)
+    {
+        info!(
+            "Quiet hours, stream of {} ended before being announced",
+            streamer_user_login
+        );
+        writer.held_announces.remove(streamer_user_login);
+        return;
+    }
+    info!(
+        "Quiet hours, holding the announce of {}",
+        streamer_user_login
+    );
+    writer.held_announces.insert(
+        streamer_user_login.to_owned(),
+        HeldAnnounce {
+            discord_user_id,
+            is_streaming,
+        },
+    );
+}
+
+/// announce the events held during quiet hours once they end
+pub async fn run_held_announces(ctx: serenity::Context, twitch: Arc<RwLock<DiscordTwitchWatcher>>) {
+    loop {
+        sleep(QUIET_HOURS_TICK).await;
+        let held = {
+            let mut writer = twitch.write().await;
+            if writer
+                .quiet_hours
+                .as_ref()
+                .is_some_and(|q| q.is_quiet(Utc::now()))
+            {
+                continue;
+            }
+            std::mem::take(&mut writer.held_announces)
+        };
+        for (streamer_user_login, held) in held {
+            info!("Quiet hours ended, announcing {}", streamer_user_login);
+            send_announce(
+                &ctx,
+                twitch.clone(),
+                held.discord_user_id,
+                &streamer_user_login,
+                held.is_streaming,
+            )
+            .await;
+        }
+    }
+}
+
+async fn send_announce(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    discord_user_id: Option<UserId>,
+    streamer_user_login: &str,
+    is_streaming: bool,
+) {
+    let guild_ids = match discord_user_id {
+        Some(user_id) => find_user_guilds(ctx, &user_id),
+        None => vec![],
+    };
+    let event = StreamEvent::new(
+        discord_user_id,
+        streamer_user_login,
+        is_streaming,
+        &twitch.read().await.announce_templates,
+        guild_ids,
+    );
+    let mut sinks: Vec<Arc<dyn NotificationSink>> = vec![];
+    if twitch.read().await.announce_enabled {
+        sinks.push(Arc::new(DiscordSink {
+            ctx: ctx.clone(),
+            twitch: twitch.clone(),
+        }));
+    }
+    sinks.extend(
+        twitch
+            .read()
+            .await
+            .notification_sinks
+            .iter()
+            .filter(|sink| sink.accepts(&event))
+            .cloned(),
+    );
+    // a slow sink doesn't delay the others
+    join_all(
+        sinks
+            .into_iter()
+            .map(|sink| send_to_sink(ctx, twitch.clone(), sink, &event)),
+    )
+    .await;
+}
+
+async fn send_to_sink(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    sink: Arc<dyn NotificationSink>,
+    event: &StreamEvent,
+) {
+    let Err(why) = sink.notify(event).await else {
+        return;
+    };
+    if is_transient_error(&why) {
+        warn!(
+            "{} is unavailable, will retry the announce {}",
+            sink.name(),
+            why
+        );
+        let since = Instant::now();
+        let ctx = ctx.clone();
+        let event = event.clone();
+        tokio::spawn(async move {
+            if let Err(why) = retry_announce_stream_event(sink, twitch.clone(), &event, since).await
+            {
+                notify_announce_error(&ctx, twitch, &event.streamer_user_login, why).await;
+            }
+        });
+    } else {
+        notify_announce_error(ctx, twitch, &event.streamer_user_login, why).await;
+    }
+}
+
+/// guilds in cache where the user is a known member or in a voice channel
+fn find_user_guilds(ctx: &serenity::Context, user_id: &UserId) -> Vec<GuildId> {
+    ctx.cache
+        .guilds()
+        .into_iter()
+        .filter(|guild_id| {
+            ctx.cache.guild(*guild_id).is_some_and(|guild| {
+                guild.members.contains_key(user_id) || guild.voice_states.contains_key(user_id)
+            })
+        })
+        .collect()
+}
+
+async fn notify_announce_error(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    streamer_user_login: &str,
+    why: anyhow::Error,
+) {
+    error!("Error on announcing stream event {}", why);
+    let error_notifier = twitch.read().await.error_notifier.clone();
+    error_notifier
+        .notify(
+            &ctx.http,
+            "announce",
+            &format!("Can't announce stream of {} : {}", streamer_user_login, why),
+        )
+        .await;
+}
+
 pub async fn find_current_user_voice_channel(
     ctx: &serenity::Context,
     twitch: Arc<RwLock<DiscordTwitchWatcher>>,
     discord_user_id: &UserId,
 ) -> anyhow::Result<Option<ChannelId>> {
-    let mut ret: Option<ChannelId> = None;
-    if let Some(user) = twitch.read().await.users.get(discord_user_id) {
-        if user.has_been_part_of_voice_state_event {
-            ret = user.current_channel_id;
-        } else {
-            debug!("searching user current voice channel slow way");
-            for server in twitch.read().await.servers.iter() {
-                if let Some(guild) = ctx.cache.guild(*server) {
-                    if let Some(streamer) = guild.voice_states.get(&user.discord_id) {
-                        ret = streamer.channel_id;
-                    }
-                }
+    let (cached_channel_id, servers) = {
+        let reader = twitch.read().await;
+        match reader.users.get(discord_user_id) {
+            Some(user) => (
+                user.has_been_part_of_voice_state_event
+                    .then_some(user.current_channel_id),
+                reader.servers.clone(),
+            ),
+            None => {
+                warn!("Discord user {} doesn't exist", discord_user_id);
+                return Ok(None);
             }
         }
-    }
+    };
 
-    if let Some(channel_id) = ret {
-        if let Some(user) = twitch.write().await.users.get_mut(discord_user_id) {
-            user.has_been_part_of_voice_state_event = true;
-            user.current_channel_id = Some(channel_id);
-        } else {
-            warn!("Discord user {} doesn't exist", discord_user_id);
+    // a missed voice state event can leave the cached channel stale, so the live voice
+    // state from the guild cache takes precedence when available
+    let ret = match find_live_user_voice_channel(ctx, &servers, discord_user_id) {
+        Some(live_channel_id) => {
+            if let Some(cached_channel_id) = cached_channel_id {
+                if cached_channel_id != live_channel_id {
+                    warn!(
+                        "Discord user {} was tracked in {:?} but is in {:?}, correcting it",
+                        discord_user_id, cached_channel_id, live_channel_id
+                    );
+                }
+            }
+            twitch
+                .write()
+                .await
+                .set_user_voice_channel(discord_user_id, live_channel_id);
+            live_channel_id
         }
-    } else {
+        None => {
+            debug!("No configured guild in cache, using tracked voice channel");
+            cached_channel_id.flatten()
+        }
+    };
+
+    if ret.is_none() {
         debug!(
             "Discord user {} not found in voice channel",
             discord_user_id
@@ -130,26 +716,261 @@ pub async fn find_current_user_voice_channel(
     Ok(ret)
 }
 
-pub async fn get_channel_new_name<'a>(
-    ctx: &'a serenity::Context,
+/// correct the tracked voice channels of the monitored users from the guild cache
+pub async fn reconcile_voice_states(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+) {
+    let mut writer = twitch.write().await;
+    let servers = writer.servers.clone();
+    let user_ids: Vec<UserId> = writer.users.keys().copied().collect();
+    for user_id in user_ids {
+        if let Some(live_channel_id) = find_live_user_voice_channel(ctx, &servers, &user_id) {
+            if writer
+                .users
+                .get(&user_id)
+                .is_some_and(|u| u.current_channel_id != live_channel_id)
+            {
+                debug!(
+                    "Discord user {} is now tracked in {:?}",
+                    user_id, live_channel_id
+                );
+            }
+            writer.set_user_voice_channel(&user_id, live_channel_id);
+        }
+    }
+}
+
+/// look for the user in the voice states of the cached guilds
+/// returns None if none of the servers are in cache, since the voice state is then unknown
+fn find_live_user_voice_channel(
+    ctx: &serenity::Context,
+    servers: &[GuildId],
+    discord_user_id: &UserId,
+) -> Option<Option<ChannelId>> {
+    let mut ret: Option<Option<ChannelId>> = None;
+    for server in servers {
+        if let Some(guild) = ctx.cache.guild(*server) {
+            match guild.voice_states.get(discord_user_id) {
+                Some(voice_state) if voice_state.channel_id.is_some() => {
+                    return Some(voice_state.channel_id);
+                }
+                _ => ret = Some(None),
+            }
+        }
+    }
+    ret
+}
+
+/// apply to name the changes Discord makes to a submitted channel name
+/// text channels are lowercased and their spaces are replaced by dashes
+pub fn normalize_channel_name(name: &str, kind: ChannelType) -> String {
+    let name = name.trim();
+    let name = match kind {
+        ChannelType::Text | ChannelType::News => {
+            let mut ret = String::with_capacity(name.len());
+            for c in name.chars() {
+                let c = if c.is_whitespace() { '-' } else { c };
+                if !(c == '-' && ret.ends_with('-')) {
+                    ret.extend(c.to_lowercase());
+                }
+            }
+            ret
+        }
+        _ => name.split_whitespace().collect::<Vec<&str>>().join(" "),
+    };
+    name.chars().take(CHANNEL_NAME_MAX_LENGTH).collect()
+}
+
+/// rename the channels of a streaming user who joined, left or moved between voice channels,
+/// once the user stayed rename_cooldown in a channel so that quick moves rename it only once
+pub async fn handle_streamer_move(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    discord_user_id: UserId,
+) -> anyhow::Result<()> {
+    let (rename_cooldown, move_count) = {
+        let mut writer = twitch.write().await;
+        (
+            writer.rename_cooldown,
+            writer.record_voice_move(discord_user_id),
+        )
+    };
+    if rename_cooldown.is_zero() {
+        twitch.write().await.voice_moves.remove(&discord_user_id);
+        return settle_streamer_channels(ctx, twitch, &discord_user_id).await;
+    }
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        sleep(rename_cooldown).await;
+        if !twitch
+            .write()
+            .await
+            .settle_voice_move(discord_user_id, move_count)
+        {
+            trace!("Discord user {} moved again, waiting more", discord_user_id);
+            return;
+        }
+        if let Err(why) = settle_streamer_channels(&ctx, twitch, &discord_user_id).await {
+            error!("Error on renaming channels after voice move {}", why);
+        }
+    });
+    Ok(())
+}
+
+/// restore the channels of the guild renamed for a monitored user who left it, so that they
+/// don't stay renamed until the end of the stream, and forget the user if configured
+pub async fn handle_streamer_departure(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    guild_id: GuildId,
+    discord_user_id: UserId,
+) -> anyhow::Result<()> {
+    let restored = {
+        let mut writer = twitch.write().await;
+        if !writer.users.contains_key(&discord_user_id) || !writer.servers.contains(&guild_id) {
+            return Ok(());
+        }
+        info!("Monitored user {} left guild {}", discord_user_id, guild_id);
+        let in_guild_voice = writer
+            .users
+            .get(&discord_user_id)
+            .and_then(|u| u.current_channel_id)
+            .is_some_and(|c| {
+                ctx.cache
+                    .guild(guild_id)
+                    .is_none_or(|g| g.channels.contains_key(&c))
+            });
+        writer.depart_streamer(&discord_user_id, guild_id, in_guild_voice)
+    };
+    // the stream isn't over, so the channels get their original name instead of the offline one
+    for (channel_id, original_name) in restored {
+        info!(
+            "Restoring the original name of channel {}, its streamer left",
+            channel_id
+        );
+        apply_new_name(
+            ctx,
+            twitch.clone(),
+            channel_id,
+            original_name,
+            Some(format!("User {} left the server", discord_user_id)),
+        )
+        .await;
+    }
+    Ok(())
+}
+
+/// restore the channels renamed for a streaming user they aren't in anymore,
+/// and rename the one they are in
+async fn settle_streamer_channels(
+    ctx: &serenity::Context,
     twitch: Arc<RwLock<DiscordTwitchWatcher>>,
-    discord_user_id: &'a UserId,
+    discord_user_id: &UserId,
+) -> anyhow::Result<()> {
+    let (current_channel_id, renamed_channel_ids) = {
+        let reader = twitch.read().await;
+        let Some(user) = reader
+            .users
+            .get(discord_user_id)
+            .filter(|u| !u.is_snoozed() && u.twitch_is_streaming == Some(true))
+        else {
+            return Ok(());
+        };
+        if reader.defer_rename && reader.deferred_announces.contains_key(&user.twitch_id) {
+            trace!(
+                "Stream of {} isn't announced yet, not renaming",
+                discord_user_id
+            );
+            return Ok(());
+        }
+        (
+            user.current_channel_id,
+            reader.find_session_channels(discord_user_id),
+        )
+    };
+    for channel_id in renamed_channel_ids {
+        if Some(channel_id) != current_channel_id {
+            rename_channel(ctx, twitch.clone(), discord_user_id, &channel_id, false).await?;
+            // the channel is restored or kept renamed by the streamers still in it
+            if let Some(user) = twitch.write().await.users.get_mut(discord_user_id) {
+                user.session_channels.remove(&channel_id);
+            }
+        }
+    }
+    if let Some(channel_id) = current_channel_id {
+        rename_channel(ctx, twitch, discord_user_id, &channel_id, true).await?;
+    }
+    Ok(())
+}
+
+/// channel from the cache, or from Discord if the cache doesn't have it, like when it's cold
+async fn get_guild_channel(
+    ctx: &serenity::Context,
     channel_id: &ChannelId,
-    is_streaming: bool,
-) -> anyhow::Result<Option<(ChannelId, EditChannel<'a>, Option<String>)>> {
-    // will be true if the channel has already been renamed
-    let channel_has_been_renamed = twitch.read().await.channels.contains_key(channel_id);
+) -> anyhow::Result<serenity::GuildChannel> {
+    let cached = ctx
+        .cache
+        .guilds()
+        .into_iter()
+        .find_map(|guild_id| ctx.cache.guild(guild_id)?.channels.get(channel_id).cloned());
+    if let Some(channel) = cached {
+        trace!("Channel {} found in cache", channel_id);
+        return Ok(channel);
+    }
+    let channel = ctx
+        .http
+        .get_channel(*channel_id)
+        .await
+        .with_context(|| format!("Channel {} isn't in cache nor on Discord", channel_id))?
+        .guild()
+        .ok_or(anyhow!("Channel {} isn't a guild channel", channel_id))?;
+    debug!("Channel {} not in cache, fetched from Discord", channel_id);
+    Ok(channel)
+}
 
-    if twitch
+pub async fn get_channel_new_name(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    discord_user_id: &UserId,
+    channel_id: &ChannelId,
+    is_streaming: bool,
+    force_reapply: bool,
+) -> anyhow::Result<Option<(ChannelId, String, Option<String>)>> {
+    let naming = twitch
         .read()
         .await
-        .find_user_in_channel(*channel_id)
-        .iter()
-        .filter(|f| f.twitch_is_streaming.is_some_and(|a| a) && channel_has_been_renamed)
-        .count()
-        >= 1
+        .channels
+        .get(channel_id)
+        .map(|c| c.naming);
+    // will be true if the channel has already been renamed for a live streamer
+    let channel_has_been_renamed = naming == Some(ChannelNaming::Live);
+    // the stream ended and the channel has the offline name
+    let channel_is_offline = matches!(naming, Some(ChannelNaming::Offline { .. }));
+    // a channel already renamed for this streamer is renamed again if its name is outdated,
+    // like after a template change, or after a category change when forced
+    let reapply_template = is_streaming && {
+        let reader = twitch.read().await;
+        (force_reapply || reader.reapply_template)
+            && reader.channels.get(channel_id).is_some_and(|c| {
+                c.naming == ChannelNaming::Live && c.streamer.is_none_or(|s| s == *discord_user_id)
+            })
+    };
+
+    if is_streaming && !twitch.read().await.rename_enabled {
+        debug!("Renames are disabled, not renaming channel {}", channel_id);
+        return Ok(None);
+    } else if reapply_template {
+        debug!("Checking if channel {} name is outdated", channel_id);
+    } else if channel_has_been_renamed && twitch.read().await.live_users_in_channel(*channel_id) > 0
     {
-        debug!("We do not change channel name, more than one streamer are in this channel");
+        // the status of discord_user_id is already updated by the event, so when it goes
+        // offline only the other streamers still live in the channel keep it renamed,
+        // monitored users who aren't streaming don't
+        debug!(
+            "Channel {} stays renamed, a live streamer is in it",
+            channel_id
+        );
         return Ok(None);
     } else if is_streaming {
         if channel_has_been_renamed {
@@ -158,14 +979,27 @@ pub async fn get_channel_new_name<'a>(
         } else {
             debug!("Channel {} need to be renamed", channel_id);
         }
+    } else if channel_is_offline {
+        debug!("Channel {} already has the offline name", channel_id);
+        return Ok(None);
+    } else if !channel_has_been_renamed {
+        debug!("Channel {} hasn't been renamed, nothing to restore", channel_id);
+        return Ok(None);
     }
 
     // actual name of the channel on Discord
-    let discord_channel_name: String;
-    if let Some(discord_channel) = ctx.cache.channel(channel_id) {
-        discord_channel_name = discord_channel.name.clone();
-    } else {
-        return Err(anyhow!("Channel {} doesn't exist", channel_id));
+    let discord_channel = get_guild_channel(ctx, channel_id).await?;
+    let discord_channel_name = discord_channel.name;
+    let discord_channel_status = discord_channel.status;
+    let discord_channel_kind = discord_channel.kind;
+    let discord_channel_guild_id = discord_channel.guild_id;
+    let rename_mode = twitch.read().await.rename_mode;
+    if rename_mode == RenameMode::VoiceStatus && discord_channel_kind != ChannelType::Voice {
+        debug!(
+            "Channel {} isn't a voice channel, it has no voice status",
+            channel_id
+        );
+        return Ok(None);
     }
 
     // the new name of the channel
@@ -174,25 +1008,123 @@ pub async fn get_channel_new_name<'a>(
         trace!("before write lock");
         let mut writer = twitch.write().await;
         trace!("after write lock");
-        new_channel_name = if is_streaming {
-            let to_insert = Channel {
-                original_name: discord_channel_name,
-            };
-            writer.channels.insert(*channel_id, to_insert);
-            writer.renamed_channel_name.clone()
+        // the channel may have been restored meanwhile, like by /restore_all or a departure,
+        // while its name was fetched from Discord
+        if (reapply_template || !is_streaming) && !writer.channels.contains_key(channel_id) {
+            debug!("Channel {} has been restored meanwhile", channel_id);
+            return Ok(None);
+        }
+        // a channel being restored has the name of the streamer it was renamed for
+        let streamer = match is_streaming {
+            true => *discord_user_id,
+            false => writer
+                .channels
+                .get(channel_id)
+                .and_then(|c| c.streamer)
+                .unwrap_or(*discord_user_id),
+        };
+        // in voice-status mode, the name is the voice status
+        let (renamed_channel_name, discord_channel_name) = match rename_mode {
+            RenameMode::Name => (
+                normalize_channel_name(
+                    &writer.renamed_name_for(channel_id, discord_channel_guild_id, &streamer),
+                    discord_channel_kind,
+                ),
+                discord_channel_name,
+            ),
+            RenameMode::VoiceStatus => (
+                writer.voice_status_for(discord_user_id),
+                discord_channel_status.unwrap_or_default(),
+            ),
+        };
+        // Discord changes submitted names, so names are only compared once normalized
+        let same_name = |a: &str, b: &str| match rename_mode {
+            RenameMode::Name => {
+                normalize_channel_name(a, discord_channel_kind)
+                    == normalize_channel_name(b, discord_channel_kind)
+            }
+            RenameMode::VoiceStatus => a == b,
+        };
+        new_channel_name = if reapply_template {
+            if same_name(&discord_channel_name, &renamed_channel_name) {
+                info!("Channel {} is already renamed", channel_id);
+                return Ok(None);
+            }
+            info!(
+                "Channel {} is named {} instead of {}, renaming it again",
+                channel_id, discord_channel_name, renamed_channel_name
+            );
+            // original_name is kept, the channel is still renamed
+            if let Some(channel) = writer.channels.get_mut(channel_id) {
+                channel.applied_name = Some(renamed_channel_name.clone());
+            }
+            renamed_channel_name
+        } else if is_streaming {
+            match writer.channels.get_mut(channel_id) {
+                // the channel has the offline name, its original name is still the one to restore
+                Some(channel) => {
+                    channel.naming = ChannelNaming::Live;
+                    channel.streamer = Some(*discord_user_id);
+                    channel.applied_name = Some(renamed_channel_name.clone());
+                }
+                None => {
+                    let to_insert = Channel {
+                        original_name: discord_channel_name,
+                        naming: ChannelNaming::Live,
+                        guild_id: Some(discord_channel_guild_id),
+                        streamer: Some(*discord_user_id),
+                        applied_name: Some(renamed_channel_name.clone()),
+                    };
+                    writer.channels.insert(*channel_id, to_insert);
+                }
+            }
+            renamed_channel_name
         } else {
-            let to_restore = writer.channels.remove(channel_id).unwrap();
-            to_restore.original_name
+            // the name the bot gave, the one of the current template may differ after a
+            // category or template change
+            let applied_name = writer
+                .channels
+                .get(channel_id)
+                .and_then(|c| c.applied_name.clone());
+            if applied_name.is_some_and(|applied| !same_name(&discord_channel_name, &applied)) {
+                warn!(
+                    "Channel {} has been renamed to {} since the bot renamed it",
+                    channel_id, discord_channel_name
+                );
+            }
+            match writer
+                .offline_name_for(discord_channel_guild_id)
+                .map(String::from)
+            {
+                Some(offline_name) => {
+                    let Some(channel) = writer.channels.get_mut(channel_id) else {
+                        return Ok(None);
+                    };
+                    channel.naming = ChannelNaming::Offline {
+                        since: SystemTime::now(),
+                    };
+                    match rename_mode {
+                        RenameMode::Name => {
+                            normalize_channel_name(&offline_name, discord_channel_kind)
+                        }
+                        RenameMode::VoiceStatus => offline_name,
+                    }
+                }
+                None => match writer.channels.remove(channel_id) {
+                    Some(channel) => channel.original_name,
+                    None => return Ok(None),
+                },
+            }
         };
+        writer.save_channels();
     }
 
     let reason = match is_streaming {
         true => format!("User {} is streaming", discord_user_id),
         false => format!("User {} has stopped his stream", discord_user_id),
     };
-    let edit_channel = EditChannel::new().name(new_channel_name);
 
-    Ok(Some((*channel_id, edit_channel, Some(reason))))
+    Ok(Some((*channel_id, new_channel_name, Some(reason))))
 }
 
 pub async fn rename_channel(
@@ -203,39 +1135,22 @@ pub async fn rename_channel(
     is_streaming: bool,
 ) -> anyhow::Result<()> {
     debug!("Renaming channel");
-    match get_channel_new_name(ctx, twitch, discord_user_id, channel_id, is_streaming).await? {
-        Some((a, b, c)) => {
-            debug!("Editing channel {:?} {:?} {:?}", a, b, c);
-            if let Some(ratelimiter) = &ctx.http.ratelimiter {
-                let routes = ratelimiter.routes();
-                let reader = routes.read().await;
-
-                let route = Route::Channel { channel_id: a };
-                if let Some(route) = reader.get(&route.ratelimiting_bucket()) {
-                    debug!("--------");
-                    debug!("limit: {:?}", route.lock().await.limit());
-                    debug!("remaining: {:?}", route.lock().await.remaining());
-                    if let Some(reset) = route.lock().await.reset() {
-                        debug!(
-                            "reset: {}",
-                            reset
-                                .duration_since(SystemTime::UNIX_EPOCH)
-                                .unwrap()
-                                .as_secs()
-                        );
-                    }
-                    if let Some(reset) = route.lock().await.reset_after() {
-                        debug!("reset after: {:?}", reset);
-                    }
-                    debug!("--------");
-                }
-            }
-            if let Err(why) = ctx.http.edit_channel(a, &b, c.as_deref()).await {
-                error!("Error on channel rename {}", why);
-            } else {
-                debug!("Done editing channel");
-            }
+    if is_streaming {
+        if let Some(user) = twitch.write().await.users.get_mut(discord_user_id) {
+            user.session_channels.insert(*channel_id);
         }
+    }
+    match get_channel_new_name(
+        ctx,
+        twitch.clone(),
+        discord_user_id,
+        channel_id,
+        is_streaming,
+        false,
+    )
+    .await?
+    {
+        Some((a, b, c)) => apply_new_name(ctx, twitch, a, b, c).await,
         None => {
             debug!("None returned from get_channel_new_name");
         }
@@ -244,6 +1159,192 @@ pub async fn rename_channel(
     Ok(())
 }
 
+/// rename the channel, or set its voice status in voice-status mode, through the rename queue
+async fn apply_new_name(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    channel_id: ChannelId,
+    name: String,
+    reason: Option<String>,
+) {
+    if twitch.read().await.rename_mode == RenameMode::VoiceStatus {
+        apply_voice_status(ctx, twitch, channel_id, &name).await;
+    } else if twitch
+        .write()
+        .await
+        .rename_queue
+        .schedule(channel_id, name.clone(), reason.clone())
+    {
+        apply_rename(ctx, twitch, channel_id, &name, reason.as_deref()).await;
+    } else {
+        info!(
+            "Rename of channel {} to {} has been queued",
+            channel_id, name
+        );
+    }
+}
+
+/// give back their original name to the channels which had the offline name for offline_restore
+pub async fn run_offline_restore(
+    ctx: serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+) {
+    loop {
+        sleep(OFFLINE_RESTORE_TICK).await;
+        let restored = {
+            let mut writer = twitch.write().await;
+            let restored = writer
+                .find_expired_offline_channels()
+                .into_iter()
+                .filter_map(|channel_id| {
+                    writer
+                        .channels
+                        .remove(&channel_id)
+                        .map(|c| (channel_id, c.original_name))
+                })
+                .collect::<Vec<(ChannelId, String)>>();
+            if !restored.is_empty() {
+                writer.save_channels();
+            }
+            restored
+        };
+        for (channel_id, original_name) in restored {
+            info!("Restoring the original name of channel {}", channel_id);
+            apply_new_name(
+                &ctx,
+                twitch.clone(),
+                channel_id,
+                original_name,
+                Some(String::from("Stream ended")),
+            )
+            .await;
+        }
+    }
+}
+
+/// rename the channel on Discord, errors are logged and notified
+pub async fn apply_rename(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    channel_id: ChannelId,
+    name: &str,
+    reason: Option<&str>,
+) {
+    debug!("Editing channel {:?} {:?} {:?}", channel_id, name, reason);
+    if let Some(ratelimiter) = &ctx.http.ratelimiter {
+        let routes = ratelimiter.routes();
+        let reader = routes.read().await;
+
+        let route = Route::Channel { channel_id };
+        if let Some(route) = reader.get(&route.ratelimiting_bucket()) {
+            debug!("--------");
+            debug!("limit: {:?}", route.lock().await.limit());
+            debug!("remaining: {:?}", route.lock().await.remaining());
+            if let Some(reset) = route.lock().await.reset() {
+                debug!(
+                    "reset: {}",
+                    reset
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap()
+                        .as_secs()
+                );
+            }
+            if let Some(reset) = route.lock().await.reset_after() {
+                debug!("reset after: {:?}", reset);
+            }
+            debug!("--------");
+        }
+    }
+    let edit_channel = EditChannel::new().name(name);
+    match ctx
+        .http
+        .edit_channel(channel_id, &edit_channel, reason)
+        .await
+    {
+        Err(why) => {
+            error!("Error on channel rename {}", why);
+            let error_notifier = twitch.read().await.error_notifier.clone();
+            error_notifier
+                .notify(
+                    &ctx.http,
+                    "rename",
+                    &format!("Can't rename channel {} : {}", channel_id.mention(), why),
+                )
+                .await;
+        }
+        Ok(channel) if channel.name != name => {
+            warn!(
+                "Channel {} has been renamed to {} instead of {}",
+                channel_id, channel.name, name
+            );
+        }
+        Ok(_) => debug!("Done editing channel"),
+    }
+}
+
+/// put back every renamed channel as it was, bypassing the rename queue
+pub async fn restore_all_channels(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+) {
+    let (channels, rename_mode) = {
+        let mut writer = twitch.write().await;
+        let channels = std::mem::take(&mut writer.channels);
+        writer.save_channels();
+        (channels, writer.rename_mode)
+    };
+    for (channel_id, channel) in channels {
+        match rename_mode {
+            RenameMode::Name => {
+                apply_rename(
+                    ctx,
+                    twitch.clone(),
+                    channel_id,
+                    &channel.original_name,
+                    Some("Bot is shutting down"),
+                )
+                .await
+            }
+            RenameMode::VoiceStatus => {
+                apply_voice_status(ctx, twitch.clone(), channel_id, &channel.original_name).await
+            }
+        }
+    }
+}
+
+/// set the voice status of the channel on Discord, errors are logged and notified
+/// voice statuses aren't rate limited like renames, so they don't go through the rename queue
+pub async fn apply_voice_status(
+    ctx: &serenity::Context,
+    twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+    channel_id: ChannelId,
+    status: &str,
+) {
+    debug!(
+        "Setting voice status of channel {} to {}",
+        channel_id, status
+    );
+    let result = ctx
+        .http
+        .edit_voice_status(channel_id, &serde_json::json!({ "status": status }), None)
+        .await;
+    if let Err(why) = result {
+        error!("Error on setting voice status {}", why);
+        let error_notifier = twitch.read().await.error_notifier.clone();
+        error_notifier
+            .notify(
+                &ctx.http,
+                "rename",
+                &format!(
+                    "Can't set voice status of channel {} : {}",
+                    channel_id.mention(),
+                    why
+                ),
+            )
+            .await;
+    }
+}
+
 #[poise::command(slash_command, check = "is_trusted")]
 pub async fn status(ctx: DiscordContext<'_>) -> Result<(), Error> {
     let text: String;
@@ -252,7 +1353,18 @@ pub async fn status(ctx: DiscordContext<'_>) -> Result<(), Error> {
         let mut channels = reader
             .channels
             .iter()
-            .map(|m| format!("{}:{}", m.0, m.1.original_name))
+            .map(|m| {
+                let offline = match m.1.naming {
+                    ChannelNaming::Live => "",
+                    ChannelNaming::Offline { .. } => " (offline)",
+                };
+                match m.1.guild_id {
+                    Some(guild_id) => {
+                        format!("{}/{}:{}{}", guild_id, m.0, m.1.original_name, offline)
+                    }
+                    None => format!("{}:{}{}", m.0, m.1.original_name, offline),
+                }
+            })
             .collect::<Vec<String>>()
             .join(", ");
         if channels.is_empty() {
@@ -276,6 +1388,159 @@ pub async fn status(ctx: DiscordContext<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+/// (debug) override the voice channel tracked for a monitored user
+#[poise::command(slash_command, guild_only, check = "is_trusted")]
+pub async fn set_voice(
+    ctx: DiscordContext<'_>,
+    #[description = "Monitored user"] user: serenity::User,
+    #[description = "Voice channel the user is in, none if absent"]
+    #[channel_types("Voice", "Stage")]
+    channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    let text = {
+        let mut writer = ctx.data().twitch.write().await;
+        let channel_id = channel.as_ref().map(|c| c.id);
+        if channel
+            .as_ref()
+            .is_some_and(|c| !writer.servers.contains(&c.guild_id))
+        {
+            String::from("The channel isn't in a configured server")
+        } else if writer.set_user_voice_channel(&user.id, channel_id) {
+            warn!(
+                "{} overrode the voice channel of {} to {:?}",
+                ctx.author().id,
+                user.id,
+                channel_id
+            );
+            match channel_id {
+                Some(channel_id) => {
+                    format!("{} is now tracked in {}", user.name, channel_id.mention())
+                }
+                None => format!("{} is now tracked out of voice channels", user.name),
+            }
+        } else {
+            format!("{} isn't a monitored streamer", user.name)
+        }
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
+
+/// show whether your discord account is linked to a twitch channel
+#[poise::command(slash_command)]
+pub async fn link_status(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let text = match ctx.data().twitch.read().await.users.get(&ctx.author().id) {
+        Some(user) => format!(
+            "Your account is linked to the twitch channel {}\n\
+            Streaming : {}",
+            user.twitch_id,
+            match user.twitch_is_streaming {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "unknown",
+            }
+        ),
+        None => {
+            String::from("Your account isn't linked to a twitch channel, ask an operator to add it")
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// set the name given to a channel while a streamer is in it, or reset it to the default one
+#[poise::command(slash_command, guild_only, check = "is_trusted")]
+pub async fn channel_template_set(
+    ctx: DiscordContext<'_>,
+    #[description = "Channel to rename differently"] channel: serenity::GuildChannel,
+    #[description = "Name while streaming, default if absent"] template: Option<String>,
+) -> Result<(), Error> {
+    let text = {
+        let mut writer = ctx.data().twitch.write().await;
+        match template {
+            Some(template) => match validate_channel_name("The name", &template) {
+                Err(why) => why.to_string(),
+                Ok(()) => {
+                    let normalized = normalize_channel_name(&template, channel.kind);
+                    if normalized.is_empty() {
+                        String::from("The name must not be empty")
+                    } else {
+                        writer.channel_templates.insert(channel.id, template);
+                        writer.save_channel_templates();
+                        format!(
+                            "{} will be renamed {} while streaming",
+                            channel.id.mention(),
+                            normalized
+                        )
+                    }
+                }
+            },
+            None => {
+                writer.channel_templates.remove(&channel.id);
+                writer.save_channel_templates();
+                format!(
+                    "{} will be renamed {} while streaming",
+                    channel.id.mention(),
+                    normalize_channel_name(
+                        writer.renamed_name_of_guild(channel.guild_id),
+                        channel.kind
+                    )
+                )
+            }
+        }
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
+
+/// set the name of your channel while you stream, or reset it to the default one
+#[poise::command(slash_command)]
+pub async fn my_template(
+    ctx: DiscordContext<'_>,
+    #[description = "Name while streaming, default if absent"] template: Option<String>,
+) -> Result<(), Error> {
+    let text = {
+        let mut writer = ctx.data().twitch.write().await;
+        match writer.users.get_mut(&ctx.author().id) {
+            None => String::from(
+                "Your account isn't linked to a twitch channel, ask an operator to add it",
+            ),
+            Some(user) => match template {
+                Some(template) => match validate_channel_name("The name", &template) {
+                    Err(why) => why.to_string(),
+                    Ok(()) => {
+                        let text = format!(
+                            "Your channel will be renamed {} while streaming",
+                            normalize_channel_name(&template, ChannelType::Voice)
+                        );
+                        user.custom_template = Some(template);
+                        writer.save_user_templates();
+                        text
+                    }
+                },
+                None => {
+                    user.custom_template = None;
+                    let text = match &user.rename_override {
+                        Some(rename_override) => format!(
+                            "Your channel will be renamed {} while streaming",
+                            normalize_channel_name(rename_override, ChannelType::Voice)
+                        ),
+                        None => String::from(
+                            "Your channel will be renamed with the name of the channel while streaming",
+                        ),
+                    };
+                    writer.save_user_templates();
+                    text
+                }
+            },
+        }
+    };
+    ctx.send(poise::CreateReply::default().content(text).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
 #[poise::command(slash_command, check = "is_trusted")]
 pub async fn update_streaming_status(
     ctx: DiscordContext<'_>,
@@ -283,29 +1548,625 @@ pub async fn update_streaming_status(
     is_streaming: bool,
 ) -> Result<(), Error> {
     let twitch_user_id: String;
-    let twitch_user_login: String;
     if let Some(local_user) = ctx.data().twitch.read().await.users.get(&user.id) {
         twitch_user_id = local_user.twitch_id.to_string();
-        twitch_user_login = user.name.to_lowercase();
     } else {
         ctx.say("User isn't registered").await?;
         return Ok(());
     }
+    // the deferred response is replaced by the acknowledgment, so it must be as visible
+    match ctx.data().ack_style {
+        AckStyle::Ephemeral => ctx.defer_ephemeral().await?,
+        _ => ctx.defer().await?,
+    }
+    let twitch_user_login = resolve_twitch_login(ctx.data(), &twitch_user_id).await;
+    send_stream_event(ctx.data(), twitch_user_id, twitch_user_login, is_streaming).await?;
+    acknowledge(ctx).await?;
+    Ok(())
+}
+
+/// monitored streamers whose twitch login contains partial, sorted and limited to what
+/// Discord accepts, the ones whose login isn't known yet are suggested by id if with_ids
+async fn monitored_choices(data: &Data, partial: &str, with_ids: bool) -> Vec<String> {
+    let partial = partial.trim().trim_start_matches('@').to_lowercase();
+    let reader = data.twitch.read().await;
+    let mut choices: Vec<String> = reader
+        .users
+        .values()
+        .filter_map(|u| match &u.twitch_login {
+            Some(login) => Some(login.clone()),
+            None => with_ids.then(|| u.twitch_id.to_string()),
+        })
+        .filter(|choice| choice.to_lowercase().contains(&partial))
+        .collect();
+    choices.sort();
+    choices.dedup();
+    choices.truncate(AUTOCOMPLETE_MAX_CHOICES);
+    choices
+}
+
+async fn autocomplete_monitored(ctx: DiscordContext<'_>, partial: &str) -> Vec<String> {
+    monitored_choices(ctx.data(), partial, true).await
+}
+
+async fn autocomplete_monitored_login(ctx: DiscordContext<'_>, partial: &str) -> Vec<String> {
+    monitored_choices(ctx.data(), partial, false).await
+}
+
+/// mark a stream online or offline by twitch login or id, for streamers without a discord user too
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn update_twitch_streaming_status(
+    ctx: DiscordContext<'_>,
+    #[description = "Twitch login or id of the streamer"]
+    #[autocomplete = "autocomplete_monitored"]
+    twitch_user: String,
+    is_streaming: bool,
+) -> Result<(), Error> {
+    match ctx.data().ack_style {
+        AckStyle::Ephemeral => ctx.defer_ephemeral().await?,
+        _ => ctx.defer().await?,
+    }
+    let login_or_id = twitch_user.trim().to_owned();
+    match request_twitch(ctx.data(), |reply| TwitchRequest::ResolveMonitored {
+        login_or_id,
+        reply,
+    })
+    .await
+    {
+        Ok((twitch_user_id, twitch_user_login)) => {
+            send_stream_event(ctx.data(), twitch_user_id, twitch_user_login, is_streaming).await?;
+            acknowledge(ctx).await?;
+        }
+        Err(why) => {
+            ctx.say(format!("Can't use {} : {}", twitch_user, why))
+                .await?;
+        }
+    }
+    Ok(())
+}
 
-    ctx.data()
-        .sender
+/// inject a stream event as if it came from the twitch side
+async fn send_stream_event(
+    data: &Data,
+    twitch_user_id: String,
+    twitch_user_login: String,
+    is_streaming: bool,
+) -> Result<(), Error> {
+    data.sender
         .lock()
         .await
-        .send(InterComm {
+        .send(InterComm::Streamer(StreamerEvent {
             message_type: match is_streaming {
-                true => MessageType::TwitchStreamOnline,
+                true => MessageType::TwitchStreamOnline {
+                    is_backlog: false,
+                    category: None,
+                },
                 false => MessageType::TwitchStreamOffline,
             },
             streamer_user_id: twitch_user_id,
             streamer_user_login: twitch_user_login,
-        })
+        }))
         .await?;
+    Ok(())
+}
 
-    ctx.say("ok").await?;
+/// force a refresh of the twitch token
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn twitch_refresh(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let text = match request_twitch(ctx.data(), |reply| TwitchRequest::RefreshToken { reply }).await
+    {
+        Ok(expires_in) => format!(
+            "Token refreshed, it expires in {}",
+            format_duration(expires_in)
+        ),
+        Err(why) => format!("Token refresh failed : {}", why),
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
+
+/// delete the twitch subscriptions of streamers removed from the config
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn twitch_prune(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let text = match request_twitch(ctx.data(), |reply| TwitchRequest::PruneSubscriptions {
+        reply,
+    })
+    .await
+    {
+        Ok(pruned) => format!("Pruned {} subscriptions", pruned),
+        Err(why) => format!("Subscriptions prune failed : {}", why),
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
+
+/// describe a rate limit for commands
+pub fn format_rate_limit(rate_limit: Option<RateLimitInfo>) -> String {
+    let Some(rate_limit) = rate_limit else {
+        return String::from("no helix response seen yet");
+    };
+    let timestamp = |time: SystemTime| {
+        time.duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    };
+    format!(
+        "{}/{} requests left, reset <t:{}:R>, seen <t:{}:R>",
+        rate_limit.remaining,
+        rate_limit
+            .limit
+            .map_or(String::from("?"), |limit| limit.to_string()),
+        timestamp(rate_limit.reset_at),
+        timestamp(rate_limit.observed_at)
+    )
+}
+
+/// show the last helix rate limit seen by the twitch side
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn twitch_ratelimit(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    let text = match request_twitch(ctx.data(), |reply| TwitchRequest::RateLimit { reply }).await {
+        Ok(rate_limit) => format!("Twitch rate limit : {}", format_rate_limit(rate_limit)),
+        Err(why) => format!("Can't get the rate limit : {}", why),
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
+
+/// show the current stream of a twitch channel, monitored or not
+#[poise::command(slash_command, user_cooldown = 10)]
+pub async fn stream_info(
+    ctx: DiscordContext<'_>,
+    #[description = "Twitch login of the channel"]
+    #[autocomplete = "autocomplete_monitored_login"]
+    login: String,
+) -> Result<(), Error> {
+    let login = login.trim().trim_start_matches('@').to_lowercase();
+    if login.is_empty() || !login.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        ctx.say(format!("{} isn't a valid twitch login", login))
+            .await?;
+        return Ok(());
+    }
+    ctx.defer().await?;
+    let text = match request_twitch(ctx.data(), |reply| TwitchRequest::LookupStream {
+        login: login.clone(),
+        reply,
+    })
+    .await
+    {
+        Ok(Some(stream)) => {
+            let uptime = stream
+                .started_at
+                .and_then(|t| t.elapsed().ok())
+                .map(format_duration)
+                .unwrap_or_else(|| String::from("unknown"));
+            format!(
+                "{} is live : {}\nGame : {}\nViewers : {}\nUptime : {}\nhttps://twitch.tv/{}",
+                stream.user_name,
+                stream.title,
+                stream.game_name,
+                stream.viewer_count,
+                uptime,
+                stream.user_login
+            )
+        }
+        Ok(None) => format!("{} isn't live", login),
+        Err(why) => format!("Can't get the stream of {} : {}", login, why),
+    };
+    ctx.send(
+        poise::CreateReply::default()
+            .content(text)
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// check the twitch token and show its scopes and expiry
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn twitch_token_status(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let text = match request_twitch(ctx.data(), |reply| TwitchRequest::TokenStatus { reply }).await
+    {
+        Ok(Some(status)) => format!(
+            "Token is valid for {} ({}), it expires in {}\nScopes : {}",
+            status.login,
+            status.user_id,
+            format_duration(status.expires_in),
+            match status.scopes.is_empty() {
+                true => String::from("none"),
+                false => status.scopes.join(", "),
+            }
+        ),
+        Ok(None) => String::from("Token isn't valid, use /twitch_refresh to get a new one"),
+        Err(why) => format!("Can't check the token : {}", why),
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
+
+/// show which twitch features the scopes of the token allow
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn twitch_scopes(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let text = match request_twitch(ctx.data(), |reply| TwitchRequest::TokenStatus { reply }).await
+    {
+        Ok(Some(status)) => {
+            let mut text = format!(
+                "Scopes of the token of {} : {}",
+                status.login,
+                match status.scopes.is_empty() {
+                    true => String::from("none"),
+                    false => status.scopes.join(", "),
+                }
+            );
+            for (feature, scope, used) in TWITCH_FEATURES {
+                let allowed = scope.is_none_or(|scope| status.scopes.iter().any(|s| s == scope));
+                text.push_str(&format!(
+                    "\n{} {}{}{}",
+                    match allowed {
+                        true => "✅",
+                        false => "❌",
+                    },
+                    feature,
+                    match scope {
+                        Some(scope) if !allowed => format!(", needs {}", scope),
+                        _ => String::new(),
+                    },
+                    match used {
+                        true => "",
+                        false => " (not used by the bot yet)",
+                    }
+                ));
+            }
+            text
+        }
+        Ok(None) => String::from("Token isn't valid, use /twitch_refresh to get a new one"),
+        Err(why) => format!("Can't check the token : {}", why),
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
+
+/// list the eventsub subscriptions registered on twitch
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn twitch_subs(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let text = match request_twitch(ctx.data(), |reply| TwitchRequest::ListSubscriptions {
+        reply,
+    })
+    .await
+    {
+        Ok(subs) if subs.is_empty() => String::from("No subscription"),
+        Ok(subs) => {
+            let mut text = format!("{} subscriptions :", subs.len());
+            for sub in subs {
+                let line = format!(
+                    "\n{} {} : {}",
+                    sub.event_type,
+                    sub.broadcaster_user_id.as_deref().unwrap_or("-"),
+                    sub.status
+                );
+                if text.len() + line.len() > MESSAGE_MAX_LENGTH {
+                    break;
+                }
+                text.push_str(&line);
+            }
+            text
+        }
+        Err(why) => format!("Can't list subscriptions : {}", why),
+    };
+    ctx.say(text).await?;
     Ok(())
 }
+
+/// show the subscriptions a reconnect would keep, create and delete, without changing them
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn twitch_reconcile_dryrun(ctx: DiscordContext<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let lines =
+        match request_twitch(ctx.data(), |reply| TwitchRequest::ReconcileDryRun { reply }).await {
+            Ok(plan) => {
+                let mut lines = vec![format!(
+                    "Reconciliation plan : {} kept, {} to create, {} to delete",
+                    plan.keep.len(),
+                    plan.create.len(),
+                    plan.delete.len()
+                )];
+                for (prefix, subs) in [("+", plan.create), ("-", plan.delete), ("=", plan.keep)] {
+                    lines.extend(subs.iter().map(|sub| {
+                        format!(
+                            "`{}` {} {}{}",
+                            prefix,
+                            sub.event_type,
+                            sub.broadcaster_user_id.as_deref().unwrap_or("-"),
+                            match sub.status.is_empty() {
+                                true => String::new(),
+                                false => format!(" ({})", sub.status),
+                            }
+                        )
+                    }));
+                }
+                lines
+            }
+            Err(why) => vec![format!("Can't compute the reconciliation plan : {}", why)],
+        };
+    for text in split_message(&lines) {
+        ctx.say(text).await?;
+    }
+    Ok(())
+}
+
+/// check twitch accepts subscriptions on the session, for your linked twitch channel by default
+#[poise::command(slash_command, check = "is_trusted")]
+pub async fn subscribe_test(
+    ctx: DiscordContext<'_>,
+    #[description = "Twitch login or id of a monitored streamer"]
+    #[autocomplete = "autocomplete_monitored"]
+    twitch_user: Option<String>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let login_or_id = match twitch_user {
+        Some(twitch_user) => twitch_user.trim().to_owned(),
+        None => match ctx.data().twitch.read().await.users.get(&ctx.author().id) {
+            Some(user) => user.twitch_id.to_string(),
+            None => {
+                ctx.say("You aren't linked to a twitch channel, give one with twitch_user")
+                    .await?;
+                return Ok(());
+            }
+        },
+    };
+    let text = match request_twitch(ctx.data(), |reply| TwitchRequest::TestSubscription {
+        login_or_id,
+        reply,
+    })
+    .await
+    {
+        Ok(test) => {
+            let mut text = match &test.confirmed_status {
+                Some(status) => format!(
+                    "Subscription accepted by Twitch : {} subscription of {} created ({}) and listed ({}) in {}ms, this doesn't prove events are delivered",
+                    test.event_type,
+                    test.broadcaster_user_login,
+                    test.created_status,
+                    status,
+                    test.elapsed.as_millis()
+                ),
+                None => format!(
+                    "Subscription not confirmed by Twitch : {} subscription of {} created ({}) but not listed after {}s",
+                    test.event_type,
+                    test.broadcaster_user_login,
+                    test.created_status,
+                    test.elapsed.as_secs()
+                ),
+            };
+            if let Some(why) = test.cleanup_error {
+                text.push_str(&format!(
+                    "\nCan't delete the test subscription, twitch drops it with the session : {}",
+                    why
+                ));
+            }
+            text
+        }
+        Err(why) => format!("Can't test the subscriptions : {}", why),
+    };
+    ctx.say(text).await?;
+    Ok(())
+}
+
+/// send a request to the twitch side and wait for its answer
+pub async fn request_twitch<T>(
+    data: &Data,
+    request: impl FnOnce(oneshot::Sender<Result<T, String>>) -> TwitchRequest,
+) -> Result<T, String> {
+    send_twitch_request(&data.twitch_requests, request).await
+}
+
+/// send a request to the twitch side outside of a command and wait for its answer
+pub async fn send_twitch_request<T>(
+    twitch_requests: &Sender<TwitchRequest>,
+    request: impl FnOnce(oneshot::Sender<Result<T, String>>) -> TwitchRequest,
+) -> Result<T, String> {
+    let (reply, answer) = oneshot::channel();
+    twitch_requests
+        .send(request(reply))
+        .await
+        .map_err(|_| String::from("Twitch side isn't running"))?;
+    match timeout(TWITCH_REQUEST_TIMEOUT, answer).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(_)) => Err(String::from("Twitch side dropped the request")),
+        Err(_) => Err(String::from("Twitch side didn't answer in time")),
+    }
+}
+
+/// get the twitch login of a twitch user id, falling back to the id if it can't be resolved
+async fn resolve_twitch_login(data: &Data, twitch_user_id: &str) -> String {
+    let user_id = twitch_user_id.to_owned();
+    match request_twitch(data, |reply| TwitchRequest::ResolveLogin { user_id, reply }).await {
+        Ok(login) => login,
+        Err(why) => {
+            warn!(
+                "Can't resolve login of twitch user {}, using its id instead : {}",
+                twitch_user_id, why
+            );
+            twitch_user_id.to_owned()
+        }
+    }
+}
+
+/// format a duration as hours and minutes
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    format!("{}h{:02}", minutes / 60, minutes % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discord::fixtures::{channel_id, context, mock_channel, renames, user, watcher};
+    use wiremock::MockServer;
+
+    /// streamer 1 live, tracked by voice state events, without announces
+    fn live_streamer() -> Arc<RwLock<DiscordTwitchWatcher>> {
+        let mut watcher = watcher();
+        watcher.announce_enabled = false;
+        let mut user = user(1, 100);
+        user.twitch_is_streaming = Some(true);
+        user.has_been_part_of_voice_state_event = true;
+        watcher.users.insert(UserId::new(1), user);
+        Arc::new(RwLock::new(watcher))
+    }
+
+    fn live_name() -> String {
+        normalize_channel_name("🔴 En live", ChannelType::Voice)
+    }
+
+    async fn join_and_rename(
+        ctx: &serenity::Context,
+        twitch: Arc<RwLock<DiscordTwitchWatcher>>,
+        id: u64,
+    ) {
+        let streamer = UserId::new(1);
+        twitch
+            .write()
+            .await
+            .set_user_voice_channel(&streamer, Some(channel_id(id)));
+        rename_channel(ctx, twitch, &streamer, &channel_id(id), true)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn going_offline_restores_every_channel_of_the_session() {
+        let server = MockServer::start().await;
+        let ctx = context(&server).await;
+        for (id, name) in [(10, "general"), (11, "gaming"), (12, "music")] {
+            mock_channel(&server, id, name).await;
+        }
+        let twitch = live_streamer();
+        for id in [10, 11, 12] {
+            join_and_rename(&ctx, twitch.clone(), id).await;
+        }
+
+        handle_stream_event(&ctx, twitch.clone(), 100, "streamer", false, false, None)
+            .await
+            .unwrap();
+
+        let renames = renames(&server).await;
+        assert_eq!(
+            renames[..3],
+            [(10, live_name()), (11, live_name()), (12, live_name())]
+        );
+        let mut restores = renames[3..].to_vec();
+        restores.sort();
+        assert_eq!(
+            restores,
+            [
+                (10, String::from("general")),
+                (11, String::from("gaming")),
+                (12, String::from("music"))
+            ]
+        );
+        assert!(twitch.read().await.channels.is_empty());
+    }
+
+    #[tokio::test]
+    async fn channel_left_before_going_offline_is_restored() {
+        let server = MockServer::start().await;
+        let ctx = context(&server).await;
+        mock_channel(&server, 10, "general").await;
+        let twitch = live_streamer();
+        let streamer = UserId::new(1);
+        join_and_rename(&ctx, twitch.clone(), 10).await;
+        twitch.write().await.set_user_voice_channel(&streamer, None);
+
+        handle_stream_event(&ctx, twitch.clone(), 100, "streamer", false, false, None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            renames(&server).await,
+            [(10, live_name()), (10, String::from("general"))]
+        );
+        assert!(twitch.read().await.channels.is_empty());
+    }
+
+    #[tokio::test]
+    async fn quick_moves_rename_only_the_last_channel() {
+        let server = MockServer::start().await;
+        let ctx = context(&server).await;
+        for (id, name) in [(10, "general"), (11, "a"), (12, "b"), (13, "c")] {
+            mock_channel(&server, id, name).await;
+        }
+        let twitch = live_streamer();
+        let streamer = UserId::new(1);
+        twitch.write().await.rename_cooldown = Duration::from_millis(100);
+        join_and_rename(&ctx, twitch.clone(), 10).await;
+
+        for id in [11, 12, 13] {
+            twitch
+                .write()
+                .await
+                .set_user_voice_channel(&streamer, Some(channel_id(id)));
+            handle_streamer_move(&ctx, twitch.clone(), streamer)
+                .await
+                .unwrap();
+        }
+        sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(
+            renames(&server).await,
+            [
+                (10, live_name()),
+                (10, String::from("general")),
+                (13, live_name())
+            ]
+        );
+        let reader = twitch.read().await;
+        assert_eq!(
+            reader.channels.keys().copied().collect::<Vec<ChannelId>>(),
+            [channel_id(13)]
+        );
+        assert!(reader.voice_moves.is_empty());
+    }
+
+    #[tokio::test]
+    async fn users_who_arent_live_dont_keep_a_channel_renamed() {
+        let server = MockServer::start().await;
+        let ctx = context(&server).await;
+        mock_channel(&server, 10, "general").await;
+        let twitch = live_streamer();
+        let streamer = UserId::new(1);
+        join_and_rename(&ctx, twitch.clone(), 10).await;
+        {
+            let mut writer = twitch.write().await;
+            let mut not_live = user(2, 200);
+            not_live.twitch_is_streaming = Some(false);
+            writer.users.insert(UserId::new(2), not_live);
+            writer.set_user_voice_channel(&UserId::new(2), Some(channel_id(10)));
+            // the status of a streamer going offline is updated before their channel
+            writer
+                .users
+                .get_mut(&streamer)
+                .unwrap()
+                .set_twitch_is_streaming(Some(false));
+        }
+
+        let new_name = get_channel_new_name(
+            &ctx,
+            twitch.clone(),
+            &streamer,
+            &channel_id(10),
+            false,
+            false,
+        )
+        .await
+        .unwrap()
+        .map(|(id, name, _)| (id, name));
+
+        assert_eq!(new_name, Some((channel_id(10), String::from("general"))));
+        assert!(twitch.read().await.channels.is_empty());
+    }
+}
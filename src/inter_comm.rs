@@ -1,12 +1,171 @@
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::oneshot;
+
 #[derive(Debug)]
 pub enum MessageType {
-    TwitchStreamOnline,
+    // is_backlog is true when the stream was already live before we first knew its status,
+    // like on startup, so it isn't newly live
+    // category is the one the stream started with, None if it couldn't be looked up
+    TwitchStreamOnline {
+        is_backlog: bool,
+        category: Option<String>,
+    },
     TwitchStreamOffline,
+    // the category of the channel changed during the stream
+    TwitchCategoryUpdate {
+        category: String,
+    },
+    // a message of the twitch chat of the streamer, only for streamers with chat_relay
+    ChatMessage(ChatMessage),
+}
+
+#[derive(Debug)]
+pub struct ChatMessage {
+    // display name of the chatter
+    pub author: String,
+    pub text: String,
+    // set ids of the badges of the chatter, like moderator or subscriber
+    pub badges: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum InterComm {
+    Streamer(StreamerEvent),
+    // an error on twitch side that operators should know about, kind groups the errors that
+    // are notified at most once in a while
+    Error { kind: &'static str, message: String },
 }
 
+/// event of a monitored streamer
 #[derive(Debug)]
-pub struct InterComm {
+pub struct StreamerEvent {
     pub message_type: MessageType,
     pub streamer_user_id: String,
     pub streamer_user_login: String,
 }
+
+/// eventsub subscription as seen by twitch
+#[derive(Debug)]
+pub struct SubscriptionInfo {
+    pub event_type: String,
+    pub broadcaster_user_id: Option<String>,
+    pub status: String,
+}
+
+/// changes the subscriptions reconciliation would make on twitch
+#[derive(Debug)]
+pub struct ReconcilePlan {
+    pub keep: Vec<SubscriptionInfo>,
+    pub create: Vec<SubscriptionInfo>,
+    pub delete: Vec<SubscriptionInfo>,
+}
+
+/// outcome of a temporary subscription created to check that eventsub works
+#[derive(Debug)]
+pub struct SubscriptionTest {
+    pub broadcaster_user_login: String,
+    pub event_type: String,
+    // status given by twitch when the subscription was created
+    pub created_status: String,
+    // status of the subscription when listed back, None if twitch didn't list it in time
+    pub confirmed_status: Option<String>,
+    // time between the creation request and the confirmation
+    pub elapsed: Duration,
+    // None if the subscription has been deleted, the error otherwise
+    pub cleanup_error: Option<String>,
+}
+
+/// last rate limit reported by the helix api
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitInfo {
+    // requests allowed per minute
+    pub limit: Option<u64>,
+    pub remaining: u64,
+    // when remaining goes back to limit
+    pub reset_at: SystemTime,
+    pub observed_at: SystemTime,
+}
+
+/// twitch token as reported by the validate endpoint
+#[derive(Debug)]
+pub struct TokenStatus {
+    pub login: String,
+    pub user_id: String,
+    pub scopes: Vec<String>,
+    pub expires_in: Duration,
+}
+
+/// details of a live stream
+#[derive(Debug)]
+pub struct StreamInfo {
+    pub user_login: String,
+    pub user_name: String,
+    pub title: String,
+    pub game_name: String,
+    // None if twitch sent an invalid time
+    pub started_at: Option<SystemTime>,
+    pub viewer_count: usize,
+    // contains {width} and {height} placeholders
+    pub thumbnail_url: String,
+}
+
+/// request sent by the discord side to the twitch side, the answer is sent back through reply
+#[derive(Debug)]
+pub enum TwitchRequest {
+    /// refresh the token now, reply with the time before the new token expires
+    RefreshToken {
+        reply: oneshot::Sender<Result<Duration, String>>,
+    },
+    /// reply with the login of the twitch user
+    ResolveLogin {
+        user_id: String,
+        reply: oneshot::Sender<Result<String, String>>,
+    },
+    /// reply with the id and login of a monitored twitch user given by login or id
+    ResolveMonitored {
+        login_or_id: String,
+        reply: oneshot::Sender<Result<(String, String), String>>,
+    },
+    /// delete the subscriptions of users which aren't monitored anymore, reply with their count
+    PruneSubscriptions {
+        reply: oneshot::Sender<Result<usize, String>>,
+    },
+    /// reply with the subscriptions registered on twitch
+    ListSubscriptions {
+        reply: oneshot::Sender<Result<Vec<SubscriptionInfo>, String>>,
+    },
+    /// reply with the subscriptions the reconciliation would keep, create and delete,
+    /// without changing them
+    ReconcileDryRun {
+        reply: oneshot::Sender<Result<ReconcilePlan, String>>,
+    },
+    /// reply with the last rate limit seen, None if no helix request has been made yet
+    RateLimit {
+        reply: oneshot::Sender<Result<Option<RateLimitInfo>, String>>,
+    },
+    /// reply with the stream of the twitch user, None if it's offline
+    StreamInfo {
+        user_id: String,
+        reply: oneshot::Sender<Result<Option<StreamInfo>, String>>,
+    },
+    /// validate the current token, reply with its status, None if it isn't valid
+    TokenStatus {
+        reply: oneshot::Sender<Result<Option<TokenStatus>, String>>,
+    },
+    /// reply with the stream of any twitch user given by login, None if it's offline
+    LookupStream {
+        login: String,
+        reply: oneshot::Sender<Result<Option<StreamInfo>, String>>,
+    },
+    /// create a temporary subscription for a monitored twitch user given by login or id on the
+    /// current session, wait for twitch to confirm it then delete it
+    TestSubscription {
+        login_or_id: String,
+        reply: oneshot::Sender<Result<SubscriptionTest, String>>,
+    },
+    /// reply with the time before the current token expires
+    TokenExpiry {
+        reply: oneshot::Sender<Result<Duration, String>>,
+    },
+}
@@ -1,39 +1,120 @@
 mod config;
 mod discord;
 mod inter_comm;
+mod log_buffer;
+mod proxy;
+mod quiet_hours;
+mod shutdown;
+mod store;
+mod templates;
 mod twitch;
 
 use std::env::var;
-use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::config::Config;
-use crate::inter_comm::InterComm;
-use tokio::join;
-use tokio::sync::mpsc;
-use tracing::debug;
+use crate::inter_comm::{InterComm, TwitchRequest};
+use crate::log_buffer::LogBufferLayer;
+use crate::twitch::circuit_breaker::CircuitBreaker;
+use crate::twitch::health::TwitchHealth;
+use time::macros::format_description;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::{join, select};
+use tracing::{debug, warn};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::time::UtcTime;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::{fmt, EnvFilter};
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+// log lines kept for /logs when LOG_BUFFER_SIZE isn't set
+const DEFAULT_LOG_BUFFER_SIZE: usize = 200;
 
 #[tokio::main]
 async fn main() {
     let _ = dotenvy::dotenv();
 
-    tracing_subscriber::registry()
-        .with(EnvFilter::from_default_env())
-        .with(fmt::layer())
-        .init();
+    // the last lines shown by /logs, filtered separately so that they don't depend on RUST_LOG
+    let log_buffer_size = var("LOG_BUFFER_SIZE")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_LOG_BUFFER_SIZE);
+    let log_buffer_level = var("LOG_BUFFER_LEVEL")
+        .ok()
+        .and_then(|v| v.parse::<LevelFilter>().ok())
+        .unwrap_or(LevelFilter::WARN);
+    let log_buffer = log_buffer::init(log_buffer_size);
+    let registry = tracing_subscriber::registry()
+        .with(LogBufferLayer::new(log_buffer.clone()).with_filter(log_buffer_level));
+    let env_filter = EnvFilter::from_default_env();
+    let log_format = var("LOG_FORMAT").unwrap_or(String::from("full"));
+    match log_format.as_str() {
+        "json" => registry
+            .with(fmt::layer().json().with_filter(env_filter))
+            .init(),
+        "compact" => registry
+            .with(
+                fmt::layer()
+                    .compact()
+                    .with_ansi(false)
+                    .with_timer(UtcTime::new(format_description!(
+                        "[month]-[day] [hour]:[minute]:[second]"
+                    )))
+                    .with_filter(env_filter),
+            )
+            .init(),
+        _ => registry.with(fmt::layer().with_filter(env_filter)).init(),
+    }
+    if !["full", "json", "compact"].contains(&log_format.as_str()) {
+        warn!("Unknown LOG_FORMAT {}, using full", log_format);
+    }
     debug!("We are in debug mode");
 
-    let config = serde_json::from_str::<Config>(
-        &fs::read_to_string(var("CONFIG_PATH").unwrap_or(String::from("./config.json")))
-            .expect("Error while reading config file"),
-    )
-    .expect("Error while parsing config file");
+    let config = Config::load().await.expect("Error while loading config");
+
+    let store = store::create_store(&config.store).expect("Error while opening store");
+
+    let twitch_health = Arc::new(TwitchHealth::new(CircuitBreaker::new(
+        5,
+        Duration::from_secs(300),
+    )));
 
     let (tx, rx) = mpsc::channel::<InterComm>(32);
-    let (_, _) = join!(
-        discord::bot::run(tx.clone(), rx, &config),
-        twitch::websocket::run(tx, &config)
-    );
+    let (twitch_tx, twitch_rx) = mpsc::channel::<TwitchRequest>(8);
+    let (stop_twitch, twitch_stopping) = watch::channel(false);
+    let (stop_discord, discord_stopping) = watch::channel(false);
+    let (twitch_stopped, twitch_stopped_rx) = oneshot::channel();
+    let (discord_stopped, discord_stopped_rx) = oneshot::channel();
+    let discord = async {
+        let result = discord::bot::run(
+            tx.clone(),
+            rx,
+            twitch_tx,
+            &config,
+            store.clone(),
+            twitch_health.clone(),
+            discord_stopping,
+        )
+        .await;
+        let _ = discord_stopped.send(());
+        result
+    };
+    let twitch = async {
+        let result = twitch::websocket::run(
+            tx.clone(),
+            &config,
+            twitch_rx,
+            store.clone(),
+            twitch_health.clone(),
+            twitch_stopping,
+        )
+        .await;
+        let _ = twitch_stopped.send(());
+        result
+    };
+    select! {
+        _ = async { join!(discord, twitch) } => {}
+        _ = shutdown::coordinate(stop_twitch, twitch_stopped_rx, stop_discord, discord_stopped_rx) => {}
+    }
 }
@@ -0,0 +1,131 @@
+use std::env::var;
+
+use anyhow::{anyhow, bail, Context};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{debug, info};
+use tungstenite::protocol::WebSocketConfig;
+use url::Url;
+
+use crate::config::Config;
+
+// a proxy answer to CONNECT longer than this is considered broken
+const PROXY_RESPONSE_MAX_LENGTH: usize = 8192;
+
+pub type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// proxy used for the requests to twitch, from the config or the HTTPS_PROXY env var
+pub fn proxy_url(config: &Config) -> anyhow::Result<Option<Url>> {
+    let proxy = config
+        .proxy
+        .clone()
+        .or_else(|| var("HTTPS_PROXY").ok())
+        .or_else(|| var("https_proxy").ok())
+        .filter(|p| !p.is_empty());
+    match proxy {
+        Some(proxy) => {
+            let url = Url::parse(&proxy).with_context(|| format!("Invalid proxy url {}", proxy))?;
+            info!(
+                "Using proxy {}:{} for twitch",
+                url.host_str().unwrap_or_default(),
+                url.port_or_known_default().unwrap_or_default()
+            );
+            Ok(Some(url))
+        }
+        None => Ok(None),
+    }
+}
+
+/// http client going through proxy if any
+pub fn http_client(proxy: Option<&Url>) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent("samousse-rs")
+        .redirect(reqwest::redirect::Policy::none());
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy.as_str())?);
+    }
+    Ok(builder.build()?)
+}
+
+/// connect to a websocket, tunneled through proxy with CONNECT if any
+pub async fn connect_websocket(
+    url: &Url,
+    proxy: Option<&Url>,
+    config: WebSocketConfig,
+) -> anyhow::Result<WebSocket> {
+    let Some(proxy) = proxy else {
+        let (socket, _) =
+            tokio_tungstenite::connect_async_with_config(url, Some(config), false).await?;
+        return Ok(socket);
+    };
+    let stream = connect_tunnel(url, proxy).await?;
+    let (socket, _) =
+        tokio_tungstenite::client_async_tls_with_config(url.as_str(), stream, Some(config), None)
+            .await
+            .context("Websocket handshake through the proxy failed")?;
+    Ok(socket)
+}
+
+/// open a tcp connection to the host of url through an http proxy
+async fn connect_tunnel(url: &Url, proxy: &Url) -> anyhow::Result<TcpStream> {
+    if proxy.scheme() != "http" {
+        bail!(
+            "Only http proxies can tunnel the websocket, {} isn't supported",
+            proxy.scheme()
+        );
+    }
+    let proxy_host = proxy.host_str().ok_or(anyhow!("Proxy url has no host"))?;
+    let proxy_port = proxy.port_or_known_default().unwrap_or(80);
+    let host = url
+        .host_str()
+        .ok_or(anyhow!("Websocket url {} has no host", url))?;
+    let port = url
+        .port_or_known_default()
+        .ok_or(anyhow!("Websocket url {} has no port", url))?;
+
+    let mut stream = TcpStream::connect((proxy_host, proxy_port))
+        .await
+        .with_context(|| format!("Can't connect to proxy {}:{}", proxy_host, proxy_port))?;
+    let mut request = format!("CONNECT {0}:{1} HTTP/1.1\r\nHost: {0}:{1}\r\n", host, port);
+    if !proxy.username().is_empty() {
+        let credentials = format!(
+            "{}:{}",
+            proxy.username(),
+            proxy.password().unwrap_or_default()
+        );
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            STANDARD.encode(credentials)
+        ));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // read byte by byte so that nothing after the headers, which belongs to the tunnel, is lost
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() > PROXY_RESPONSE_MAX_LENGTH {
+            bail!("Proxy answer to CONNECT is too long");
+        }
+        let byte = stream
+            .read_u8()
+            .await
+            .context("Proxy closed the connection before accepting the tunnel")?;
+        response.push(byte);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        bail!(
+            "Proxy refused the tunnel to {}:{} : {}",
+            host,
+            port,
+            status_line
+        );
+    }
+    debug!("Tunnel to {}:{} opened through the proxy", host, port);
+    Ok(stream)
+}
@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+
+use crate::config::QuietHoursConfig;
+
+// format of start and end
+const TIME_FORMAT: &str = "%H:%M";
+
+/// time range of each day during which stream events aren't announced
+#[derive(Debug, Clone)]
+pub struct QuietHours {
+    timezone: Tz,
+    start: NaiveTime,
+    end: NaiveTime,
+    // announce the held events when the range ends instead of dropping them
+    pub hold: bool,
+}
+
+impl QuietHours {
+    pub fn new(config: &QuietHoursConfig) -> anyhow::Result<QuietHours> {
+        let timezone = config.timezone.parse::<Tz>().map_err(|e| {
+            anyhow!(
+                "quiet_hours.timezone \"{}\" isn't a timezone like Europe/Paris : {}",
+                config.timezone,
+                e
+            )
+        })?;
+        let start = NaiveTime::parse_from_str(&config.start, TIME_FORMAT)
+            .with_context(|| format!("quiet_hours.start \"{}\" isn't like 02:00", config.start))?;
+        let end = NaiveTime::parse_from_str(&config.end, TIME_FORMAT)
+            .with_context(|| format!("quiet_hours.end \"{}\" isn't like 08:00", config.end))?;
+        if start == end {
+            return Err(anyhow!("quiet_hours.start and quiet_hours.end must differ"));
+        }
+        Ok(QuietHours {
+            timezone,
+            start,
+            end,
+            hold: config.hold,
+        })
+    }
+
+    /// true if now is in the range, which ends the next day if it crosses midnight
+    pub fn is_quiet(&self, now: DateTime<Utc>) -> bool {
+        let time = now.with_timezone(&self.timezone).time();
+        match self.start < self.end {
+            true => self.start <= time && time < self.end,
+            false => time >= self.start || time < self.end,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn quiet_hours(timezone: &str, start: &str, end: &str) -> QuietHours {
+        QuietHours::new(&QuietHoursConfig {
+            timezone: timezone.to_string(),
+            start: start.to_string(),
+            end: end.to_string(),
+            hold: false,
+        })
+        .unwrap()
+    }
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 15, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn range_crossing_midnight() {
+        let quiet_hours = quiet_hours("UTC", "22:00", "06:00");
+        assert!(quiet_hours.is_quiet(at(23, 30)));
+        assert!(quiet_hours.is_quiet(at(0, 0)));
+        assert!(quiet_hours.is_quiet(at(5, 59)));
+        assert!(!quiet_hours.is_quiet(at(6, 0)));
+        assert!(!quiet_hours.is_quiet(at(21, 59)));
+    }
+
+    #[test]
+    fn range_in_a_day() {
+        let quiet_hours = quiet_hours("UTC", "02:00", "08:00");
+        assert!(quiet_hours.is_quiet(at(2, 0)));
+        assert!(!quiet_hours.is_quiet(at(8, 0)));
+        assert!(!quiet_hours.is_quiet(at(23, 0)));
+    }
+
+    #[test]
+    fn follows_daylight_saving_time() {
+        let quiet_hours = quiet_hours("Europe/Paris", "02:00", "08:00");
+        // 06:30 UTC is 07:30 in Paris during winter, 08:30 during summer
+        assert!(quiet_hours.is_quiet(at(6, 30)));
+        let summer = Utc.with_ymd_and_hms(2024, 7, 15, 6, 30, 0).unwrap();
+        assert!(!quiet_hours.is_quiet(summer));
+    }
+
+    #[test]
+    fn invalid_timezone_is_rejected() {
+        let config = QuietHoursConfig {
+            timezone: String::from("+02:00"),
+            start: String::from("02:00"),
+            end: String::from("08:00"),
+            hold: false,
+        };
+        assert!(QuietHours::new(&config).is_err());
+    }
+}
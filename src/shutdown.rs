@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use tokio::sync::{oneshot, watch};
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+// time given to each task to stop before giving up on it
+const TASK_STOP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// wait for ctrl-c, or SIGTERM on unix
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(why) => warn!("Can't listen to SIGTERM {}", why),
+        }
+    }
+    if let Err(why) = tokio::signal::ctrl_c().await {
+        warn!("Can't listen to ctrl-c {}", why);
+        std::future::pending::<()>().await;
+    }
+}
+
+/// on signal, stop the twitch side first so that no new event arrives, then the discord side
+/// each side acknowledges by completing its oneshot, which it does when its task returns
+pub async fn coordinate(
+    stop_twitch: watch::Sender<bool>,
+    twitch_stopped: oneshot::Receiver<()>,
+    stop_discord: watch::Sender<bool>,
+    discord_stopped: oneshot::Receiver<()>,
+) {
+    wait_for_signal().await;
+    info!("Shutting down, stopping twitch");
+    let _ = stop_twitch.send(true);
+    if timeout(TASK_STOP_TIMEOUT, twitch_stopped).await.is_err() {
+        warn!("Twitch didn't stop in time, stopping discord anyway");
+    }
+    info!("Stopping discord");
+    let _ = stop_discord.send(true);
+    if timeout(TASK_STOP_TIMEOUT, discord_stopped).await.is_err() {
+        warn!("Discord didn't stop in time, exiting anyway");
+    }
+    info!("Shutdown complete");
+}
@@ -0,0 +1,94 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::info;
+
+use crate::config::StoreConfig;
+use crate::store::file::FileStore;
+use crate::store::sqlite::SqliteStore;
+
+mod file;
+#[cfg(test)]
+pub mod memory;
+mod sqlite;
+
+// key of the twitch token
+pub const TWITCH_TOKEN_KEY: &str = "twitch_cache";
+// key of the channels renamed by the bot
+pub const RENAMED_CHANNELS_KEY: &str = "renamed_channels";
+// key of the users to notify when a streamer goes live
+pub const NOTIFY_SUBSCRIPTIONS_KEY: &str = "notify_subscriptions";
+// key of the end of the snoozes of streamers
+pub const SNOOZES_KEY: &str = "snoozes";
+// key of the renamed channel names set for specific channels
+pub const CHANNEL_TEMPLATES_KEY: &str = "channel_templates";
+// key of the renamed channel names set by streamers for their own channel
+pub const USER_TEMPLATES_KEY: &str = "user_templates";
+// key of the timers changed by /tuning_set
+pub const TUNING_KEY: &str = "tuning";
+// key of the scheduled messages
+pub const SCHEDULES_KEY: &str = "schedules";
+// key of the go-live announces of the current streams
+pub const ANNOUNCE_MESSAGES_KEY: &str = "announce_messages";
+// key of the forum threads of the current streams
+pub const ANNOUNCE_THREADS_KEY: &str = "announce_threads";
+
+/// key-value storage of the state that must survive a restart
+/// values are json documents
+pub trait Store: Send + Sync + Debug {
+    fn get(&self, key: &str) -> anyhow::Result<Option<String>>;
+    fn set(&self, key: &str, value: &str) -> anyhow::Result<()>;
+}
+
+impl dyn Store + '_ {
+    pub fn load<T: DeserializeOwned>(&self, key: &str) -> anyhow::Result<Option<T>> {
+        match self.get(key)? {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save<T: Serialize>(&self, key: &str, value: &T) -> anyhow::Result<()> {
+        self.set(key, &serde_json::to_string(value)?)
+    }
+}
+
+pub fn create_store(config: &StoreConfig) -> anyhow::Result<Arc<dyn Store>> {
+    Ok(match config {
+        StoreConfig::File { directory } => {
+            info!("Using file store in {}", directory.display());
+            Arc::new(FileStore::new(directory.clone()))
+        }
+        StoreConfig::Sqlite { path } => {
+            info!("Using sqlite store {}", path.display());
+            Arc::new(SqliteStore::new(path)?)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::store::memory::MemoryStore;
+
+    #[test]
+    fn missing_key_loads_none() {
+        let store: &dyn Store = &MemoryStore::default();
+        assert_eq!(store.load::<u64>(TUNING_KEY).unwrap(), None);
+    }
+
+    #[test]
+    fn saved_value_loads_back() {
+        let store: &dyn Store = &MemoryStore::default();
+        let snoozes = HashMap::from([(String::from("1234"), 42u64)]);
+        store.save(SNOOZES_KEY, &snoozes).unwrap();
+        assert_eq!(
+            store.load::<HashMap<String, u64>>(SNOOZES_KEY).unwrap(),
+            Some(snoozes)
+        );
+    }
+}
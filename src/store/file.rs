@@ -0,0 +1,36 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use crate::store::Store;
+
+/// store every key in its own json file
+#[derive(Debug)]
+pub struct FileStore {
+    directory: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(directory: PathBuf) -> FileStore {
+        FileStore { directory }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.directory.join(format!("{}.json", key))
+    }
+}
+
+impl Store for FileStore {
+    fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        match fs::read_to_string(self.path(key)) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str) -> anyhow::Result<()> {
+        fs::write(self.path(key), value)?;
+        Ok(())
+    }
+}
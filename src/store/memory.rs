@@ -0,0 +1,24 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::store::Store;
+
+/// store keeping every value in memory, for tests
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    values: Mutex<HashMap<String, String>>,
+}
+
+impl Store for MemoryStore {
+    fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.values.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &str) -> anyhow::Result<()> {
+        self.values
+            .lock()
+            .unwrap()
+            .insert(key.to_owned(), value.to_owned());
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::store::Store;
+
+/// store every key as a row of a sqlite table
+/// ":memory:" can be used as path to get a store that isn't persisted
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn new(path: &Path) -> anyhow::Result<SqliteStore> {
+        let connection = Connection::open(path)?;
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS store (key TEXT PRIMARY KEY, value TEXT NOT NULL)",
+            (),
+        )?;
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    fn connection(&self) -> anyhow::Result<std::sync::MutexGuard<'_, Connection>> {
+        self.connection
+            .lock()
+            .map_err(|_| anyhow!("Sqlite connection is poisoned"))
+    }
+}
+
+impl Store for SqliteStore {
+    fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        Ok(self
+            .connection()?
+            .query_row(
+                "SELECT value FROM store WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn set(&self, key: &str, value: &str) -> anyhow::Result<()> {
+        self.connection()?.execute(
+            "INSERT INTO store (key, value) VALUES (?1, ?2) \
+            ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+}
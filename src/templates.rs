@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+
+use crate::config::EventKind;
+
+/// placeholders that can be used in the template of each kind of event
+pub fn placeholders(kind: EventKind) -> &'static [&'static str] {
+    match kind {
+        EventKind::Online | EventKind::Offline => &["login", "url"],
+    }
+}
+
+/// placeholders that can be used in the names given to renamed channels
+pub const CHANNEL_NAME_PLACEHOLDERS: &[&str] = &["game"];
+
+/// template used when the config doesn't override it
+pub fn default_template(kind: EventKind) -> &'static str {
+    match kind {
+        EventKind::Online => "{login} is live! {url}",
+        EventKind::Offline => "{login} stream has ended",
+    }
+}
+
+/// texts of the announces of each kind of event
+#[derive(Debug, Clone, Default)]
+pub struct AnnounceTemplates {
+    // overrides of the default templates
+    templates: HashMap<EventKind, String>,
+}
+
+impl AnnounceTemplates {
+    pub fn new(templates: HashMap<EventKind, String>) -> AnnounceTemplates {
+        AnnounceTemplates { templates }
+    }
+
+    pub fn template(&self, kind: EventKind) -> &str {
+        self.templates
+            .get(&kind)
+            .map(String::as_str)
+            .unwrap_or(default_template(kind))
+    }
+
+    /// text of the announce of an event, values are the placeholders of its kind
+    pub fn render(&self, kind: EventKind, values: &[(&str, &str)]) -> String {
+        render(self.template(kind), values)
+    }
+}
+
+/// replace every {name} of template by its value, unknown placeholders are kept as is
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut text = template.to_owned();
+    for (name, value) in values {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+/// check that template only uses the placeholders of its kind of event
+pub fn validate(kind: EventKind, template: &str) -> anyhow::Result<()> {
+    check_placeholders(
+        &format!("the {:?} template", kind),
+        template,
+        placeholders(kind),
+    )
+}
+
+/// check that template only uses allowed placeholders, name is what template is in errors
+pub fn check_placeholders(name: &str, template: &str, allowed: &[&str]) -> anyhow::Result<()> {
+    let unknown: Vec<&str> = placeholder_names(template)
+        .into_iter()
+        .filter(|name| !allowed.contains(name))
+        .collect();
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "{} uses unknown placeholders {}, only {} are available",
+        name,
+        unknown
+            .iter()
+            .map(|name| format!("{{{}}}", name))
+            .collect::<Vec<String>>()
+            .join(", "),
+        allowed
+            .iter()
+            .map(|name| format!("{{{}}}", name))
+            .collect::<Vec<String>>()
+            .join(", ")
+    ))
+}
+
+/// names of the {name} in template, braces around other text aren't placeholders
+fn placeholder_names(template: &str) -> Vec<&str> {
+    let mut names = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let name = &rest[..end];
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            names.push(name);
+            rest = &rest[end + 1..];
+        }
+    }
+    names
+}
@@ -1,2 +1,8 @@
+mod auth;
+mod backoff;
+pub mod chat;
+pub mod circuit_breaker;
+pub mod health;
+mod log_limiter;
+mod rate_limit;
 pub mod websocket;
-mod auth;
\ No newline at end of file
@@ -1,13 +1,15 @@
 use std::env::var;
-use std::fmt::Error;
 use std::fs;
-use std::fs::File;
-use std::io::Write;
 
 use anyhow::anyhow;
 use reqwest::{header, StatusCode};
 use serde::{Deserialize, Serialize};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+
+use crate::store::{Store, TWITCH_TOKEN_KEY};
+
+// base url of the twitch authentication endpoints
+const DEFAULT_ID_URL: &str = "https://id.twitch.tv/oauth2";
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TwitchToken {
@@ -22,65 +24,273 @@ pub fn get_client_ids() -> (String, String) {
     )
 }
 
-impl TwitchToken {
-    pub async fn new() -> anyhow::Result<TwitchToken> {
-        let cred = get_client_ids();
-        let config_path = var("TWITCH_CACHE_PATH").unwrap_or(String::from("./twitch_cache.json"));
+/// base url of the twitch authentication endpoints, TWITCH_ID_URL overrides it to use a mock
+/// server like the one of the twitch cli
+pub fn id_url() -> String {
+    var("TWITCH_ID_URL")
+        .map(|url| url.trim_end_matches('/').to_owned())
+        .unwrap_or_else(|_| String::from(DEFAULT_ID_URL))
+}
 
-        let http_client = reqwest::Client::new();
+/// token written to TWITCH_CACHE_PATH by the versions without the store
+fn load_legacy_token() -> Option<TwitchToken> {
+    let path = var("TWITCH_CACHE_PATH").ok()?;
+    let token = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+    info!("Loaded twitch token from {}", path);
+    Some(token)
+}
 
-        let mut twitch_token =
-            serde_json::from_str::<TwitchToken>(&fs::read_to_string(&config_path).unwrap_or(
-                String::from("{\"access_token\":\"\",\"refresh_token\":\"\"}"),
-            ))?;
+/// answer of the validate endpoint
+#[derive(Deserialize, Debug)]
+pub struct TokenValidation {
+    pub login: String,
+    pub user_id: String,
+    pub scopes: Vec<String>,
+    /// seconds before the token expires
+    pub expires_in: u64,
+}
+
+impl TwitchToken {
+    /// fails only if no usable token can be obtained, the store may be read-only
+    /// id_url is the base url of the authentication endpoints, see id_url()
+    pub async fn new(
+        store: &dyn Store,
+        client: &reqwest::Client,
+        id_url: &str,
+    ) -> anyhow::Result<TwitchToken> {
+        let stored = store
+            .load::<TwitchToken>(TWITCH_TOKEN_KEY)
+            .unwrap_or_else(|e| {
+                warn!("Can't read the stored twitch token, using env vars : {}", e);
+                None
+            });
+        let mut changed = stored.is_none();
+        let mut twitch_token = stored.or_else(load_legacy_token).unwrap_or(TwitchToken {
+            access_token: String::new(),
+            refresh_token: String::new(),
+        });
 
         if twitch_token.access_token.is_empty() {
             if let Ok(env) = var("TWITCH_ACCESS_TOKEN") {
                 twitch_token.access_token = env;
+                changed = true;
                 info!("Filled access token from env vars");
             }
         }
         if twitch_token.refresh_token.is_empty() {
             if let Ok(env) = var("TWITCH_REFRESH_TOKEN") {
                 twitch_token.refresh_token = env;
+                changed = true;
                 info!("Filled refresh token from env vars");
             }
         }
 
-        debug!("Loaded TwitchToken file, checking validity");
-        let res = http_client
-            .get("https://id.twitch.tv/oauth2/validate")
+        debug!("Loaded TwitchToken, checking validity");
+        if twitch_token.validate(client, id_url).await?.is_some() {
+            debug!("Token is valid");
+        } else {
+            info!("Token expired, trying to logging");
+            twitch_token.refresh(client, id_url).await?;
+            changed = true;
+        }
+
+        if changed {
+            twitch_token.persist(store);
+        }
+
+        Ok(twitch_token)
+    }
+
+    /// save the token, it is kept in memory only if the store can't be written, like when the
+    /// token is mounted read-only
+    pub fn persist(&self, store: &dyn Store) {
+        if let Err(e) = store.save(TWITCH_TOKEN_KEY, self) {
+            warn!(
+                "Can't save the twitch token, it is only kept in memory until restart : {}",
+                e
+            );
+        }
+    }
+
+    /// return None if the access token isn't valid
+    pub async fn validate(
+        &self,
+        client: &reqwest::Client,
+        id_url: &str,
+    ) -> anyhow::Result<Option<TokenValidation>> {
+        let res = client
+            .get(format!("{}/validate", id_url))
             .header(
                 header::AUTHORIZATION,
-                "Bearer ".to_owned() + &twitch_token.access_token,
+                "Bearer ".to_owned() + &self.access_token,
             )
             .send()
             .await?;
         if res.status() == StatusCode::OK {
-            debug!("Token is valid");
+            Ok(Some(res.json::<TokenValidation>().await?))
         } else {
-            info!("Token expired, trying to logging");
-            let res = http_client
-                .post("https://id.twitch.tv/oauth2/token")
-                .form(&vec![
-                    ("client_id", cred.0),
-                    ("client_secret", cred.1),
-                    ("grant_type", String::from("refresh_token")),
-                    ("refresh_token", twitch_token.refresh_token),
-                ])
-                .send()
-                .await?;
-
-            if res.status() != StatusCode::OK {
-                panic!("Auth failed");
-            }
+            Ok(None)
+        }
+    }
+
+    /// get a new access token using the refresh token
+    pub async fn refresh(&mut self, client: &reqwest::Client, id_url: &str) -> anyhow::Result<()> {
+        let cred = get_client_ids();
+        let res = client
+            .post(format!("{}/token", id_url))
+            .form(&vec![
+                ("client_id", cred.0),
+                ("client_secret", cred.1),
+                ("grant_type", String::from("refresh_token")),
+                ("refresh_token", self.refresh_token.clone()),
+            ])
+            .send()
+            .await?;
 
-            twitch_token = res.json::<TwitchToken>().await?;
+        if res.status() != StatusCode::OK {
+            return Err(anyhow!("Auth failed with status {}", res.status()));
         }
 
-        let mut fi = File::create(&config_path)?;
-        fi.write_all(serde_json::to_string(&twitch_token)?.as_bytes())?;
+        *self = res.json::<TwitchToken>().await?;
+        Ok(())
+    }
+}
 
-        Ok(twitch_token)
+#[cfg(test)]
+mod tests {
+    use std::env::set_var;
+
+    use serde_json::json;
+    use wiremock::matchers::{body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::store::memory::MemoryStore;
+
+    fn token(access_token: &str, refresh_token: &str) -> TwitchToken {
+        TwitchToken {
+            access_token: access_token.to_owned(),
+            refresh_token: refresh_token.to_owned(),
+        }
+    }
+
+    fn stored_token(store: &dyn Store) -> Option<(String, String)> {
+        store
+            .load::<TwitchToken>(TWITCH_TOKEN_KEY)
+            .unwrap()
+            .map(|t| (t.access_token, t.refresh_token))
+    }
+
+    async fn server() -> MockServer {
+        // same values in every test, so they can run in parallel
+        set_var("TWITCH_CLIENT_ID", "client-id");
+        set_var("TWITCH_CLIENT_SECRET", "client-secret");
+        MockServer::start().await
+    }
+
+    async fn mock_validate(server: &MockServer, access_token: &str, status: u16) {
+        Mock::given(method("GET"))
+            .and(path("/validate"))
+            .and(header("authorization", format!("Bearer {}", access_token)))
+            .respond_with(ResponseTemplate::new(status).set_body_json(json!({
+                "login": "samousse",
+                "user_id": "1234",
+                "scopes": [],
+                "expires_in": 3600,
+            })))
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn valid_token_is_kept() {
+        let server = server().await;
+        mock_validate(&server, "valid", 200).await;
+        let store: &dyn Store = &MemoryStore::default();
+        store
+            .save(TWITCH_TOKEN_KEY, &token("valid", "refresh"))
+            .unwrap();
+
+        let token = TwitchToken::new(store, &reqwest::Client::new(), &server.uri())
+            .await
+            .unwrap();
+
+        assert_eq!(token.access_token, "valid");
+        assert_eq!(
+            stored_token(store),
+            Some((String::from("valid"), String::from("refresh")))
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_token_is_refreshed_and_stored() {
+        let server = server().await;
+        mock_validate(&server, "expired", 401).await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(body_string_contains("refresh_token=old-refresh"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "fresh",
+                "refresh_token": "new-refresh",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let store: &dyn Store = &MemoryStore::default();
+        store
+            .save(TWITCH_TOKEN_KEY, &token("expired", "old-refresh"))
+            .unwrap();
+
+        let token = TwitchToken::new(store, &reqwest::Client::new(), &server.uri())
+            .await
+            .unwrap();
+
+        assert_eq!(token.access_token, "fresh");
+        assert_eq!(
+            stored_token(store),
+            Some((String::from("fresh"), String::from("new-refresh")))
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_refresh_keeps_the_stored_token() {
+        let server = server().await;
+        mock_validate(&server, "expired", 401).await;
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(ResponseTemplate::new(400))
+            .mount(&server)
+            .await;
+        let store: &dyn Store = &MemoryStore::default();
+        store
+            .save(TWITCH_TOKEN_KEY, &token("expired", "revoked"))
+            .unwrap();
+
+        let result = TwitchToken::new(store, &reqwest::Client::new(), &server.uri()).await;
+
+        assert!(result.is_err());
+        assert_eq!(
+            stored_token(store),
+            Some((String::from("expired"), String::from("revoked")))
+        );
+    }
+
+    #[tokio::test]
+    async fn token_from_env_vars_is_stored() {
+        let server = server().await;
+        // only this test has an empty store, so only it reads these
+        set_var("TWITCH_ACCESS_TOKEN", "from-env");
+        set_var("TWITCH_REFRESH_TOKEN", "refresh-from-env");
+        mock_validate(&server, "from-env", 200).await;
+        let store: &dyn Store = &MemoryStore::default();
+
+        TwitchToken::new(store, &reqwest::Client::new(), &server.uri())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            stored_token(store),
+            Some((String::from("from-env"), String::from("refresh-from-env")))
+        );
     }
 }
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use rand::{thread_rng, Rng};
+
+/// delay before the next attempt after consecutive failures, doubled after each one up to max
+/// a random part is taken off so that clients failing together don't retry together
+#[derive(Debug)]
+pub struct Backoff {
+    failures: u32,
+    initial: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Backoff {
+        Backoff {
+            failures: 0,
+            initial,
+            max,
+        }
+    }
+
+    /// delay to wait before the next attempt, counting a new failure
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(self.failures))
+            .min(self.max);
+        self.failures = self.failures.saturating_add(1);
+        // between half the delay and the full delay
+        delay.mul_f64(thread_rng().gen_range(0.5..=1.0))
+    }
+
+    /// consecutive failures since the last reset
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// the attempt succeeded, the next failure waits initial again
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backoff() -> Backoff {
+        Backoff::new(Duration::from_secs(1), Duration::from_secs(10))
+    }
+
+    #[test]
+    fn doubles_after_each_failure() {
+        let mut backoff = backoff();
+        for expected in [1, 2, 4, 8] {
+            let full = Duration::from_secs(expected);
+            let delay = backoff.next_delay();
+            assert!(
+                delay >= full / 2 && delay <= full,
+                "{:?} for {:?}",
+                delay,
+                full
+            );
+        }
+        assert_eq!(backoff.failures(), 4);
+    }
+
+    #[test]
+    fn capped_at_max() {
+        let mut backoff = backoff();
+        for _ in 0..100 {
+            assert!(backoff.next_delay() <= Duration::from_secs(10));
+        }
+        let delay = backoff.next_delay();
+        assert!(delay >= Duration::from_secs(5));
+    }
+
+    #[test]
+    fn reset_starts_again_from_initial() {
+        let mut backoff = backoff();
+        for _ in 0..5 {
+            backoff.next_delay();
+        }
+        backoff.reset();
+        assert_eq!(backoff.failures(), 0);
+        assert!(backoff.next_delay() <= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+use http::{header, Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use twitch_api::client::{Bytes, ResponseExt};
+use twitch_api::twitch_oauth2::{TwitchToken, UserToken};
+use twitch_api::{HelixClient, HttpClient, TWITCH_HELIX_URL};
+use url::Url;
+
+use crate::config::ChatRelayConfig;
+use crate::inter_comm::ChatMessage;
+use crate::twitch::rate_limit::RateLimitedClient;
+
+// twitch_api doesn't know this subscription type, so it is handled without it
+pub const CHAT_MESSAGE_TYPE: &str = "channel.chat.message";
+// scope the token needs to receive chat messages
+pub const CHAT_SCOPE: &str = "user:read:chat";
+// subscriptions listed per request, the maximum allowed by twitch
+const SUBSCRIPTIONS_PAGE_SIZE: &str = "100";
+
+/// websocket frame of a chat subscription
+pub enum ChatFrame {
+    Message {
+        broadcaster_user_id: String,
+        broadcaster_user_login: String,
+        chatter_user_login: String,
+        message: ChatMessage,
+    },
+    Revocation {
+        broadcaster_user_id: String,
+        status: String,
+    },
+    Other,
+}
+
+#[derive(Deserialize)]
+struct RawFrame {
+    metadata: RawMetadata,
+    #[serde(default)]
+    payload: RawPayload,
+}
+
+#[derive(Deserialize)]
+struct RawMetadata {
+    message_type: String,
+    #[serde(default)]
+    subscription_type: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct RawPayload {
+    subscription: Option<RawSubscription>,
+    event: Option<RawChatEvent>,
+}
+
+#[derive(Deserialize)]
+struct RawSubscription {
+    id: String,
+    status: String,
+    condition: RawCondition,
+}
+
+#[derive(Deserialize)]
+struct RawCondition {
+    broadcaster_user_id: String,
+}
+
+#[derive(Deserialize)]
+struct RawChatEvent {
+    broadcaster_user_id: String,
+    broadcaster_user_login: String,
+    chatter_user_login: String,
+    chatter_user_name: String,
+    message: RawChatText,
+    #[serde(default)]
+    badges: Vec<RawBadge>,
+}
+
+#[derive(Deserialize)]
+struct RawChatText {
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct RawBadge {
+    set_id: String,
+}
+
+#[derive(Deserialize)]
+struct RawSubscriptions<T> {
+    data: Vec<T>,
+    #[serde(default)]
+    pagination: RawPagination,
+}
+
+#[derive(Deserialize, Default)]
+struct RawPagination {
+    cursor: Option<String>,
+}
+
+/// chat subscription as seen by twitch
+pub struct ChatSubscription {
+    pub id: String,
+    pub broadcaster_user_id: String,
+    pub status: String,
+}
+
+/// parse frame if it belongs to a chat subscription, None otherwise so that twitch_api parses it
+pub fn parse_chat_frame(frame: &str) -> Option<anyhow::Result<ChatFrame>> {
+    // avoid parsing every frame twice
+    if !frame.contains(CHAT_MESSAGE_TYPE) {
+        return None;
+    }
+    let raw: RawFrame = match serde_json::from_str(frame) {
+        Ok(raw) => raw,
+        Err(why) => return Some(Err(why.into())),
+    };
+    if raw.metadata.subscription_type.as_deref() != Some(CHAT_MESSAGE_TYPE) {
+        return None;
+    }
+    let parsed = match (raw.metadata.message_type.as_str(), raw.payload) {
+        (
+            "notification",
+            RawPayload {
+                event: Some(event), ..
+            },
+        ) => ChatFrame::Message {
+            broadcaster_user_id: event.broadcaster_user_id,
+            broadcaster_user_login: event.broadcaster_user_login,
+            chatter_user_login: event.chatter_user_login,
+            message: ChatMessage {
+                author: event.chatter_user_name,
+                text: event.message.text,
+                badges: event.badges.into_iter().map(|b| b.set_id).collect(),
+            },
+        },
+        (
+            "revocation",
+            RawPayload {
+                subscription: Some(subscription),
+                ..
+            },
+        ) => ChatFrame::Revocation {
+            broadcaster_user_id: subscription.condition.broadcaster_user_id,
+            status: subscription.status,
+        },
+        _ => ChatFrame::Other,
+    };
+    Some(Ok(parsed))
+}
+
+/// decides which chat messages are relayed, so that the discord side isn't flooded
+pub struct ChatFilter {
+    // lowercase logins
+    ignored_chatters: Vec<String>,
+    command_prefixes: Vec<String>,
+}
+
+impl ChatFilter {
+    pub fn new(config: &ChatRelayConfig) -> ChatFilter {
+        ChatFilter {
+            ignored_chatters: config
+                .ignored_chatters
+                .iter()
+                .map(|c| c.to_lowercase())
+                .collect(),
+            command_prefixes: config.command_prefixes.clone(),
+        }
+    }
+
+    pub fn accepts(&self, chatter_user_login: &str, message: &ChatMessage) -> bool {
+        let text = message.text.trim_start();
+        !text.is_empty()
+            && !self
+                .ignored_chatters
+                .iter()
+                .any(|c| c == chatter_user_login)
+            && !self
+                .command_prefixes
+                .iter()
+                .any(|p| !p.is_empty() && text.starts_with(p.as_str()))
+    }
+}
+
+/// send a request to a helix endpoint twitch_api doesn't support, through its client so that
+/// it shares the base url and the rate limit of the other requests
+async fn helix_request(
+    client: &HelixClient<'static, RateLimitedClient>,
+    token: &UserToken,
+    method: Method,
+    url: Url,
+    body: Option<Value>,
+) -> anyhow::Result<(StatusCode, Bytes)> {
+    let request = http::Request::builder()
+        .method(method)
+        .uri(url.as_str())
+        .header("Client-Id", token.client_id().as_str())
+        .header(
+            header::AUTHORIZATION,
+            format!("Bearer {}", token.token().secret()),
+        )
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(match body {
+            Some(body) => Bytes::from(serde_json::to_vec(&body)?),
+            None => Bytes::new(),
+        })?;
+    let response = client
+        .get_client()
+        .req(request)
+        .await?
+        .into_response_bytes()
+        .await?;
+    Ok((response.status(), response.into_body()))
+}
+
+fn subscriptions_url() -> anyhow::Result<Url> {
+    Ok(TWITCH_HELIX_URL.join("eventsub/subscriptions")?)
+}
+
+/// subscribe to the chat messages of broadcaster_user_id as read by the user of the token
+pub async fn create_chat_subscription(
+    client: &HelixClient<'static, RateLimitedClient>,
+    token: &UserToken,
+    broadcaster_user_id: &str,
+    user_id: &str,
+    session_id: &str,
+) -> anyhow::Result<String> {
+    let body = json!({
+        "type": CHAT_MESSAGE_TYPE,
+        "version": "1",
+        "condition": {
+            "broadcaster_user_id": broadcaster_user_id,
+            "user_id": user_id,
+        },
+        "transport": {
+            "method": "websocket",
+            "session_id": session_id,
+        },
+    });
+    let (status, body) = helix_request(
+        client,
+        token,
+        Method::POST,
+        subscriptions_url()?,
+        Some(body),
+    )
+    .await?;
+    if status != StatusCode::ACCEPTED {
+        return Err(anyhow!(
+            "Chat subscription for {} failed with status {} : {}",
+            broadcaster_user_id,
+            status,
+            String::from_utf8_lossy(&body)
+        ));
+    }
+    serde_json::from_slice::<RawSubscriptions<RawSubscription>>(&body)?
+        .data
+        .into_iter()
+        .next()
+        .map(|s| s.id)
+        .ok_or(anyhow!("Twitch didn't return the chat subscription"))
+}
+
+/// every chat subscription existing on twitch api side
+pub async fn get_chat_subscriptions(
+    client: &HelixClient<'static, RateLimitedClient>,
+    token: &UserToken,
+) -> anyhow::Result<Vec<ChatSubscription>> {
+    let subscriptions: Vec<RawSubscription> =
+        get_subscriptions_of_type(client, token, CHAT_MESSAGE_TYPE).await?;
+    Ok(subscriptions
+        .into_iter()
+        .map(|s| ChatSubscription {
+            id: s.id,
+            broadcaster_user_id: s.condition.broadcaster_user_id,
+            status: s.status,
+        })
+        .collect())
+}
+
+/// every subscription of subscription_type existing on twitch api side, twitch_api isn't used
+/// since it sends the type filter as type_, which twitch ignores
+pub async fn get_subscriptions_of_type<T: DeserializeOwned>(
+    client: &HelixClient<'static, RateLimitedClient>,
+    token: &UserToken,
+    subscription_type: &str,
+) -> anyhow::Result<Vec<T>> {
+    let mut subscriptions = vec![];
+    // every page is followed, reconciling a partial list would recreate existing subs
+    let mut cursors: HashSet<String> = HashSet::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut url = subscriptions_url()?;
+        url.query_pairs_mut()
+            .append_pair("type", subscription_type)
+            .append_pair("first", SUBSCRIPTIONS_PAGE_SIZE);
+        if let Some(cursor) = &cursor {
+            url.query_pairs_mut().append_pair("after", cursor);
+        }
+        let (status, body) = helix_request(client, token, Method::GET, url, None).await?;
+        if status != StatusCode::OK {
+            return Err(anyhow!(
+                "{} subscriptions list failed with status {}",
+                subscription_type,
+                status
+            ));
+        }
+        let page = serde_json::from_slice::<RawSubscriptions<T>>(&body)?;
+        subscriptions.extend(page.data);
+        match page.pagination.cursor {
+            Some(next) if next.is_empty() => return Ok(subscriptions),
+            Some(next) if !cursors.insert(next.clone()) => {
+                return Err(anyhow!(
+                    "Twitch sent the cursor {} twice when listing the {} subscriptions",
+                    next,
+                    subscription_type
+                ));
+            }
+            Some(next) => cursor = Some(next),
+            None => return Ok(subscriptions),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+/// state of the circuit breaker
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakerState {
+    /// calls are allowed, contains the count of consecutive failures
+    Closed(u32),
+    /// calls are refused until the instant
+    Open(Instant),
+    /// one call is allowed since the instant to test if the api is back, the breaker opens again
+    /// if its result isn't recorded within the cooldown
+    HalfOpen(Instant),
+}
+
+impl Display for BreakerState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BreakerState::Closed(0) => write!(f, "closed"),
+            BreakerState::Closed(failures) => write!(f, "closed ({} failures)", failures),
+            BreakerState::Open(until) => write!(
+                f,
+                "open (retrying in {}s)",
+                until.saturating_duration_since(Instant::now()).as_secs()
+            ),
+            BreakerState::HalfOpen(_) => write!(f, "half-open"),
+        }
+    }
+}
+
+/// error of a call refused because the breaker is open
+#[derive(Debug)]
+pub struct BreakerOpen;
+
+impl Display for BreakerOpen {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the api keeps failing, calls are suspended for a while")
+    }
+}
+
+impl std::error::Error for BreakerOpen {}
+
+/// stop calling an api that keeps failing
+/// the breaker opens after failure_threshold consecutive failures, refuses calls for cooldown
+/// then lets a single call through to check if the api has recovered
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    state: Mutex<BreakerState>,
+    failure_threshold: u32,
+    cooldown: Duration,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            state: Mutex::new(BreakerState::Closed(0)),
+            failure_threshold,
+            cooldown,
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        *self.state.lock().unwrap()
+    }
+
+    /// return true if a call can be made
+    pub fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        // the caller of the half-open call may never record its result
+        if let BreakerState::HalfOpen(since) = *state {
+            if now >= since + self.cooldown {
+                warn!("No result recorded for the half-open call, circuit breaker open again");
+                *state = BreakerState::Open(since + self.cooldown);
+            }
+        }
+        match *state {
+            BreakerState::Closed(_) => true,
+            BreakerState::Open(until) if now >= until => {
+                info!("Circuit breaker half-open, testing if the api has recovered");
+                *state = BreakerState::HalfOpen(now);
+                true
+            }
+            BreakerState::Open(_) | BreakerState::HalfOpen(_) => false,
+        }
+    }
+
+    /// same as allow, with an error to return when the call can't be made
+    pub fn check(&self) -> Result<(), BreakerOpen> {
+        match self.allow() {
+            true => Ok(()),
+            false => Err(BreakerOpen),
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if *state != BreakerState::Closed(0) {
+            info!("Circuit breaker closed");
+        }
+        *state = BreakerState::Closed(0);
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            BreakerState::Closed(failures) => failures + 1,
+            _ => self.failure_threshold,
+        };
+        *state = if failures >= self.failure_threshold {
+            warn!(
+                "Circuit breaker open after {} failures, waiting {:?}",
+                failures, self.cooldown
+            );
+            BreakerState::Open(Instant::now() + self.cooldown)
+        } else {
+            BreakerState::Closed(failures)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+
+    use super::*;
+
+    #[test]
+    fn open_breaker_refuses_calls() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        breaker.record_failure();
+        assert!(breaker.check().is_ok());
+        breaker.record_failure();
+
+        assert!(breaker.check().is_err());
+        assert!(matches!(breaker.state(), BreakerState::Open(_)));
+    }
+
+    #[test]
+    fn half_open_breaker_lets_one_call_through() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(50));
+        breaker.record_failure();
+        sleep(Duration::from_millis(50));
+
+        assert!(breaker.check().is_ok());
+        assert!(matches!(breaker.state(), BreakerState::HalfOpen(_)));
+        assert!(breaker.check().is_err());
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed(0));
+    }
+
+    #[test]
+    fn half_open_call_without_result_opens_again() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(50));
+        breaker.record_failure();
+        sleep(Duration::from_millis(50));
+        assert!(breaker.check().is_ok());
+
+        // nothing is recorded for the half-open call
+        assert!(breaker.check().is_err());
+        sleep(Duration::from_millis(50));
+
+        assert!(breaker.check().is_ok());
+        assert!(matches!(breaker.state(), BreakerState::HalfOpen(_)));
+    }
+}
@@ -0,0 +1,31 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::twitch::circuit_breaker::CircuitBreaker;
+
+/// state of the twitch side shared with the discord side for diagnostics
+#[derive(Debug)]
+pub struct TwitchHealth {
+    /// stop calling the Twitch API while it keeps failing
+    pub api_breaker: CircuitBreaker,
+    /// last time a message (welcome, keepalive, notification...) has been received from the
+    /// websocket, or start time if none has been
+    last_message: Mutex<Instant>,
+}
+
+impl TwitchHealth {
+    pub fn new(api_breaker: CircuitBreaker) -> TwitchHealth {
+        TwitchHealth {
+            api_breaker,
+            last_message: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn record_message(&self) {
+        *self.last_message.lock().unwrap() = Instant::now();
+    }
+
+    pub fn time_since_message(&self) -> Duration {
+        self.last_message.lock().unwrap().elapsed()
+    }
+}
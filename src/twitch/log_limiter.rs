@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// collapses a log line repeated within a window, so that a reconnect storm doesn't flood logs
+/// the first occurrence of each window is logged in full, the next ones are only counted and
+/// the count is reported by the first occurrence of the following window
+pub struct LogLimiter {
+    window: Duration,
+    // start of the current window and occurrences not logged in it, by log line and detail
+    lines: HashMap<(&'static str, String), (Instant, u64)>,
+}
+
+impl LogLimiter {
+    /// every occurrence is logged if window is zero
+    pub fn new(window: Duration) -> LogLimiter {
+        LogLimiter {
+            window,
+            lines: HashMap::new(),
+        }
+    }
+
+    /// None if this occurrence of line mustn't be logged, the number of occurrences
+    /// not logged since the previous logged one otherwise
+    /// detail is the error of the line, a different error is always logged
+    pub fn check(&mut self, line: &'static str, detail: &str) -> Option<u64> {
+        if self.window.is_zero() {
+            return Some(0);
+        }
+        let now = Instant::now();
+        let key = (line, detail.to_string());
+        match self.lines.get_mut(&key) {
+            Some((start, suppressed)) if now.duration_since(*start) < self.window => {
+                *suppressed += 1;
+                None
+            }
+            Some((start, suppressed)) => {
+                *start = now;
+                Some(std::mem::take(suppressed))
+            }
+            None => {
+                self.lines.insert(key, (now, 0));
+                Some(0)
+            }
+        }
+    }
+}
+
+/// end of a log line telling how many of its occurrences haven't been logged
+pub fn suppressed_note(suppressed: u64) -> String {
+    match suppressed {
+        0 => String::new(),
+        _ => format!(" ({} similar messages not logged)", suppressed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_line_is_suppressed() {
+        let mut limiter = LogLimiter::new(Duration::from_secs(60));
+        assert_eq!(limiter.check("reset", ""), Some(0));
+        assert_eq!(limiter.check("reset", ""), None);
+        assert_eq!(limiter.check("reset", ""), None);
+    }
+
+    #[test]
+    fn different_detail_is_logged() {
+        let mut limiter = LogLimiter::new(Duration::from_secs(60));
+        assert_eq!(limiter.check("welcome", "timeout"), Some(0));
+        assert_eq!(limiter.check("welcome", "unauthorized"), Some(0));
+        assert_eq!(limiter.check("welcome", "timeout"), None);
+    }
+
+    #[test]
+    fn zero_window_logs_everything() {
+        let mut limiter = LogLimiter::new(Duration::ZERO);
+        assert_eq!(limiter.check("reset", ""), Some(0));
+        assert_eq!(limiter.check("reset", ""), Some(0));
+    }
+}
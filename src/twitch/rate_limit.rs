@@ -0,0 +1,86 @@
+use std::env::var;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tracing::warn;
+use twitch_api::client::{BoxedFuture, Request, Response};
+use twitch_api::HttpClient;
+
+use crate::inter_comm::RateLimitInfo;
+
+/// base url replacing the one of helix, TWITCH_HELIX_URL sets it to use a mock server like the
+/// one of the twitch cli
+pub fn helix_url() -> Option<String> {
+    var("TWITCH_HELIX_URL").ok()
+}
+
+/// reqwest client keeping the rate limit reported by the last helix response
+#[derive(Clone)]
+pub struct RateLimitedClient {
+    inner: reqwest::Client,
+    latest: Arc<Mutex<Option<RateLimitInfo>>>,
+    // requests to helix are sent there instead, always ending with a /
+    helix_url: Option<String>,
+}
+
+impl RateLimitedClient {
+    pub fn new(inner: reqwest::Client, helix_url: Option<String>) -> RateLimitedClient {
+        RateLimitedClient {
+            inner,
+            latest: Arc::new(Mutex::new(None)),
+            helix_url: helix_url.map(|url| format!("{}/", url.trim_end_matches('/'))),
+        }
+    }
+
+    /// the wrapped client, for requests outside helix
+    pub fn inner(&self) -> &reqwest::Client {
+        &self.inner
+    }
+
+    /// None until a response with rate limit headers has been received
+    pub fn latest(&self) -> Option<RateLimitInfo> {
+        *self.latest.lock().unwrap()
+    }
+}
+
+impl HttpClient for RateLimitedClient {
+    type Error = reqwest::Error;
+
+    fn req(&self, mut request: Request) -> BoxedFuture<'_, Result<Response, Self::Error>> {
+        if let Some(helix_url) = &self.helix_url {
+            let uri = request.uri().to_string();
+            if let Some(path) = uri.strip_prefix(twitch_api::TWITCH_HELIX_URL.as_str()) {
+                match format!("{}{}", helix_url, path).parse() {
+                    Ok(uri) => *request.uri_mut() = uri,
+                    Err(e) => warn!("Can't send {} to {} : {}", uri, helix_url, e),
+                }
+            }
+        }
+        let response = self.inner.req(request);
+        let latest = self.latest.clone();
+        Box::pin(async move {
+            let response = response.await?;
+            if let Some(rate_limit) = parse_rate_limit(&response) {
+                *latest.lock().unwrap() = Some(rate_limit);
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// read the Ratelimit-* headers, None if they are absent
+fn parse_rate_limit(response: &Response) -> Option<RateLimitInfo> {
+    let header = |name: &str| -> Option<u64> {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+    };
+    Some(RateLimitInfo {
+        limit: header("ratelimit-limit"),
+        remaining: header("ratelimit-remaining")?,
+        reset_at: UNIX_EPOCH + Duration::from_secs(header("ratelimit-reset")?),
+        observed_at: SystemTime::now(),
+    })
+}
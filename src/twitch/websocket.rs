@@ -1,51 +1,253 @@
-use std::collections::{HashMap, HashSet};
-use std::hash::{Hash, Hasher};
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
-use anyhow::Context;
-use futures::{stream, TryStreamExt};
-use tokio::sync::mpsc::Sender;
-use tokio::time::sleep;
+use anyhow::{anyhow, Context};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::{oneshot, watch};
+use tokio::time::{interval_at, sleep, timeout, Instant, Interval};
 use tracing::{debug, error, info, trace, warn};
-use twitch_api::client::ClientDefault;
+use twitch_api::eventsub::channel::{ChannelRaidV1, ChannelUpdateV2};
 use twitch_api::eventsub::stream::{StreamOfflineV1, StreamOnlineV1};
 use twitch_api::eventsub::{
     Event, EventSubSubscription, EventType, EventsubWebsocketData, Message, ReconnectPayload,
     SessionData, WelcomePayload,
 };
-use twitch_api::types::{EventSubId, UserId, UserName};
+use twitch_api::helix::streams::GetStreamsRequest;
+use twitch_api::helix::teams::get_teams::GetTeamsRequest;
+use twitch_api::types::{EventSubId, UserId, UserIdRef, UserName};
 use twitch_api::{eventsub, HelixClient};
 use twitch_oauth2::UserToken;
 
-use crate::config::{Config, TwitchWatcher};
-use crate::inter_comm::{InterComm, MessageType};
-use crate::twitch::auth::{get_client_ids, TwitchToken};
+use crate::config::Config;
+use crate::inter_comm::{
+    InterComm, MessageType, ReconcilePlan, StreamInfo, StreamerEvent, SubscriptionInfo,
+    SubscriptionTest, TokenStatus, TwitchRequest,
+};
+use crate::proxy::{connect_websocket, http_client, proxy_url, WebSocket};
+use crate::store::Store;
+use crate::twitch::auth::{get_client_ids, id_url, TwitchToken};
+use crate::twitch::backoff::Backoff;
+use crate::twitch::chat::{
+    create_chat_subscription, get_chat_subscriptions, get_subscriptions_of_type, parse_chat_frame,
+    ChatFilter, ChatFrame, CHAT_MESSAGE_TYPE, CHAT_SCOPE,
+};
+use crate::twitch::health::TwitchHealth;
+use crate::twitch::log_limiter::{suppressed_note, LogLimiter};
+use crate::twitch::rate_limit::{helix_url, RateLimitedClient};
+
+// how often the members of the monitored teams are looked up
+const TEAM_REFRESH_INTERVAL: Duration = Duration::from_secs(3600);
+// the token is refreshed on a welcome message when it expires sooner than this
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(600);
+// delay before retrying a failed user lookup, doubled after each failure
+const LOGIN_LOOKUP_RETRY_DELAY: Duration = Duration::from_millis(500);
+// first delay before reconnecting to the websocket, doubled after each consecutive failure
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_secs(1);
+// longest delay before reconnecting to the websocket
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(120);
+// time given to twitch to list a test subscription, below the timeout of the discord side so
+// that the subscription is always deleted before it gives up
+const SUBSCRIPTION_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+// delay between two listings of a test subscription
+const SUBSCRIPTION_TEST_POLL: Duration = Duration::from_secs(1);
 
-pub async fn run(sender: Sender<InterComm>, config: &Config) -> anyhow::Result<()> {
-    let twitch_client: HelixClient<_> = HelixClient::with_client(
-        <reqwest::Client>::default_client_with_name(Some("samousse-rs".parse()?))?,
-    );
+pub async fn run(
+    sender: Sender<InterComm>,
+    config: &Config,
+    requests: Receiver<TwitchRequest>,
+    store: Arc<dyn Store>,
+    twitch_health: Arc<TwitchHealth>,
+    stopping: watch::Receiver<bool>,
+) -> anyhow::Result<()> {
+    // no twitch credentials are needed then, the requests from discord fail since requests
+    // is dropped
+    if !config.twitch_watcher.enabled {
+        info!("Twitch watcher is disabled, not connecting to twitch");
+        return Ok(());
+    }
+    let proxy = proxy_url(config)?;
+    let twitch_client: HelixClient<_> = HelixClient::with_client(RateLimitedClient::new(
+        http_client(proxy.as_ref())?,
+        helix_url(),
+    ));
 
+    let configured_user_ids: Vec<UserId> = config
+        .twitch_watcher
+        .channels
+        .iter()
+        .map(|i| UserId::new(i.twitch_channel_id.to_string()))
+        .collect();
+    // relayed chats, only when the relay channel is configured
+    let chat_user_ids: Vec<UserId> = match &config.twitch_watcher.chat_relay {
+        Some(_) => config
+            .twitch_watcher
+            .channels
+            .iter()
+            .filter(|i| i.chat_relay)
+            .map(|i| UserId::new(i.twitch_channel_id.to_string()))
+            .collect(),
+        None => vec![],
+    };
+    let id_url = id_url();
     let mut ws = WebsocketClient {
         sender,
+        requests,
         session_id: None,
-        token: TwitchToken::new()
+        token: TwitchToken::new(store.as_ref(), twitch_client.get_client().inner(), &id_url)
             .await
-            .expect("Error on loading token file"),
+            .expect("No usable twitch token"),
+        id_url,
         client: twitch_client,
-        user_ids: config
+        user_ids: configured_user_ids.clone(),
+        configured_user_ids,
+        chat_user_ids,
+        chat_filter: config
             .twitch_watcher
-            .channels
-            .iter()
-            .map(|i| UserId::new(i.twitch_channel_id.to_string()))
-            .collect(),
+            .chat_relay
+            .as_ref()
+            .map(ChatFilter::new),
+        teams: config.twitch_watcher.teams.clone(),
+        team_refresh: interval_at(
+            Instant::now() + TEAM_REFRESH_INTERVAL,
+            TEAM_REFRESH_INTERVAL,
+        ),
         connect_url: twitch_api::TWITCH_EVENTSUB_WEBSOCKET_URL.clone(),
+        proxy,
         event_sub_id: vec![],
+        live_status: HashMap::new(),
+        logins: HashMap::new(),
+        login_lookup_retries: config.twitch_watcher.login_lookup_retries,
+        twitch_health: twitch_health.clone(),
+        store,
+        stopping,
+        log_limiter: LogLimiter::new(Duration::from_secs(
+            config.twitch_watcher.repeated_log_window_seconds,
+        )),
+        reconnect_backoff: Backoff::new(RECONNECT_INITIAL_DELAY, RECONNECT_MAX_DELAY),
     };
 
+    if let Some(timeout) = config.twitch_watcher.watchdog_timeout_seconds {
+        tokio::spawn(watchdog(twitch_health, Duration::from_secs(timeout)));
+    }
+
+    loop {
+        if let Err(e) = ws.run().await {
+            ws.notify_error("stopped", format!("The twitch watcher stopped : {:#}", e))
+                .await;
+            return Err(e);
+        }
+        if *ws.stopping.borrow() {
+            return Ok(());
+        }
+    }
+}
+
+/// sleep for delay, returns false without waiting the end if the bot is stopping
+pub async fn sleep_unless_stopping(stopping: &mut watch::Receiver<bool>, delay: Duration) -> bool {
+    if *stopping.borrow() {
+        return false;
+    }
+    tokio::select! {
+        _ = sleep(delay) => true,
+        _ = stopping.changed() => false,
+    }
+}
+
+/// exit the process when the websocket stays disconnected for longer than `timeout`,
+/// so that it gets restarted by the supervisor
+async fn watchdog(twitch_health: Arc<TwitchHealth>, timeout: Duration) {
     loop {
-        ws.run().await?;
+        sleep(Duration::from_secs(60)).await;
+        let elapsed = twitch_health.time_since_message();
+        if elapsed > timeout {
+            error!(
+                "No message received from Twitch for {}s, exiting",
+                elapsed.as_secs()
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// answer a request of the discord side
+fn send_reply<T>(name: &str, reply: oneshot::Sender<Result<T, String>>, result: anyhow::Result<T>) {
+    if reply.send(result.map_err(|e| e.to_string())).is_err() {
+        warn!("{} requester is gone", name);
+    }
+}
+
+/// broadcaster targeted by the subscription
+fn subscription_user_id(sub: &EventSubSubscription) -> Option<UserId> {
+    sub.condition
+        .get("broadcaster_user_id")
+        .and_then(|v| v.as_str())
+        .map(|v| UserId::new(v.to_owned()))
+}
+
+/// subscriptions needed for user_ids, with the id of the existing ones, and the existing
+/// subscriptions that aren't needed
+/// category changes are only followed for linked_user_ids, whose channels are renamed
+fn plan_subscriptions(
+    user_ids: &[UserId],
+    linked_user_ids: &[UserId],
+    subs: Vec<EventSubSubscription>,
+) -> (Vec<Subscription>, Vec<EventSubSubscription>) {
+    let mut wanted: Vec<Subscription> = vec![];
+    for user_id in user_ids {
+        let mut event_types = vec![
+            SubscribedEvent::StreamOnline,
+            SubscribedEvent::StreamOffline,
+        ];
+        if linked_user_ids.contains(user_id) {
+            event_types.push(SubscribedEvent::ChannelUpdate);
+        }
+        for event_type in event_types {
+            wanted.push(Subscription {
+                event_id: None,
+                event_type,
+                user_id: user_id.clone(),
+            });
+        }
+    }
+
+    let mut obsolete = vec![];
+    for sub in subs {
+        let sub_user_id = subscription_user_id(&sub);
+        if let Some(item) = wanted.iter_mut().find(|f| {
+            f.event_id.is_none()
+                && f.event_type.event_type() == sub.type_
+                && sub_user_id.as_ref() == Some(&f.user_id)
+        }) {
+            item.event_id = Some(sub.id);
+        } else {
+            obsolete.push(sub);
+        }
+    }
+    (wanted, obsolete)
+}
+
+/// events the monitored users are subscribed to
+#[derive(Clone, Copy)]
+enum SubscribedEvent {
+    StreamOnline,
+    StreamOffline,
+    ChannelUpdate,
+}
+
+impl SubscribedEvent {
+    const ALL: [SubscribedEvent; 3] = [
+        SubscribedEvent::StreamOnline,
+        SubscribedEvent::StreamOffline,
+        SubscribedEvent::ChannelUpdate,
+    ];
+
+    fn event_type(self) -> EventType {
+        match self {
+            SubscribedEvent::StreamOnline => EventType::StreamOnline,
+            SubscribedEvent::StreamOffline => EventType::StreamOffline,
+            SubscribedEvent::ChannelUpdate => EventType::ChannelUpdate,
+        }
     }
 }
 
@@ -53,76 +255,352 @@ pub async fn run(sender: Sender<InterComm>, config: &Config) -> anyhow::Result<(
 pub struct Subscription {
     user_id: UserId,
     event_id: Option<EventSubId>,
-    event_type: EventType,
+    event_type: SubscribedEvent,
 }
 
 pub struct WebsocketClient {
     sender: Sender<InterComm>,
+    /// requests sent by the discord side
+    requests: Receiver<TwitchRequest>,
+    /// monitored users, configured ones and members of teams
     user_ids: Vec<UserId>,
+    /// users listed in the config
+    configured_user_ids: Vec<UserId>,
+    /// users whose chat is relayed to discord
+    chat_user_ids: Vec<UserId>,
+    /// chat messages not accepted by it aren't sent to discord
+    chat_filter: Option<ChatFilter>,
+    /// names of the teams whose members are monitored
+    teams: Vec<String>,
+    team_refresh: Interval,
 
     /// The session id of the websocket connection
     session_id: Option<String>,
     /// The token used to authenticate with the Twitch API
     token: TwitchToken,
+    /// base url of the twitch authentication endpoints
+    id_url: String,
     /// The client used to make requests to the Twitch API
-    client: HelixClient<'static, reqwest::Client>,
+    client: HelixClient<'static, RateLimitedClient>,
     /// The url to use for websocket
     connect_url: url::Url,
+    /// proxy the websocket is tunneled through
+    proxy: Option<url::Url>,
     /// contain the current of subscriptions in twitch api
     event_sub_id: Vec<Subscription>,
+    /// last streaming status sent to discord for each user
+    live_status: HashMap<UserId, bool>,
+    /// known logins of twitch users
+    logins: HashMap<UserId, UserName>,
+    /// how many times a failed user lookup is retried
+    login_lookup_retries: u32,
+    /// health of the connection, shared with the discord side
+    twitch_health: Arc<TwitchHealth>,
+    /// used to persist the token
+    store: Arc<dyn Store>,
+    /// true once the bot is shutting down
+    stopping: watch::Receiver<bool>,
+    /// collapses the logs repeated during reconnect storms
+    log_limiter: LogLimiter,
+    /// delay before reconnecting, reset by each welcome message
+    reconnect_backoff: Backoff,
 }
 
 impl WebsocketClient {
-    async fn connect(
-        &self,
-    ) -> anyhow::Result<
-        tokio_tungstenite::WebSocketStream<
-            tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
-        >,
-    > {
+    async fn connect(&mut self) -> anyhow::Result<WebSocket> {
         let socket_config = tungstenite::protocol::WebSocketConfig::default();
-        info!("connecting to websocket");
-        let (socket, _) = tokio_tungstenite::connect_async_with_config(
-            &self.connect_url,
-            Some(socket_config),
-            false,
-        )
-        .await
-        .context("Can't connect")?;
+        if let Some(suppressed) = self.log_limiter.check("connecting", "") {
+            info!("connecting to websocket{}", suppressed_note(suppressed));
+        }
+        let socket = connect_websocket(&self.connect_url, self.proxy.as_ref(), socket_config)
+            .await
+            .context("Can't connect")?;
+        // the session of the previous connection is gone, a welcome message will start a new one
+        self.session_id = None;
 
         Ok(socket)
     }
 
+    /// connect, retrying with the backoff while it fails, None if the bot is stopping
+    async fn connect_with_backoff(&mut self) -> Option<WebSocket> {
+        loop {
+            match self.connect().await {
+                Ok(socket) => return Some(socket),
+                Err(e) => {
+                    warn!("Can't connect to twitch websocket : {:#}", e);
+                    self.notify_error(
+                        "connection",
+                        format!("Can't connect to twitch websocket : {:#}", e),
+                    )
+                    .await;
+                }
+            }
+            if !self.wait_before_reconnect().await {
+                return None;
+            }
+        }
+    }
+
+    /// wait before reconnecting, longer after each reconnection that didn't get a welcome
+    /// returns false if the bot started stopping meanwhile
+    async fn wait_before_reconnect(&mut self) -> bool {
+        let delay = self.reconnect_backoff.next_delay();
+        info!(
+            "Reconnecting to twitch websocket in {:.1}s (attempt {})",
+            delay.as_secs_f64(),
+            self.reconnect_backoff.failures()
+        );
+        sleep_unless_stopping(&mut self.stopping, delay).await
+    }
+
     async fn run(&mut self) -> anyhow::Result<()> {
-        let mut s = self.connect().await?;
+        let Some(mut s) = self.connect_with_backoff().await else {
+            return Ok(());
+        };
 
         loop {
             tokio::select!(
-                Some(msg) = futures::StreamExt::next(&mut s) => {
+                msg = futures::StreamExt::next(&mut s) => {
                     let msg = match msg {
-                        Err(tungstenite::Error::Protocol(tungstenite::error::ProtocolError::ResetWithoutClosingHandshake)) => {
-                            warn!("connection was sent an unexpected frame or was reset, reestablishing it");
-                            s = self.connect().await.context("when reestablishing connection")?;
+                        // twitch closed the connection, like when no subscription is made in time
+                        None => {
+                            if let Some(suppressed) = self.log_limiter.check("closed", "") {
+                                warn!(
+                                    "Twitch closed the websocket connection, reestablishing it{}",
+                                    suppressed_note(suppressed)
+                                );
+                            }
+                            self.notify_error(
+                                "connection",
+                                String::from("Twitch closed the websocket connection, reestablishing it"),
+                            )
+                            .await;
+                            match self.reconnect().await {
+                                Some(socket) => s = socket,
+                                None => return Ok(()),
+                            }
+                            continue;
+                        }
+                        Some(Err(tungstenite::Error::Protocol(tungstenite::error::ProtocolError::ResetWithoutClosingHandshake))) => {
+                            if let Some(suppressed) = self.log_limiter.check("reset", "") {
+                                warn!(
+                                    "connection was sent an unexpected frame or was reset, reestablishing it{}",
+                                    suppressed_note(suppressed)
+                                );
+                            }
+                            self.notify_error(
+                                "connection",
+                                String::from("The websocket connection was reset, reestablishing it"),
+                            )
+                            .await;
+                            match self.reconnect().await {
+                                Some(socket) => s = socket,
+                                None => return Ok(()),
+                            }
                             continue;
                         }
-                        _=> msg.context("when getting message")?,
+                        Some(msg) => msg.context("when getting message")?,
                     };
                     self.process_message(msg).await?
                 }
-                else => {
-                    warn!("Twitch websocket loop exited, waiting before restart");
-                    sleep(Duration::from_secs(30)).await;
+                Some(request) = self.requests.recv() => {
+                    self.process_request(request).await
+                }
+                _ = self.team_refresh.tick(), if !self.teams.is_empty() => {
+                    self.refresh_team_subscriptions().await
+                }
+                _ = self.stopping.changed() => {
+                    info!("Stopping twitch websocket");
+                    if let Err(why) = s.close(None).await {
+                        debug!("Error on closing websocket {}", why);
+                    }
                     return Ok(());
                 }
             )
         }
     }
 
+    /// connect again once the connection is lost, None if the bot started stopping meanwhile
+    async fn reconnect(&mut self) -> Option<WebSocket> {
+        if !self.wait_before_reconnect().await {
+            return None;
+        }
+        self.connect_with_backoff().await
+    }
+
+    /// Process a request from the discord side
+    async fn process_request(&mut self, request: TwitchRequest) {
+        match request {
+            TwitchRequest::RefreshToken { reply } => {
+                send_reply("Token refresh", reply, self.refresh_token().await);
+            }
+            TwitchRequest::ResolveLogin { user_id, reply } => {
+                let result = self
+                    .resolve_login(UserId::new(user_id))
+                    .await
+                    .map(String::from);
+                send_reply("Login resolution", reply, result);
+            }
+            TwitchRequest::ResolveMonitored { login_or_id, reply } => {
+                let result = self.resolve_monitored(&login_or_id).await;
+                send_reply("Monitored user resolution", reply, result);
+            }
+            TwitchRequest::PruneSubscriptions { reply } => {
+                send_reply(
+                    "Subscriptions prune",
+                    reply,
+                    self.prune_subscriptions().await,
+                );
+            }
+            TwitchRequest::ListSubscriptions { reply } => {
+                send_reply("Subscriptions list", reply, self.list_subscriptions().await);
+            }
+            TwitchRequest::RateLimit { reply } => {
+                send_reply("Rate limit", reply, Ok(self.client.get_client().latest()));
+            }
+            TwitchRequest::StreamInfo { user_id, reply } => {
+                let result = self.stream_info(UserId::new(user_id)).await;
+                send_reply("Stream info", reply, result);
+            }
+            TwitchRequest::LookupStream { login, reply } => {
+                let result = self.lookup_stream(UserName::new(login)).await;
+                send_reply("Stream lookup", reply, result);
+            }
+            TwitchRequest::ReconcileDryRun { reply } => {
+                send_reply("Reconcile dry run", reply, self.reconcile_dry_run().await);
+            }
+            TwitchRequest::TokenStatus { reply } => {
+                send_reply("Token status", reply, self.token_status().await);
+            }
+            TwitchRequest::TestSubscription { login_or_id, reply } => {
+                let result = self.test_subscription(&login_or_id).await;
+                send_reply("Subscription test", reply, result);
+            }
+            TwitchRequest::TokenExpiry { reply } => {
+                send_reply("Token expiry", reply, self.token_expiry().await);
+            }
+        }
+    }
+
+    async fn list_subscriptions(&self) -> anyhow::Result<Vec<SubscriptionInfo>> {
+        let token = self.user_token();
+        let subs = self.get_subscriptions(&token).await?;
+        let mut infos: Vec<SubscriptionInfo> = subs
+            .iter()
+            .map(|sub| SubscriptionInfo {
+                event_type: sub.type_.to_string(),
+                broadcaster_user_id: subscription_user_id(sub).map(String::from),
+                status: format!("{:?}", sub.status),
+            })
+            .collect();
+        if self.chat_filter.is_some() {
+            let chat_subs = get_chat_subscriptions(&self.client, &token).await?;
+            infos.extend(chat_subs.into_iter().map(|sub| SubscriptionInfo {
+                event_type: String::from(CHAT_MESSAGE_TYPE),
+                broadcaster_user_id: Some(sub.broadcaster_user_id),
+                status: sub.status,
+            }));
+        }
+        Ok(infos)
+    }
+
+    async fn stream_info(&self, user_id: UserId) -> anyhow::Result<Option<StreamInfo>> {
+        let ids = [user_id.as_ref()];
+        self.get_stream(GetStreamsRequest::user_ids(&ids[..])).await
+    }
+
+    /// stream of any twitch user, monitored or not
+    async fn lookup_stream(&self, login: UserName) -> anyhow::Result<Option<StreamInfo>> {
+        let logins = [login.as_ref()];
+        self.get_stream(GetStreamsRequest::user_logins(&logins[..]))
+            .await
+    }
+
+    async fn get_stream(
+        &self,
+        request: GetStreamsRequest<'_>,
+    ) -> anyhow::Result<Option<StreamInfo>> {
+        let stream = self
+            .client
+            .req_get(request, &self.user_token())
+            .await?
+            .data
+            .into_iter()
+            .next();
+        Ok(stream.map(|stream| StreamInfo {
+            started_at: chrono::DateTime::parse_from_rfc3339(stream.started_at.as_str())
+                .ok()
+                .map(SystemTime::from),
+            user_login: stream.user_login.to_string(),
+            user_name: stream.user_name.to_string(),
+            title: stream.title,
+            game_name: stream.game_name,
+            viewer_count: stream.viewer_count,
+            thumbnail_url: stream.thumbnail_url,
+        }))
+    }
+
+    /// time before the token expires, without refreshing it
+    async fn token_expiry(&self) -> anyhow::Result<Duration> {
+        self.token_status()
+            .await?
+            .map(|status| status.expires_in)
+            .ok_or(anyhow!("Token isn't valid"))
+    }
+
+    /// validate the token without refreshing it, None if it isn't valid
+    async fn token_status(&self) -> anyhow::Result<Option<TokenStatus>> {
+        let validation = self
+            .token
+            .validate(self.client.get_client().inner(), &self.id_url)
+            .await?;
+        Ok(validation.map(|v| TokenStatus {
+            login: v.login,
+            user_id: v.user_id,
+            scopes: v.scopes,
+            expires_in: Duration::from_secs(v.expires_in),
+        }))
+    }
+
+    /// refresh the token if it isn't valid anymore or expires within TOKEN_REFRESH_MARGIN
+    async fn refresh_token_if_expiring(&mut self) -> anyhow::Result<()> {
+        let expires_in = self.token_status().await?.map(|status| status.expires_in);
+        if needs_refresh(expires_in) {
+            self.refresh_token().await?;
+        }
+        Ok(())
+    }
+
+    /// refresh the token even if it is still valid
+    async fn refresh_token(&mut self) -> anyhow::Result<Duration> {
+        info!("Refreshing token");
+        self.token
+            .refresh(self.client.get_client().inner(), &self.id_url)
+            .await?;
+        self.token.persist(self.store.as_ref());
+        let validation = self
+            .token
+            .validate(self.client.get_client().inner(), &self.id_url)
+            .await?
+            .ok_or(anyhow!("Refreshed token isn't valid"))?;
+        info!(
+            "Token refreshed for {} ({}) with scopes {:?}",
+            validation.login, validation.user_id, validation.scopes
+        );
+        Ok(Duration::from_secs(validation.expires_in))
+    }
+
     /// Process a message from the websocket
     pub async fn process_message(&mut self, msg: tungstenite::Message) -> anyhow::Result<()> {
         trace!("processing");
+        // twitch sends keepalives when there is nothing else, so any message means the
+        // connection is alive
+        self.twitch_health.record_message();
         match msg {
             tungstenite::Message::Text(s) => {
+                if let Some(frame) = parse_chat_frame(&s) {
+                    return self.process_chat_frame(frame?).await;
+                }
                 // Parse the message into a [twitch_api::eventsub::EventsubWebsocketData]
                 match Event::parse_websocket(&s)? {
                     EventsubWebsocketData::Welcome {
@@ -134,7 +612,21 @@ impl WebsocketClient {
                         ..
                     } => match self.process_welcome_message(session).await {
                         Err(e) => {
-                            error!("Error on processing welcome message : {}", e);
+                            if let Some(suppressed) =
+                                self.log_limiter.check("welcome", &format!("{:#}", e))
+                            {
+                                error!(
+                                    "Error on processing welcome message : {}{}",
+                                    e,
+                                    suppressed_note(suppressed)
+                                );
+                            }
+                            // operators are notified of every error, only the log is limited
+                            self.notify_error(
+                                "welcome",
+                                format!("Error on processing welcome message : {}", e),
+                            )
+                            .await;
                             Ok(())
                         }
                         _ => Ok(()),
@@ -144,6 +636,11 @@ impl WebsocketClient {
                         metadata: _,
                         payload,
                     } => {
+                        // subscriptions aren't reconciled yet, acting on it could desync the state
+                        if self.session_id.is_none() {
+                            warn!("Ignoring a notification received before the welcome message");
+                            return Ok(());
+                        }
                         match payload {
                             Event::StreamOnlineV1(eventsub::Payload {
                                 message: Message::Notification(notif),
@@ -152,6 +649,7 @@ impl WebsocketClient {
                                 self.handle_streamer_online(
                                     notif.broadcaster_user_id,
                                     notif.broadcaster_user_login,
+                                    false,
                                 )
                                 .await?;
                             }
@@ -165,6 +663,17 @@ impl WebsocketClient {
                                 )
                                 .await?;
                             }
+                            Event::ChannelUpdateV2(eventsub::Payload {
+                                message: Message::Notification(notif),
+                                ..
+                            }) => {
+                                self.handle_category_update(
+                                    notif.broadcaster_user_id,
+                                    notif.broadcaster_user_login,
+                                    notif.category_name,
+                                )
+                                .await?;
+                            }
                             _ => {}
                         }
                         Ok(())
@@ -194,15 +703,84 @@ impl WebsocketClient {
         }
     }
 
+    async fn process_chat_frame(&mut self, frame: ChatFrame) -> anyhow::Result<()> {
+        match frame {
+            ChatFrame::Message {
+                broadcaster_user_id,
+                broadcaster_user_login,
+                chatter_user_login,
+                message,
+            } => {
+                let Some(chat_filter) = &self.chat_filter else {
+                    return Ok(());
+                };
+                if !chat_filter.accepts(&chatter_user_login, &message) {
+                    trace!("Not relaying chat message of {}", chatter_user_login);
+                    return Ok(());
+                }
+                self.sender
+                    .send(InterComm::Streamer(StreamerEvent {
+                        message_type: MessageType::ChatMessage(message),
+                        streamer_user_id: broadcaster_user_id,
+                        streamer_user_login: broadcaster_user_login,
+                    }))
+                    .await?;
+            }
+            ChatFrame::Revocation {
+                broadcaster_user_id,
+                status,
+            } => {
+                warn!(
+                    "Chat subscription of {} has been revoked : {}",
+                    broadcaster_user_id, status
+                );
+                self.notify_error(
+                    "chat",
+                    format!(
+                        "Chat subscription of {} has been revoked ({}), its chat isn't relayed anymore",
+                        broadcaster_user_id, status
+                    ),
+                )
+                .await;
+            }
+            ChatFrame::Other => {}
+        }
+        Ok(())
+    }
+
     pub async fn process_welcome_message(&mut self, data: SessionData<'_>) -> anyhow::Result<()> {
         self.session_id = Some(data.id.to_string());
+        self.reconnect_backoff.reset();
         if let Some(url) = data.reconnect_url {
             self.connect_url = url.parse()?;
         }
 
-        let transport = eventsub::Transport::websocket(data.id.clone());
+        // refreshed before checking the breaker, whose half-open check must be followed by
+        // the result of the reconciliation
+        self.refresh_token_if_expiring()
+            .await
+            .context("Can't refresh the token")?;
+        // the subscriptions are reconciled by the next welcome message once the api is back
+        self.twitch_health
+            .api_breaker
+            .check()
+            .context("Twitch API is unavailable, subscriptions aren't reconciled")?;
+        match self.sync_subscriptions(data.id.to_string()).await {
+            Ok(()) => {
+                self.twitch_health.api_breaker.record_success();
+                Ok(())
+            }
+            Err(e) => {
+                self.twitch_health.api_breaker.record_failure();
+                Err(e)
+            }
+        }
+    }
+
+    /// token used to call the Twitch API
+    fn user_token(&self) -> UserToken {
         let cred = get_client_ids();
-        let token = UserToken::from_existing_unchecked(
+        UserToken::from_existing_unchecked(
             self.token.access_token.to_owned(),
             Some(self.token.refresh_token.to_owned().into()),
             cred.0,
@@ -211,72 +789,285 @@ impl WebsocketClient {
             "53102824".into(),
             None,
             None,
-        );
+        )
+    }
 
-        // ---------------------------------------------------------------------------
-        // We find what event we already have a sub for
-        // ---------------------------------------------------------------------------
-        // https://github.com/twitch-rs/twitch_api/issues/400
-        let subs: Vec<EventSubSubscription> = self
+    /// get the login of a twitch user, looking it up on the Twitch API if it isn't known yet
+    async fn resolve_login(&mut self, user_id: UserId) -> anyhow::Result<UserName> {
+        if let Some(login) = self.logins.get(&user_id) {
+            return Ok(login.clone());
+        }
+        debug!("Looking up login of twitch user {}", user_id);
+        let token = self.user_token();
+        let mut stopping = self.stopping.clone();
+        let user = retry_lookup(self.login_lookup_retries, &mut stopping, || {
+            self.client.get_user_from_id(&user_id, &token)
+        })
+        .await?
+        .ok_or(anyhow!("Twitch user {} doesn't exist", user_id))?;
+        self.logins.insert(user_id, user.login.clone());
+        Ok(user.login)
+    }
+
+    /// id and login of a monitored user, login_or_id being all digits for an id
+    async fn resolve_monitored(&mut self, login_or_id: &str) -> anyhow::Result<(String, String)> {
+        let (user_id, login) = if login_or_id.chars().all(|c| c.is_ascii_digit()) {
+            let user_id = UserId::new(login_or_id.to_owned());
+            let login = self.resolve_login(user_id.clone()).await?;
+            (user_id, login)
+        } else {
+            let login = login_or_id.to_lowercase();
+            match self.logins.iter().find(|(_, l)| l.as_str() == login) {
+                Some((user_id, login)) => (user_id.clone(), login.clone()),
+                None => {
+                    debug!("Looking up twitch user {}", login);
+                    let token = self.user_token();
+                    let mut stopping = self.stopping.clone();
+                    let user = retry_lookup(self.login_lookup_retries, &mut stopping, || {
+                        self.client.get_user_from_login(login.as_str(), &token)
+                    })
+                    .await?
+                    .ok_or(anyhow!("Twitch user {} doesn't exist", login))?;
+                    self.logins.insert(user.id.clone(), user.login.clone());
+                    (user.id, user.login)
+                }
+            }
+        };
+        if !self.user_ids.contains(&user_id) {
+            return Err(anyhow!("Twitch user {} isn't monitored", login));
+        }
+        Ok((user_id.to_string(), login.to_string()))
+    }
+
+    /// every subscription existing on twitch api side
+    async fn get_subscriptions(
+        &self,
+        token: &UserToken,
+    ) -> anyhow::Result<Vec<EventSubSubscription>> {
+        let mut subs: Vec<EventSubSubscription> = vec![];
+        // listed by type since twitch_api can't parse the chat subscriptions
+        for event in SubscribedEvent::ALL {
+            let event_type = event.event_type().to_string();
+            subs.extend(get_subscriptions_of_type(&self.client, token, &event_type).await?);
+        }
+        Ok(subs)
+    }
+
+    /// create a channel.raid subscription to a monitored user on the current session, which
+    /// needs no scope and isn't used otherwise, wait for twitch to list it then delete it
+    async fn test_subscription(&mut self, login_or_id: &str) -> anyhow::Result<SubscriptionTest> {
+        let session_id = self
+            .session_id
+            .clone()
+            .ok_or(anyhow!("Not connected to twitch eventsub"))?;
+        let (user_id, login) = self.resolve_monitored(login_or_id).await?;
+        let token = self.user_token();
+        let started = Instant::now();
+        let created = self
             .client
-            .get_eventsub_subscriptions(None, None, None, &token)
-            .map_ok(|r| {
-                trace!("{:?}", r);
-                stream::iter(
-                    r.subscriptions
-                        .into_iter()
-                        .map(Ok::<_, twitch_api::helix::ClientRequestError<_>>),
-                )
-            })
-            .try_flatten()
-            .try_collect()
+            .create_eventsub_subscription(
+                ChannelRaidV1::to_broadcaster_user_id(user_id),
+                eventsub::Transport::websocket(session_id),
+                &token,
+            )
             .await?;
-        // let subs: Vec<EventSubSubscription> = vec![];
+        debug!("Created test subscription {}", created.id);
 
-        debug!("There are {} subs on twitch api side", subs.len());
+        let confirmed_status = match timeout(
+            SUBSCRIPTION_TEST_TIMEOUT,
+            self.wait_subscription_listed(&created.id, &token),
+        )
+        .await
+        {
+            Ok(Ok(status)) => Some(status),
+            Ok(Err(e)) => {
+                warn!("Can't list test subscription {} : {}", created.id, e);
+                None
+            }
+            Err(_) => None,
+        };
+        let elapsed = started.elapsed();
 
-        // ---------------------------------------------------------------------------
-        // clearing the list for now
-        // ---------------------------------------------------------------------------
-        self.event_sub_id.clear();
+        // deleted even if it wasn't confirmed, twitch may list it later
+        let cleanup_error = self
+            .client
+            .delete_eventsub_subscription(created.id.clone(), &token)
+            .await
+            .err()
+            .map(|e| e.to_string());
+        if let Some(e) = &cleanup_error {
+            warn!("Can't delete test subscription {} : {}", created.id, e);
+        }
+        Ok(SubscriptionTest {
+            broadcaster_user_login: login,
+            event_type: created.type_.to_string(),
+            created_status: format!("{:?}", created.status),
+            confirmed_status,
+            elapsed,
+            cleanup_error,
+        })
+    }
 
-        // ---------------------------------------------------------------------------
-        // add all the event we need to have
-        // ---------------------------------------------------------------------------
-        for user_id in &self.user_ids {
-            debug!("Subscribing to events for {}", user_id);
-            let mut sub = Subscription {
-                event_id: None,
-                event_type: EventType::StreamOnline,
-                user_id: user_id.clone(),
+    /// status of the channel.raid subscription id once twitch lists it
+    async fn wait_subscription_listed(
+        &self,
+        id: &EventSubId,
+        token: &UserToken,
+    ) -> anyhow::Result<String> {
+        loop {
+            let subs: Vec<EventSubSubscription> =
+                get_subscriptions_of_type(&self.client, token, &EventType::ChannelRaid.to_string())
+                    .await?;
+            if let Some(sub) = subs.iter().find(|sub| &sub.id == id) {
+                return Ok(format!("{:?}", sub.status));
+            }
+            sleep(SUBSCRIPTION_TEST_POLL).await;
+        }
+    }
+
+    /// what sync_subscriptions would create and delete, without changing anything
+    async fn reconcile_dry_run(&self) -> anyhow::Result<ReconcilePlan> {
+        let subs = self.get_subscriptions(&self.user_token()).await?;
+        let statuses: HashMap<EventSubId, String> = subs
+            .iter()
+            .map(|sub| (sub.id.clone(), format!("{:?}", sub.status)))
+            .collect();
+        let (wanted, obsolete) =
+            plan_subscriptions(&self.user_ids, &self.configured_user_ids, subs);
+        let mut plan = ReconcilePlan {
+            keep: vec![],
+            create: vec![],
+            delete: vec![],
+        };
+        for sub in wanted {
+            let info = SubscriptionInfo {
+                event_type: sub.event_type.event_type().to_string(),
+                broadcaster_user_id: Some(sub.user_id.to_string()),
+                status: sub
+                    .event_id
+                    .as_ref()
+                    .and_then(|id| statuses.get(id).cloned())
+                    .unwrap_or_default(),
             };
-            self.event_sub_id.push(sub.clone());
+            match sub.event_id {
+                Some(_) => plan.keep.push(info),
+                None => plan.create.push(info),
+            }
+        }
+        plan.delete = obsolete
+            .iter()
+            .map(|sub| SubscriptionInfo {
+                event_type: sub.type_.to_string(),
+                broadcaster_user_id: subscription_user_id(sub).map(String::from),
+                status: format!("{:?}", sub.status),
+            })
+            .collect();
+        Ok(plan)
+    }
+
+    /// delete the subscriptions whose broadcaster isn't in user_ids
+    async fn prune_subscriptions(&mut self) -> anyhow::Result<usize> {
+        let token = self.user_token();
+        let mut pruned = 0;
+        for sub in self.get_subscriptions(&token).await? {
+            if subscription_user_id(&sub).is_some_and(|u| self.user_ids.contains(&u)) {
+                continue;
+            }
+            info!("Pruning subscription {} ({:?})", sub.id, sub.condition);
+            self.event_sub_id
+                .retain(|f| f.event_id.as_ref() != Some(&sub.id));
+            self.client
+                .delete_eventsub_subscription(sub.id, &token)
+                .await?;
+            pruned += 1;
+        }
+        Ok(pruned)
+    }
+
+    /// set user_ids to the configured users and the members of teams
+    /// returns true if user_ids changed
+    async fn refresh_teams(&mut self, token: &UserToken) -> anyhow::Result<bool> {
+        let mut user_ids = self.configured_user_ids.clone();
+        for team_name in &self.teams {
+            let request = GetTeamsRequest::name(team_name.as_str());
+            let teams = self.client.req_get(request, token).await?.data;
+            let team = teams
+                .into_iter()
+                .next()
+                .ok_or(anyhow!("Twitch team {} doesn't exist", team_name))?;
+            debug!("Team {} has {} members", team_name, team.users.len());
+            for user in team.users {
+                self.logins.insert(user.id.clone(), user.login);
+                if !user_ids.contains(&user.id) {
+                    user_ids.push(user.id);
+                }
+            }
+        }
+        let changed = user_ids != self.user_ids;
+        self.user_ids = user_ids;
+        Ok(changed)
+    }
+
+    /// pick up members added to or removed from teams since the last refresh
+    async fn refresh_team_subscriptions(&mut self) {
+        let Some(session_id) = self.session_id.clone() else {
+            return;
+        };
+        if !self.twitch_health.api_breaker.allow() {
+            debug!("Twitch API is unavailable, skipping team refresh");
+            return;
+        }
+        let result = match self.refresh_teams(&self.user_token()).await {
+            Ok(true) => {
+                info!("Team members changed, updating subscriptions");
+                self.sync_subscriptions(session_id).await
+            }
+            Ok(false) => Ok(()),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => self.twitch_health.api_breaker.record_success(),
+            Err(e) => {
+                self.twitch_health.api_breaker.record_failure();
+                warn!("Error on refreshing teams : {}", e);
+                self.notify_error("teams", format!("Error on refreshing teams : {}", e))
+                    .await;
+            }
+        }
+    }
 
-            sub.event_type = EventType::StreamOffline;
-            self.event_sub_id.push(sub);
+    /// create and delete subscriptions so that twitch api matches user_ids
+    async fn sync_subscriptions(&mut self, session_id: String) -> anyhow::Result<()> {
+        let transport = eventsub::Transport::websocket(session_id.clone());
+        let token = self.user_token();
+
+        if !self.teams.is_empty() {
+            // keep the previous members if teams can't be looked up
+            if let Err(e) = self.refresh_teams(&token).await {
+                warn!("Can't look up team members : {}", e);
+                self.notify_error("teams", format!("Can't look up team members : {}", e))
+                    .await;
+            }
         }
 
         // ---------------------------------------------------------------------------
-        // find event that are already subscribed
+        // We find what event we already have a sub for
+        // ---------------------------------------------------------------------------
+        let subs = self.get_subscriptions(&token).await?;
+
+        debug!("There are {} subs on twitch api side", subs.len());
+
+        // ---------------------------------------------------------------------------
+        // find event that are already subscribed, and the ones not needed anymore
         // ---------------------------------------------------------------------------
-        for sub in subs {
-            if let Some(item) = self.event_sub_id.iter_mut().find(|f| {
-                f.event_type == sub.type_
-                    && f.user_id
-                        == UserId::new(
-                            sub.condition
-                                .get("broadcaster_user_id")
-                                .unwrap()
-                                .to_string(),
-                        )
-            }) {
-                item.event_id = Some(sub.id);
-            } else {
-                debug!("deleting old sub {}", sub.id);
-                self.client
-                    .delete_eventsub_subscription(sub.id, &token)
-                    .await?;
-            }
+        let (wanted, obsolete) =
+            plan_subscriptions(&self.user_ids, &self.configured_user_ids, subs);
+        self.event_sub_id = wanted;
+        for sub in obsolete {
+            debug!("deleting old sub {}", sub.id);
+            self.client
+                .delete_eventsub_subscription(sub.id, &token)
+                .await?;
         }
 
         // ---------------------------------------------------------------------------
@@ -288,7 +1079,7 @@ impl WebsocketClient {
             .filter(|f| f.event_id.is_none())
         {
             let event = match to_sub.event_type {
-                EventType::StreamOnline => {
+                SubscribedEvent::StreamOnline => {
                     self.client
                         .create_eventsub_subscription(
                             StreamOnlineV1::broadcaster_user_id(to_sub.user_id.clone()),
@@ -298,7 +1089,7 @@ impl WebsocketClient {
                         .await?
                         .id
                 }
-                EventType::StreamOffline => {
+                SubscribedEvent::StreamOffline => {
                     self.client
                         .create_eventsub_subscription(
                             StreamOfflineV1::broadcaster_user_id(to_sub.user_id.clone()),
@@ -308,49 +1099,711 @@ impl WebsocketClient {
                         .await?
                         .id
                 }
-                _ => {
-                    panic!("Unknown event type")
+                SubscribedEvent::ChannelUpdate => {
+                    self.client
+                        .create_eventsub_subscription(
+                            ChannelUpdateV2::broadcaster_user_id(to_sub.user_id.clone()),
+                            transport.clone(),
+                            &token,
+                        )
+                        .await?
+                        .id
                 }
             };
             to_sub.event_id = Some(event);
         }
 
+        // the stream events work without the chat, so its errors aren't fatal
+        if let Err(e) = self.sync_chat_subscriptions(&session_id, &token).await {
+            warn!("Can't subscribe to chats : {}", e);
+            self.notify_error("chat", format!("Can't subscribe to chats : {}", e))
+                .await;
+        }
+
+        // ---------------------------------------------------------------------------
+        // catch up on stream changes we may have missed while disconnected
+        // ---------------------------------------------------------------------------
+        self.reconcile_live_status(&token).await?;
+
         info!("welcome message sent");
         Ok(())
     }
 
-    pub async fn handle_streamer_online(
+    /// create and delete chat subscriptions so that twitch api matches chat_user_ids
+    async fn sync_chat_subscriptions(
         &self,
+        session_id: &str,
+        token: &UserToken,
+    ) -> anyhow::Result<()> {
+        if self.chat_user_ids.is_empty() {
+            return Ok(());
+        }
+        let validation = self
+            .token
+            .validate(self.client.get_client().inner(), &self.id_url)
+            .await?
+            .ok_or(anyhow!("the token isn't valid"))?;
+        if !validation.scopes.iter().any(|s| s == CHAT_SCOPE) {
+            warn!(
+                "The token doesn't have the {} scope, chats won't be relayed",
+                CHAT_SCOPE
+            );
+            self.notify_error(
+                "chat",
+                format!(
+                    "The token doesn't have the {} scope, chats won't be relayed",
+                    CHAT_SCOPE
+                ),
+            )
+            .await;
+            return Ok(());
+        }
+
+        let mut missing: Vec<&UserId> = self.chat_user_ids.iter().collect();
+        for sub in get_chat_subscriptions(&self.client, token).await? {
+            // subscriptions of a previous session are disabled and must be recreated
+            let position = missing
+                .iter()
+                .position(|u| u.as_str() == sub.broadcaster_user_id);
+            if let (Some(position), "enabled") = (position, sub.status.as_str()) {
+                missing.remove(position);
+            } else {
+                debug!("deleting old chat sub {}", sub.id);
+                self.client
+                    .delete_eventsub_subscription(EventSubId::new(sub.id), token)
+                    .await?;
+            }
+        }
+        for user_id in missing {
+            debug!("Subscribing to the chat of {}", user_id);
+            create_chat_subscription(
+                &self.client,
+                token,
+                user_id.as_str(),
+                &validation.user_id,
+                session_id,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// query the current streams of every monitored user and notify discord of the ones
+    /// whose status differs from the last one we sent
+    async fn reconcile_live_status(&mut self, token: &UserToken) -> anyhow::Result<()> {
+        let mut live: HashMap<UserId, UserName> = HashMap::new();
+        for chunk in self.user_ids.chunks(100) {
+            let ids: Vec<&UserIdRef> = chunk.iter().map(|i| i.as_ref()).collect();
+            let request = GetStreamsRequest::user_ids(&ids[..]);
+            for stream in self.client.req_get(request, token).await?.data {
+                live.insert(stream.user_id, stream.user_login);
+            }
+        }
+        debug!("{} monitored streams are online", live.len());
+
+        for user_id in self.user_ids.clone() {
+            let is_live = live.contains_key(&user_id);
+            if self.live_status.get(&user_id) == Some(&is_live) {
+                trace!("Stream status of {} hasn't changed", user_id);
+                continue;
+            }
+            match live.remove(&user_id) {
+                Some(login) => {
+                    // the stream was live before we knew its status, like on startup
+                    let is_backlog = !self.live_status.contains_key(&user_id);
+                    self.handle_streamer_online(user_id, login, is_backlog)
+                        .await?
+                }
+                None => {
+                    // the status stays unknown on failure so it's retried by the next reconcile
+                    let login = match self.resolve_login(user_id.clone()).await {
+                        Ok(login) => login,
+                        Err(e) => {
+                            warn!("Can't look up login of {} : {:#}", user_id, e);
+                            self.notify_error(
+                                "lookup",
+                                format!("Can't look up login of {} : {:#}", user_id, e),
+                            )
+                            .await;
+                            continue;
+                        }
+                    };
+                    self.handle_streamer_offline(user_id, login).await?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// forward an error to the discord side so that operators are notified, errors of the
+    /// same kind are notified at most once in a while
+    async fn notify_error(&self, kind: &'static str, message: String) {
+        if let Err(e) = self.sender.send(InterComm::Error { kind, message }).await {
+            warn!("Can't notify error, the discord side is gone : {}", e);
+        }
+    }
+
+    pub async fn handle_streamer_online(
+        &mut self,
         broadcaster_user_id: UserId,
         broadcaster_user_login: UserName,
+        is_backlog: bool,
     ) -> anyhow::Result<()> {
         info!("{} stream is online", broadcaster_user_login);
+        self.live_status.insert(broadcaster_user_id.clone(), true);
+        self.logins
+            .insert(broadcaster_user_id.clone(), broadcaster_user_login.clone());
+
+        // channel.update is only sent on changes, the category the stream started with is
+        // looked up, the stream is still handled if it can't be
+        let token = self.user_token();
+        let category = match self
+            .client
+            .get_channel_from_id(&broadcaster_user_id, &token)
+            .await
+        {
+            Ok(channel) => channel
+                .map(|c| c.game_name.to_string())
+                .filter(|c| !c.is_empty()),
+            Err(e) => {
+                warn!(
+                    "Can't look up the category of {} : {}",
+                    broadcaster_user_login, e
+                );
+                self.notify_error(
+                    "lookup",
+                    format!(
+                        "Can't look up the category of {} : {}",
+                        broadcaster_user_login, e
+                    ),
+                )
+                .await;
+                None
+            }
+        };
 
         self.sender
-            .send(InterComm {
-                message_type: MessageType::TwitchStreamOnline,
+            .send(InterComm::Streamer(StreamerEvent {
+                message_type: MessageType::TwitchStreamOnline {
+                    is_backlog,
+                    category,
+                },
+                streamer_user_id: broadcaster_user_id.to_string(),
+                streamer_user_login: broadcaster_user_login.to_string(),
+            }))
+            .await?;
+
+        Ok(())
+    }
+
+    /// the channel title or category changed, only the category is sent to discord
+    pub async fn handle_category_update(
+        &mut self,
+        broadcaster_user_id: UserId,
+        broadcaster_user_login: UserName,
+        category: String,
+    ) -> anyhow::Result<()> {
+        debug!("{} category is now {}", broadcaster_user_login, category);
+        self.sender
+            .send(InterComm::Streamer(StreamerEvent {
+                message_type: MessageType::TwitchCategoryUpdate { category },
                 streamer_user_id: broadcaster_user_id.into(),
                 streamer_user_login: broadcaster_user_login.into(),
-            })
+            }))
             .await?;
-
         Ok(())
     }
     pub async fn handle_streamer_offline(
-        &self,
+        &mut self,
         broadcaster_user_id: UserId,
         broadcaster_user_login: UserName,
     ) -> anyhow::Result<()> {
         info!("{} stream is offline", broadcaster_user_login);
+        self.live_status.insert(broadcaster_user_id.clone(), false);
+        self.logins
+            .insert(broadcaster_user_id.clone(), broadcaster_user_login.clone());
 
         self.sender
-            .send(InterComm {
+            .send(InterComm::Streamer(StreamerEvent {
                 message_type: MessageType::TwitchStreamOffline,
                 streamer_user_id: broadcaster_user_id.into(),
                 streamer_user_login: broadcaster_user_login.into(),
-            })
+            }))
             .await?;
 
         Ok(())
     }
 }
+
+/// true if a token expiring in expires_in must be refreshed, None if it isn't valid anymore
+fn needs_refresh(expires_in: Option<Duration>) -> bool {
+    expires_in.is_none_or(|expires_in| expires_in <= TOKEN_REFRESH_MARGIN)
+}
+
+/// run a twitch user lookup, retrying it up to retries times with a growing delay if the twitch
+/// api fails, an unknown user isn't a failure, the retries stop when the bot is stopping
+async fn retry_lookup<T, E, F, Fut>(
+    retries: u32,
+    stopping: &mut watch::Receiver<bool>,
+    mut lookup: F,
+) -> anyhow::Result<T>
+where
+    E: std::fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, E>>,
+{
+    let mut delay = LOGIN_LOOKUP_RETRY_DELAY;
+    let mut attempt = 0;
+    loop {
+        match lookup().await {
+            Ok(result) => return Ok(result),
+            Err(why) if attempt < retries => {
+                attempt += 1;
+                debug!(
+                    "Twitch user lookup failed, retry {}/{} in {:?} : {}",
+                    attempt, retries, delay, why
+                );
+                if !sleep_unless_stopping(stopping, delay).await {
+                    return Err(anyhow!(
+                        "the bot is stopping, the Twitch user lookup is abandoned"
+                    ));
+                }
+                delay *= 2;
+            }
+            Err(why) => {
+                return Err(anyhow!(
+                    "couldn't verify that Twitch channel, the Twitch API failed {} times : {}",
+                    attempt + 1,
+                    why
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::set_var;
+
+    use serde_json::json;
+    use tokio::sync::mpsc;
+    use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::store::memory::MemoryStore;
+    use crate::twitch::circuit_breaker::CircuitBreaker;
+
+    const STREAMER_ID: &str = "1";
+
+    /// client monitoring STREAMER_ID whose helix requests are sent to server, with the
+    /// receiver of what it sends to discord and the sender stopping it
+    fn client(server: &MockServer) -> (WebsocketClient, Receiver<InterComm>, watch::Sender<bool>) {
+        // same values as the tests of auth, so that they can run in parallel
+        set_var("TWITCH_CLIENT_ID", "client-id");
+        set_var("TWITCH_CLIENT_SECRET", "client-secret");
+        let (sender, receiver) = mpsc::channel(10);
+        let (_, requests) = mpsc::channel(1);
+        let (stop, stopping) = watch::channel(false);
+        let user_ids = vec![UserId::new(String::from(STREAMER_ID))];
+        let ws = WebsocketClient {
+            sender,
+            requests,
+            user_ids: user_ids.clone(),
+            configured_user_ids: user_ids,
+            chat_user_ids: vec![],
+            chat_filter: None,
+            teams: vec![],
+            team_refresh: interval_at(
+                Instant::now() + TEAM_REFRESH_INTERVAL,
+                TEAM_REFRESH_INTERVAL,
+            ),
+            session_id: None,
+            token: TwitchToken {
+                access_token: String::from("access"),
+                refresh_token: String::from("refresh"),
+            },
+            id_url: server.uri(),
+            client: HelixClient::with_client(RateLimitedClient::new(
+                reqwest::Client::new(),
+                Some(server.uri()),
+            )),
+            connect_url: twitch_api::TWITCH_EVENTSUB_WEBSOCKET_URL.clone(),
+            proxy: None,
+            event_sub_id: vec![],
+            live_status: HashMap::new(),
+            logins: HashMap::new(),
+            login_lookup_retries: 1,
+            twitch_health: Arc::new(TwitchHealth::new(CircuitBreaker::new(
+                3,
+                Duration::from_secs(60),
+            ))),
+            store: Arc::new(MemoryStore::default()),
+            stopping,
+            log_limiter: LogLimiter::new(Duration::ZERO),
+            reconnect_backoff: Backoff::new(RECONNECT_INITIAL_DELAY, RECONNECT_MAX_DELAY),
+        };
+        (ws, receiver, stop)
+    }
+
+    /// answer the GET requests to endpoint of helix with body
+    async fn mock_get(server: &MockServer, endpoint: &str, body: serde_json::Value) {
+        Mock::given(method("GET"))
+            .and(path(endpoint))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .mount(server)
+            .await;
+    }
+
+    /// STREAMER_ID is live, playing Celeste
+    async fn mock_live_stream(server: &MockServer) {
+        mock_get(
+            server,
+            "/streams",
+            json!({
+                "data": [{
+                    "id": "42",
+                    "user_id": STREAMER_ID,
+                    "user_login": "streamer",
+                    "user_name": "Streamer",
+                    "game_id": "504461",
+                    "game_name": "Celeste",
+                    "type": "live",
+                    "title": "title",
+                    "tags": [],
+                    "viewer_count": 10,
+                    "started_at": "2026-10-17T10:00:00Z",
+                    "language": "fr",
+                    "thumbnail_url": "",
+                    "is_mature": false,
+                }],
+                "pagination": {},
+            }),
+        )
+        .await;
+        mock_get(
+            server,
+            "/channels",
+            json!({
+                "data": [{
+                    "broadcaster_id": STREAMER_ID,
+                    "broadcaster_login": "streamer",
+                    "broadcaster_name": "Streamer",
+                    "game_id": "504461",
+                    "game_name": "Celeste",
+                    "broadcaster_language": "fr",
+                    "title": "title",
+                    "delay": 0,
+                    "tags": [],
+                    "content_classification_labels": [],
+                    "is_branded_content": false,
+                }],
+            }),
+        )
+        .await;
+    }
+
+    /// is_backlog of the online event received from the client
+    fn online_is_backlog(message: InterComm) -> bool {
+        match message {
+            InterComm::Streamer(StreamerEvent {
+                message_type: MessageType::TwitchStreamOnline { is_backlog, .. },
+                ..
+            }) => is_backlog,
+            _ => panic!("not an online event"),
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_live_on_startup_isnt_announced() {
+        let server = MockServer::start().await;
+        mock_live_stream(&server).await;
+        let (mut ws, mut receiver, _stop) = client(&server);
+
+        ws.reconcile_live_status(&ws.user_token()).await.unwrap();
+
+        assert!(online_is_backlog(receiver.recv().await.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn stream_started_while_watched_is_announced() {
+        let server = MockServer::start().await;
+        mock_live_stream(&server).await;
+        let (mut ws, mut receiver, _stop) = client(&server);
+        ws.live_status
+            .insert(UserId::new(String::from(STREAMER_ID)), false);
+
+        ws.reconcile_live_status(&ws.user_token()).await.unwrap();
+
+        assert!(!online_is_backlog(receiver.recv().await.unwrap()));
+    }
+
+    /// answer the user lookups with status and body, at most times times if it's given
+    async fn mock_users(
+        server: &MockServer,
+        status: u16,
+        body: serde_json::Value,
+        times: Option<u64>,
+    ) {
+        let mock = Mock::given(method("GET"))
+            .and(path("/users"))
+            .respond_with(ResponseTemplate::new(status).set_body_json(body));
+        match times {
+            Some(times) => mock.up_to_n_times(times).expect(times),
+            None => mock,
+        }
+        .mount(server)
+        .await;
+    }
+
+    fn rate_limited() -> serde_json::Value {
+        json!({"error": "Too Many Requests", "status": 429, "message": "rate limited"})
+    }
+
+    #[tokio::test]
+    async fn unknown_user_isnt_retried() {
+        let server = MockServer::start().await;
+        mock_users(&server, 200, json!({"data": []}), Some(1)).await;
+        let (mut ws, _receiver, _stop) = client(&server);
+
+        let error = ws.resolve_monitored("nobody").await.unwrap_err();
+
+        assert_eq!(error.to_string(), "Twitch user nobody doesn't exist");
+    }
+
+    #[tokio::test]
+    async fn rate_limited_lookup_is_retried() {
+        let server = MockServer::start().await;
+        mock_users(&server, 429, rate_limited(), Some(1)).await;
+        mock_users(
+            &server,
+            200,
+            json!({"data": [{
+                "id": STREAMER_ID,
+                "login": "streamer",
+                "display_name": "Streamer",
+                "type": "",
+                "broadcaster_type": "",
+                "description": "",
+                "profile_image_url": "",
+                "offline_image_url": "",
+                "created_at": "2016-12-14T20:32:28Z",
+            }]}),
+            None,
+        )
+        .await;
+        let (mut ws, _receiver, _stop) = client(&server);
+
+        let login = ws
+            .resolve_login(UserId::new(String::from(STREAMER_ID)))
+            .await
+            .unwrap();
+
+        assert_eq!(login.as_str(), "streamer");
+    }
+
+    #[tokio::test]
+    async fn lookup_fails_once_retries_are_exhausted() {
+        let server = MockServer::start().await;
+        // the first attempt and login_lookup_retries retries
+        mock_users(&server, 429, rate_limited(), Some(2)).await;
+        let (mut ws, _receiver, _stop) = client(&server);
+
+        let error = ws
+            .resolve_login(UserId::new(String::from(STREAMER_ID)))
+            .await
+            .unwrap_err();
+
+        assert!(error
+            .to_string()
+            .starts_with("couldn't verify that Twitch channel, the Twitch API failed 2 times"));
+    }
+
+    /// websocket message notifying that STREAMER_ID went live
+    fn stream_online_notification() -> tungstenite::Message {
+        let frame = json!({
+            "metadata": {
+                "message_id": "befa7b53-d79d-478f-86b9-120f112b044e",
+                "message_type": "notification",
+                "message_timestamp": "2026-10-17T10:00:00.123Z",
+                "subscription_type": "stream.online",
+                "subscription_version": "1",
+            },
+            "payload": {
+                "subscription": {
+                    "id": "f1c2a387-161a-49f9-a165-0f21d7a4e1c4",
+                    "status": "enabled",
+                    "type": "stream.online",
+                    "version": "1",
+                    "cost": 0,
+                    "condition": {"broadcaster_user_id": STREAMER_ID},
+                    "transport": {"method": "websocket", "session_id": "session"},
+                    "created_at": "2026-10-17T09:00:00.123Z",
+                },
+                "event": {
+                    "id": "42",
+                    "broadcaster_user_id": STREAMER_ID,
+                    "broadcaster_user_login": "streamer",
+                    "broadcaster_user_name": "Streamer",
+                    "type": "live",
+                    "started_at": "2026-10-17T10:00:00Z",
+                },
+            },
+        });
+        tungstenite::Message::Text(frame.to_string())
+    }
+
+    #[tokio::test]
+    async fn notification_before_welcome_is_ignored() {
+        let server = MockServer::start().await;
+        mock_live_stream(&server).await;
+        let (mut ws, mut receiver, _stop) = client(&server);
+
+        ws.process_message(stream_online_notification())
+            .await
+            .unwrap();
+
+        assert!(receiver.try_recv().is_err());
+        assert!(ws.live_status.is_empty());
+        // once the session is established the same notification is handled
+        ws.session_id = Some(String::from("session"));
+        ws.process_message(stream_online_notification())
+            .await
+            .unwrap();
+        assert!(!online_is_backlog(receiver.recv().await.unwrap()));
+    }
+
+    /// stream.online subscription to broadcaster_user_id
+    fn online_subscription(id: &str, broadcaster_user_id: &str) -> serde_json::Value {
+        json!({
+            "id": id,
+            "status": "enabled",
+            "type": "stream.online",
+            "version": "1",
+            "cost": 0,
+            "condition": {"broadcaster_user_id": broadcaster_user_id},
+            "transport": {"method": "websocket", "session_id": "session"},
+            "created_at": "2026-10-17T09:00:00.123Z",
+        })
+    }
+
+    /// page of the subscription list, followed by the page of cursor if it's given
+    fn subscriptions_page(subs: Vec<serde_json::Value>, cursor: Option<&str>) -> serde_json::Value {
+        json!({
+            "total": subs.len(),
+            "total_cost": 0,
+            "max_total_cost": 10000,
+            "data": subs,
+            "pagination": cursor.map_or(json!({}), |cursor| json!({"cursor": cursor})),
+        })
+    }
+
+    #[tokio::test]
+    async fn every_page_of_subscriptions_is_listed() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/eventsub/subscriptions"))
+            .and(query_param("type", "stream.online"))
+            .and(query_param_is_missing("after"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(subscriptions_page(
+                vec![online_subscription("a", "1"), online_subscription("b", "2")],
+                Some("page2"),
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/eventsub/subscriptions"))
+            .and(query_param("type", "stream.online"))
+            .and(query_param("after", "page2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(subscriptions_page(
+                vec![online_subscription("c", "3")],
+                None,
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+        // the other types have no subscription
+        mock_get(
+            &server,
+            "/eventsub/subscriptions",
+            subscriptions_page(vec![], None),
+        )
+        .await;
+        let (ws, _receiver, _stop) = client(&server);
+
+        let subs = ws.get_subscriptions(&ws.user_token()).await.unwrap();
+
+        let ids: Vec<&str> = subs.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn repeated_cursor_fails_the_listing() {
+        let server = MockServer::start().await;
+        mock_get(
+            &server,
+            "/eventsub/subscriptions",
+            subscriptions_page(vec![online_subscription("a", "1")], Some("same")),
+        )
+        .await;
+        let (ws, _receiver, _stop) = client(&server);
+
+        let error = ws.get_subscriptions(&ws.user_token()).await.unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Twitch sent the cursor same twice when listing the stream.online subscriptions"
+        );
+    }
+
+    #[tokio::test]
+    async fn connection_closed_by_twitch_is_reestablished() {
+        let server = MockServer::start().await;
+        let (mut ws, _receiver, stop) = client(&server);
+        // the discord side keeps its sender for the whole life of the bot
+        let (_requests, requests) = mpsc::channel(1);
+        ws.requests = requests;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        ws.connect_url = format!("ws://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        ws.reconnect_backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(10));
+        let run = tokio::spawn(async move { ws.run().await });
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut first = tokio_tungstenite::accept_async(stream).await.unwrap();
+        first.close(None).await.unwrap();
+        drop(first);
+
+        let (stream, _) = timeout(Duration::from_secs(5), listener.accept())
+            .await
+            .expect("the client didn't reconnect")
+            .unwrap();
+        let _second = tokio_tungstenite::accept_async(stream).await.unwrap();
+        stop.send(true).unwrap();
+        run.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn token_is_refreshed_only_close_to_expiry() {
+        assert!(!needs_refresh(Some(Duration::from_secs(3600))));
+        assert!(needs_refresh(Some(Duration::from_secs(60))));
+        assert!(needs_refresh(None));
+    }
+
+    #[tokio::test]
+    async fn lookup_isnt_retried_while_stopping() {
+        let (_stop, mut stopping) = watch::channel(true);
+        let mut attempts = 0;
+
+        let result: anyhow::Result<()> = retry_lookup(3, &mut stopping, || {
+            attempts += 1;
+            async { Err("rate limited") }
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+}